@@ -0,0 +1,142 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chain::chain::ChainError;
+use chain::snapshot::SnapshotError;
+use serde::Serialize;
+
+/// Error returned by every handler, rendered as
+///
+/// ```json
+/// { "code": "pruned", "message": "Block at height 3 has been pruned", "chain_error": "Pruned" }
+/// ```
+///
+/// `chain_error` names the `ChainError` variant behind the failure, if any, and
+/// `reason` which validation rule a rejected block or transaction broke.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chain_error: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            chain_error: None,
+            reason: None,
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", message)
+    }
+
+    /// The chain mutex was poisoned by a panicking handler
+    pub fn lock_poisoned() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "lock_poisoned",
+            "Failed to lock chain",
+        )
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(self)
+    }
+}
+
+impl From<ChainError> for ApiError {
+    fn from(e: ChainError) -> Self {
+        let message = e.to_string();
+        let reason = match &e {
+            ChainError::InvalidBlock(e) => Some(e.kind()),
+            ChainError::ValidationError(e) => Some(e.kind()),
+            _ => None,
+        };
+        let (status, code, variant) = match e {
+            ChainError::InvalidBlock(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "validation_failed",
+                "InvalidBlock",
+            ),
+            ChainError::ValidationError(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "validation_failed",
+                "ValidationError",
+            ),
+            ChainError::SerializeError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "serialization_failed",
+                "SerializeError",
+            ),
+            ChainError::RetrieveBlockError(_) => {
+                (StatusCode::NOT_FOUND, "not_found", "RetrieveBlockError")
+            }
+            ChainError::StoreError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "storage_failed",
+                "StoreError",
+            ),
+            ChainError::Pruned(_) => (StatusCode::GONE, "pruned", "Pruned"),
+        };
+
+        Self {
+            status,
+            code,
+            message,
+            chain_error: Some(variant),
+            reason,
+        }
+    }
+}
+
+impl From<SnapshotError> for ApiError {
+    fn from(e: SnapshotError) -> Self {
+        match e {
+            SnapshotError::Chain(e) => e.into(),
+            SnapshotError::Io(_) | SnapshotError::Serialize(_) => Self::internal(e.to_string()),
+            SnapshotError::NotValidating => {
+                Self::new(StatusCode::CONFLICT, "not_validating", e.to_string())
+            }
+            SnapshotError::OutOfOrder { .. } => {
+                Self::new(StatusCode::CONFLICT, "out_of_order", e.to_string())
+            }
+            e => Self::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "snapshot_block_rejected",
+                e.to_string(),
+            ),
+        }
+    }
+}
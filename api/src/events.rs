@@ -0,0 +1,69 @@
+//! Server-Sent Events stream of the chain events, served on `GET /events`.
+//!
+//! Each `ChainEvent` is sent as an SSE frame named after its kind, with the
+//! event as JSON data. `?address=` only forwards events involving that address.
+//! A client too slow to keep up receives a `lagged` frame with the number of
+//! events it missed.
+
+use crate::{lock_chain, ApiError};
+use chain::address::Address;
+use chain::chain::Blockchain;
+use chain::events::ChainEvent;
+
+use actix_web::{get, http::header, web, HttpResponse};
+use futures_util::stream;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+#[derive(Deserialize, Debug)]
+struct EventFilter {
+    address: Option<Address>,
+}
+
+fn frame(name: &str, data: &impl serde::Serialize) -> Result<web::Bytes, actix_web::Error> {
+    let data = serde_json::to_string(data).map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(web::Bytes::from(format!(
+        "event: {}\ndata: {}\n\n",
+        name, data
+    )))
+}
+
+/// Waits for the next event passing `address`, `None` once the chain is gone
+async fn next_frame(
+    receiver: &mut Receiver<ChainEvent>,
+    address: Option<&Address>,
+) -> Option<Result<web::Bytes, actix_web::Error>> {
+    loop {
+        match receiver.recv().await {
+            Ok(event) if address.is_none_or(|address| event.touches(address)) => {
+                return Some(frame(event.kind(), &event));
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                return Some(frame("lagged", &serde_json::json!({ "skipped": skipped })));
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[tracing::instrument(skip(data))]
+#[get("/events")]
+async fn events(
+    filter: web::Query<EventFilter>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let receiver = lock_chain(&data)?.subscribe();
+    let address = filter.into_inner().address;
+
+    let frames = stream::unfold((receiver, address), |(mut receiver, address)| async move {
+        let frame = next_frame(&mut receiver, address.as_ref()).await?;
+        Some((frame, (receiver, address)))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .streaming(frames))
+}
@@ -1,34 +1,45 @@
 use actix_cors::Cors;
-use actix_web::{dev::Server, get, post, web, App, HttpResponse, HttpServer, Responder};
-use chain::{self, chain::Blockchain, wallet}; // bad naming
+use actix_web::{
+    dev::Server, get, http::header::ContentType, post, web, App, HttpResponse, HttpServer,
+    Responder,
+};
+use chain::{
+    self,
+    chain::{Blockchain, ChainError},
+    wallet,
+}; // bad naming
 use std::net::TcpListener;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use serde::{Deserialize, Serialize};
 use tracing::{self, debug, info, trace};
 
+use chain::address::Address;
+use chain::block::{Block, BlockHash, BlockHeader};
+use chain::hash::Hash256;
+use chain::hd::HdWallet;
+use chain::keystore::{Keystore, KeystoreError};
+use chain::miner::Miner;
+use chain::multisig::Multisig;
 use chain::transaction::Transaction;
 
-#[derive(Deserialize, Serialize)]
-struct TransactionRequest {
-    sender_address: String,
-    recipient_address: String,
-    value: f32,
-}
+mod error;
+mod events;
+pub mod rpc;
+
+pub use error::ApiError;
 
-#[derive(Deserialize, Serialize)]
-struct NewBlockRequest {
-    timestamp: i64,
-    nonce: i64,
-    previous_hash: String,
-    // Don't know if this is enough but I can create a transaction from this so it should be
-    transactions: Vec<TransactionRequest>,
+/// Locks the chain, turning a poisoned mutex into an error response
+fn lock_chain(data: &Arc<Mutex<Blockchain>>) -> Result<MutexGuard<'_, Blockchain>, ApiError> {
+    data.lock().map_err(|_| ApiError::lock_poisoned())
 }
 
 #[tracing::instrument]
 #[get("/transactions/pending")]
-async fn get_transactions_mempool(data: web::Data<Arc<Mutex<Blockchain>>>) -> impl Responder {
-    let chain = data.lock().unwrap();
+async fn get_transactions_mempool(
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let chain = lock_chain(&data)?;
     let mempool = chain.mempool();
 
     let mempool_deref = mempool
@@ -36,99 +47,284 @@ async fn get_transactions_mempool(data: web::Data<Arc<Mutex<Blockchain>>>) -> im
         .map(|tx| &**tx)
         .collect::<Vec<&Transaction>>();
 
-    let res_body = serde_json::to_string(&mempool_deref);
+    let res = serde_json::to_string(&mempool_deref).map_err(|_| {
+        debug!("Failed to serialize transactions {:?}", mempool_deref);
+        ApiError::internal("Failed to serialize transactions")
+    })?;
 
-    match res_body {
-        Ok(res) => {
-            trace!("Transactions in the mempool fetched {}", res);
-            HttpResponse::Ok().body(res)
-        }
-        Err(_) => {
-            debug!("Failed to serialize transactions {:?}", mempool_deref);
-            HttpResponse::ExpectationFailed().body("Failed to serialize transactions")
-        }
-    }
+    trace!("Transactions in the mempool fetched {}", res);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(res))
 }
 
 #[tracing::instrument]
 #[post("/block/new")]
-async fn new_block(req_body: String, data: web::Data<Arc<Mutex<Blockchain>>>) -> impl Responder {
-    let new_block = serde_json::from_str::<NewBlockRequest>(&req_body);
+async fn new_block(
+    req_body: String,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let block = serde_json::from_str::<Block>(&req_body)
+        .map_err(|e| ApiError::bad_request(format!("Failed to deserialize block: {}", e)))?;
 
-    if new_block.is_err() {
-        return HttpResponse::BadRequest().body("Failed to deserialize block");
-    }
+    // Signatures are verified on the blocking pool, without holding the lock
+    let context = lock_chain(&data)?.block_context()?;
+    let block = web::block(move || context.check(block))
+        .await
+        .map_err(|_| ApiError::internal("Failed to check block"))?;
 
-    match data.lock() {
-        Ok(mut chain) => {
-            let new_block = new_block.unwrap(); // safe, as we would've returned above
-            let transactions: Vec<Arc<Transaction>> = new_block
-                .transactions
-                .iter()
-                .map(|tx| {
-                    Arc::new(Transaction::new(
-                        tx.sender_address.clone(),
-                        tx.recipient_address.clone(),
-                        tx.value,
-                    ))
-                })
-                .collect();
-
-            let block = chain::block::Block::new(
-                new_block.timestamp,
-                transactions,
-                new_block.nonce,
-                new_block.previous_hash,
-            );
-            match chain.verify_and_add_block(block) {
-                Ok(_) => {
-                    info!("New block added");
-                    return HttpResponse::Ok().body("New block added");
-                }
-                Err(err) => {
-                    debug!("Failed to add block: {:?}", err);
-                    return HttpResponse::ExpectationFailed().body("Failed to lock data");
-                }
-            }
-        }
-        Err(_) => return HttpResponse::ExpectationFailed().body("Failed to lock data"),
-    }
+    let mut chain = lock_chain(&data)?;
+    block
+        .and_then(|block| chain.add_checked_block(block))
+        .map_err(|err| {
+            debug!("Failed to add block: {:?}", err);
+            ApiError::from(err)
+        })?;
+
+    info!("New block added");
+    Ok(HttpResponse::Ok().body("New block added"))
 }
+
 #[get("/")]
 async fn hello() -> impl Responder {
     HttpResponse::Ok().body("Hello world!")
 }
 
 #[get("/mine")]
-async fn mine(data: web::Data<Arc<Mutex<Blockchain>>>) -> impl Responder {
-    let mut chain = match data.lock() {
-        Ok(chain) => chain,
-        Err(_) => return HttpResponse::ExpectationFailed().body("Failed to lock data"),
-    };
-
-    match chain.mine() {
-        Ok(block) => {
-            info!("Block mined");
-            debug!("{}", format!("Block: {:?}", block));
-            return HttpResponse::Ok().body(format!("Block mined: {}", block));
-        }
-        Err(_) => HttpResponse::ExpectationFailed().body("Failed to mine block"),
+async fn mining_status(miner: web::Data<Miner>) -> impl Responder {
+    HttpResponse::Ok().json(miner.status())
+}
+
+#[post("/mine/start")]
+async fn start_mining(miner: web::Data<Miner>) -> impl Responder {
+    if miner.start() {
+        info!("Mining started");
     }
+    HttpResponse::Ok().json(miner.status())
 }
 
-#[tracing::instrument]
-#[get("/wallet/new")]
-async fn new_wallet() -> impl Responder {
-    let wallet = wallet::Wallet::generate_new();
-    let res_body = serde_json::to_string(&wallet);
-    match res_body {
-        Ok(body) => {
-            info!("Wallet created");
-            debug!("{}", format!("New wallet: {}", body));
-            HttpResponse::Ok().body(body)
+#[post("/mine/stop")]
+async fn stop_mining(miner: web::Data<Miner>) -> Result<HttpResponse, ApiError> {
+    let miner = miner.into_inner();
+    // Joining the worker blocks until its next cancellation check
+    let status = web::block(move || {
+        if miner.stop() {
+            info!("Mining stopped");
         }
-        Err(_) => HttpResponse::InternalServerError().body("Failed to serialize wallet"),
+        miner.status()
+    })
+    .await
+    .map_err(|_| ApiError::internal("Failed to stop the miner"))?;
+
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// Length of the mnemonic phrase of new wallets
+const MNEMONIC_WORDS: usize = 12;
+
+#[derive(Deserialize)]
+struct NewWalletRequest {
+    passphrase: String,
+}
+
+#[derive(Deserialize)]
+struct RestoreWalletRequest {
+    mnemonic: String,
+    passphrase: String,
+}
+
+#[derive(Deserialize)]
+struct ImportWalletRequest {
+    wif: String,
+    passphrase: String,
+}
+
+#[derive(Deserialize)]
+struct ExportWalletRequest {
+    keystore: Keystore,
+    passphrase: String,
+}
+
+fn parse_wallet_request<T: serde::de::DeserializeOwned>(req_body: &str) -> Result<T, ApiError> {
+    serde_json::from_str(req_body)
+        .map_err(|e| ApiError::bad_request(format!("Failed to deserialize request: {}", e)))
+}
+
+/// `wallet` and its keystore sealed under `passphrase`. The private key itself
+/// is never sent
+async fn seal_wallet(
+    wallet: wallet::Wallet,
+    passphrase: String,
+) -> Result<serde_json::Value, ApiError> {
+    if passphrase.is_empty() {
+        return Err(ApiError::bad_request("The passphrase must not be empty"));
     }
+
+    // Key derivation is deliberately slow, keep it off the workers
+    let keystore = web::block(move || Keystore::create(wallet, &passphrase))
+        .await
+        .map_err(|_| ApiError::internal("Failed to seal wallet"))?
+        .map_err(|e| ApiError::internal(format!("Failed to seal wallet: {}", e)))?;
+
+    Ok(serde_json::json!({
+        "wallet": keystore.wallet(),
+        "keystore": keystore,
+    }))
+}
+
+/// Wallet of the first receiving address of `hd_wallet`
+fn first_receiving_wallet(hd_wallet: &HdWallet) -> Result<wallet::Wallet, ApiError> {
+    hd_wallet
+        .receiving_wallet(0, 0)
+        .map_err(|e| ApiError::internal(format!("Failed to derive wallet: {}", e)))
+}
+
+/// Creates a wallet from a new mnemonic phrase, sent back once for the user to
+/// write down
+#[tracing::instrument(skip(req_body))]
+#[post("/wallet/new")]
+async fn new_wallet(req_body: String) -> Result<HttpResponse, ApiError> {
+    let request: NewWalletRequest = parse_wallet_request(&req_body)?;
+
+    // Stretching the phrase into a seed is slow too
+    let hd_wallet = web::block(|| HdWallet::generate(MNEMONIC_WORDS, ""))
+        .await
+        .map_err(|_| ApiError::internal("Failed to generate wallet"))?
+        .map_err(|e| ApiError::internal(format!("Failed to generate wallet: {}", e)))?;
+    let mut sealed = seal_wallet(first_receiving_wallet(&hd_wallet)?, request.passphrase).await?;
+    sealed["mnemonic"] = serde_json::json!(hd_wallet.phrase());
+
+    info!("Wallet created");
+    Ok(HttpResponse::Ok().json(sealed))
+}
+
+/// Seals the wallet of a mnemonic phrase given by `/wallet/new`
+#[tracing::instrument(skip(req_body))]
+#[post("/wallet/restore")]
+async fn restore_wallet(req_body: String) -> Result<HttpResponse, ApiError> {
+    let request: RestoreWalletRequest = parse_wallet_request(&req_body)?;
+    let hd_wallet = web::block(move || HdWallet::from_phrase(&request.mnemonic, ""))
+        .await
+        .map_err(|_| ApiError::internal("Failed to restore wallet"))?
+        .map_err(|e| ApiError::bad_request(format!("Invalid mnemonic phrase: {}", e)))?;
+    let wallet = first_receiving_wallet(&hd_wallet)?;
+
+    info!("Wallet {} restored", wallet.address());
+    Ok(HttpResponse::Ok().json(seal_wallet(wallet, request.passphrase).await?))
+}
+
+/// Seals a key exported by another tool in Wallet Import Format
+#[tracing::instrument(skip(req_body))]
+#[post("/wallet/import")]
+async fn import_wallet(req_body: String) -> Result<HttpResponse, ApiError> {
+    let request: ImportWalletRequest = parse_wallet_request(&req_body)?;
+    let wallet = wallet::Wallet::from_wif(&request.wif)
+        .map_err(|e| ApiError::bad_request(format!("Invalid WIF key: {}", e)))?;
+
+    info!("Wallet {} imported", wallet.address());
+    Ok(HttpResponse::Ok().json(seal_wallet(wallet, request.passphrase).await?))
+}
+
+/// Unlocks `keystore` off the workers and applies `with_wallet` to its wallet.
+/// A wrong passphrase is refused with 403
+async fn with_unlocked_wallet<T, F>(
+    mut keystore: Keystore,
+    passphrase: String,
+    with_wallet: F,
+) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&wallet::Wallet) -> T + Send + 'static,
+{
+    web::block(move || keystore.unlock(&passphrase).map(with_wallet))
+        .await
+        .map_err(|_| ApiError::internal("Failed to unlock keystore"))?
+        .map_err(|e| match e {
+            KeystoreError::WrongPassphrase => ApiError::new(
+                actix_web::http::StatusCode::FORBIDDEN,
+                "wrong_passphrase",
+                e.to_string(),
+            ),
+            _ => ApiError::bad_request(e.to_string()),
+        })
+}
+
+/// Unseals a keystore, answering with its key in Wallet Import Format
+#[tracing::instrument(skip(req_body))]
+#[post("/wallet/export")]
+async fn export_wallet(req_body: String) -> Result<HttpResponse, ApiError> {
+    let ExportWalletRequest {
+        keystore,
+        passphrase,
+    } = parse_wallet_request(&req_body)?;
+
+    let wif = with_unlocked_wallet(keystore, passphrase, |wallet| wallet.to_wif()).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "wif": wif })))
+}
+
+#[derive(Deserialize)]
+struct SignMessageRequest {
+    keystore: Keystore,
+    passphrase: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct VerifyMessageRequest {
+    address: String,
+    message: String,
+    signature: String,
+}
+
+/// Signs a message with the key of a keystore, proving ownership of its address
+#[tracing::instrument(skip(req_body))]
+#[post("/message/sign")]
+async fn sign_message(req_body: String) -> Result<HttpResponse, ApiError> {
+    let SignMessageRequest {
+        keystore,
+        passphrase,
+        message,
+    } = parse_wallet_request(&req_body)?;
+    let address = keystore.address();
+
+    let signature = with_unlocked_wallet(keystore, passphrase, move |wallet| {
+        wallet.sign_message(&message)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "address": address,
+        "signature": signature,
+    })))
+}
+
+#[tracing::instrument(skip(req_body))]
+#[post("/message/verify")]
+async fn verify_message(req_body: String) -> Result<HttpResponse, ApiError> {
+    let request: VerifyMessageRequest = parse_wallet_request(&req_body)?;
+    let address = wallet::Wallet::validate_address(&request.address)
+        .map_err(|e| ApiError::bad_request(format!("Invalid address: {}", e)))?;
+    let valid = wallet::Wallet::verify_message(&address, &request.message, &request.signature)
+        .map_err(|e| ApiError::bad_request(format!("Invalid signature: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "address": address,
+        "valid": valid,
+    })))
+}
+
+/// Address of an m-of-n account, for its keys to be listed in any order
+#[tracing::instrument(skip(req_body))]
+#[post("/multisig/address")]
+async fn multisig_address(req_body: String) -> Result<HttpResponse, ApiError> {
+    let multisig: Multisig = serde_json::from_str(&req_body)
+        .map_err(|e| ApiError::bad_request(format!("Invalid multisig policy: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "address": multisig.address(),
+        "multisig": multisig,
+    })))
 }
 
 #[tracing::instrument]
@@ -136,48 +332,325 @@ async fn new_wallet() -> impl Responder {
 async fn new_transaction(
     req_body: String,
     data: web::Data<Arc<Mutex<Blockchain>>>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     info!("New transaction");
 
-    let transaction: Result<TransactionRequest, _> = serde_json::from_str(&req_body);
-    match transaction {
-        Ok(tx) => {
-            let mut chain = data.lock().expect("Failed to mutex lock chain");
+    let tx: Transaction = serde_json::from_str(&req_body)
+        .map_err(|e| ApiError::bad_request(format!("Failed to deserialize transaction: {}", e)))?;
 
-            chain.add_transaction(&tx.sender_address, &tx.recipient_address, tx.value);
-            HttpResponse::Ok().body("Transaction added successfully")
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().body("Failed to serialize transaction")
-        }
-    }
+    lock_chain(&data)?.submit_transaction(tx)?;
+    Ok(HttpResponse::Ok().body("Transaction added successfully"))
+}
+
+#[derive(Deserialize, Debug)]
+struct ChainRange {
+    from: Option<u64>,
+    to: Option<u64>,
 }
 
 #[tracing::instrument]
 #[get("/chain")]
-async fn get_chain(data: web::Data<Arc<Mutex<Blockchain>>>) -> impl Responder {
-    let chain = data.lock().unwrap().chain();
-
-    let res_body = serde_json::to_string(&chain);
-    match res_body {
-        Ok(body) => {
-            info!("Query for chain succeeded");
-            debug!("{}", format!("Chain: {}", body));
-            HttpResponse::Ok().body(body)
-        }
-        Err(_) => HttpResponse::InternalServerError().body("Failed to serialize wallet"),
-    }
+async fn get_chain(
+    range: web::Query<ChainRange>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let chain = lock_chain(&data)?;
+    let height = chain.height()?;
+    let blocks = chain.blocks_in_range(range.from.unwrap_or(0), range.to.unwrap_or(height))?;
+    drop(chain);
+
+    let body = serde_json::to_string(&blocks)
+        .map_err(|_| ApiError::internal("Failed to serialize chain"))?;
+
+    info!("Query for chain succeeded");
+    debug!("{}", format!("Chain: {}", body));
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(body))
 }
 
-pub fn run(listener: TcpListener) -> Result<Server, std::io::Error> {
-    tracing_subscriber::fmt::Subscriber::builder()
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+#[tracing::instrument]
+#[get("/transaction/{id}")]
+async fn get_transaction(
+    id: web::Path<Hash256>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let (transaction, height) = lock_chain(&data)?.find_transaction(&id)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "height": height,
+        "transaction": &*transaction,
+    })))
+}
 
+#[derive(Serialize)]
+struct BlockResponse<T: Serialize> {
+    height: u64,
+    hash: BlockHash,
+    #[serde(flatten)]
+    content: T,
+}
+
+#[derive(Serialize)]
+struct FullBlock {
+    block: Block,
+}
+
+#[derive(Serialize)]
+struct HeaderOnly {
+    header: BlockHeader,
+}
+
+fn block_at(chain: &Blockchain, height: u64) -> Result<BlockResponse<FullBlock>, ChainError> {
+    Ok(BlockResponse {
+        height,
+        hash: chain.hash_at_height(height)?,
+        content: FullBlock {
+            block: chain.block_at_height(height)?,
+        },
+    })
+}
+
+fn header_at(chain: &Blockchain, height: u64) -> Result<BlockResponse<HeaderOnly>, ChainError> {
+    let hash = chain.hash_at_height(height)?;
+    Ok(BlockResponse {
+        height,
+        content: HeaderOnly {
+            header: chain.header_by_hash(&hash)?,
+        },
+        hash,
+    })
+}
+
+fn height_of(chain: &Blockchain, hash: &BlockHash) -> Result<u64, ChainError> {
+    chain
+        .height_of(hash)
+        .ok_or_else(|| ChainError::RetrieveBlockError(format!("Block {} not found", hash)))
+}
+
+#[tracing::instrument]
+#[get("/tip")]
+async fn get_tip(data: web::Data<Arc<Mutex<Blockchain>>>) -> Result<HttpResponse, ApiError> {
+    let chain = lock_chain(&data)?;
+    let tip = header_at(&chain, chain.height()?)?;
+    Ok(HttpResponse::Ok().json(tip))
+}
+
+#[tracing::instrument]
+#[get("/block/height/{height}")]
+async fn get_block_by_height(
+    height: web::Path<u64>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let block = block_at(&*lock_chain(&data)?, *height)?;
+    Ok(HttpResponse::Ok().json(block))
+}
+
+#[tracing::instrument]
+#[get("/block/height/{height}/header")]
+async fn get_header_by_height(
+    height: web::Path<u64>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let header = header_at(&*lock_chain(&data)?, *height)?;
+    Ok(HttpResponse::Ok().json(header))
+}
+
+#[tracing::instrument]
+#[get("/block/{hash}")]
+async fn get_block_by_hash(
+    hash: web::Path<BlockHash>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let chain = lock_chain(&data)?;
+    let block = block_at(&chain, height_of(&chain, &hash)?)?;
+    Ok(HttpResponse::Ok().json(block))
+}
+
+#[tracing::instrument]
+#[get("/block/{hash}/header")]
+async fn get_header_by_hash(
+    hash: web::Path<BlockHash>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let chain = lock_chain(&data)?;
+    let header = header_at(&chain, height_of(&chain, &hash)?)?;
+    Ok(HttpResponse::Ok().json(header))
+}
+
+#[derive(Deserialize, Debug)]
+struct BlockPage {
+    from: Option<u64>,
+    limit: Option<u64>,
+}
+
+/// Heights covered by a page, empty once past the tip
+fn page_heights(chain: &Blockchain, page: &BlockPage) -> Result<Vec<u64>, ChainError> {
+    let from = page.from.unwrap_or(0);
+    let limit = page
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE as u64)
+        .min(MAX_PAGE_SIZE as u64);
+    let to = (chain.height()? + 1).min(from.saturating_add(limit));
+    Ok((from..to).collect())
+}
+
+#[tracing::instrument]
+#[get("/blocks")]
+async fn get_blocks(
+    page: web::Query<BlockPage>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let chain = lock_chain(&data)?;
+    let blocks = page_heights(&chain, &page)?
+        .into_iter()
+        .map(|height| block_at(&chain, height))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(HttpResponse::Ok().json(blocks))
+}
+
+#[tracing::instrument]
+#[get("/headers")]
+async fn get_headers(
+    page: web::Query<BlockPage>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let chain = lock_chain(&data)?;
+    let headers = page_heights(&chain, &page)?
+        .into_iter()
+        .map(|height| header_at(&chain, height))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(HttpResponse::Ok().json(headers))
+}
+
+#[derive(Deserialize, Debug)]
+struct Pagination {
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Answers whether `address` is well formed, with the reason when it is not
+#[tracing::instrument]
+#[get("/address/{address}/validate")]
+async fn validate_address(address: web::Path<String>) -> impl Responder {
+    HttpResponse::Ok().json(match wallet::Wallet::validate_address(&address) {
+        Ok(decoded) => serde_json::json!({
+            "address": decoded,
+            "is_valid": true,
+            "version": decoded.version(),
+            "encoding": decoded.encoding(),
+        }),
+        Err(err) => serde_json::json!({
+            "address": address.as_str(),
+            "is_valid": false,
+            "reason": err.to_string(),
+        }),
+    })
+}
+
+#[tracing::instrument]
+#[get("/address/{address}/balance")]
+async fn get_address_balance(
+    address: web::Path<Address>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let chain = lock_chain(&data)?;
+    let balance = chain.get_balance(&address);
+    let next_sequence = chain.next_sequence(&address)?;
+    drop(chain);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "address": *address,
+        "balance": balance,
+        "next_sequence": next_sequence,
+    })))
+}
+
+#[tracing::instrument]
+#[get("/address/{address}/transactions")]
+async fn get_address_transactions(
+    address: web::Path<Address>,
+    page: web::Query<Pagination>,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let offset = page.offset.unwrap_or(0);
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+
+    let chain = lock_chain(&data)?;
+    let transactions = chain.get_history(&address, offset, limit);
+    let total = chain.get_history_len(&address);
+    drop(chain);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "address": *address,
+        "total": total,
+        "offset": offset,
+        "limit": limit,
+        "transactions": transactions,
+    })))
+}
+
+#[tracing::instrument]
+#[get("/snapshot/status")]
+async fn get_snapshot_status(
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    Ok(match lock_chain(&data)?.snapshot_status() {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => HttpResponse::Ok().json(serde_json::json!({ "status": "not_from_snapshot" })),
+    })
+}
+
+/// Checks a historical block against the snapshot the node started from
+#[tracing::instrument]
+#[post("/snapshot/block")]
+async fn add_historical_block(
+    req_body: String,
+    data: web::Data<Arc<Mutex<Blockchain>>>,
+) -> Result<HttpResponse, ApiError> {
+    let block = serde_json::from_str::<Block>(&req_body)
+        .map_err(|e| ApiError::bad_request(format!("Failed to deserialize block: {}", e)))?;
+
+    // Signatures are verified on the blocking pool, without holding the lock
+    let context = lock_chain(&data)?.historical_block_context()?;
+    let block = web::block(move || context.check(block))
+        .await
+        .map_err(|_| ApiError::internal("Failed to check block"))??;
+
+    let status = lock_chain(&data)?.add_checked_historical_block(block)?;
+
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// Serves a fresh in-memory chain
+pub fn run(listener: TcpListener) -> Result<Server, std::io::Error> {
     let root_wallet = wallet::Wallet::generate_new();
     let address = root_wallet.address();
 
-    let shared_blockchain = Arc::new(Mutex::new(Blockchain::new(address, 4)));
+    run_with_chain(listener, Blockchain::new(address, chain::MINING_DIFFICULTY))
+}
+
+pub fn run_with_chain(
+    listener: TcpListener,
+    blockchain: Blockchain,
+) -> Result<Server, std::io::Error> {
+    run_with_shared_chain(listener, Arc::new(Mutex::new(blockchain)))
+}
+
+/// Serves a chain that other tasks of the node also hold on to
+pub fn run_with_shared_chain(
+    listener: TcpListener,
+    shared_blockchain: Arc<Mutex<Blockchain>>,
+) -> Result<Server, std::io::Error> {
+    // Several servers may share a process in tests, only the first one installs the subscriber
+    let _ = tracing_subscriber::fmt::Subscriber::builder()
+        .with_max_level(tracing::Level::DEBUG)
+        .try_init();
+
+    let miner = web::Data::new(Miner::new(shared_blockchain.clone()));
 
     let server = HttpServer::new(move || {
         let cors = Cors::default()
@@ -195,11 +668,45 @@ pub fn run(listener: TcpListener) -> Result<Server, std::io::Error> {
         App::new()
             .wrap(cors)
             .app_data(web::Data::new(shared_blockchain.clone()))
+            .app_data(miner.clone())
+            .app_data(
+                web::QueryConfig::default()
+                    .error_handler(|err, _| ApiError::bad_request(err.to_string()).into()),
+            )
+            .app_data(
+                web::PathConfig::default()
+                    .error_handler(|err, _| ApiError::bad_request(err.to_string()).into()),
+            )
             .service(hello)
             .service(new_wallet)
+            .service(restore_wallet)
+            .service(import_wallet)
+            .service(export_wallet)
+            .service(sign_message)
+            .service(verify_message)
+            .service(multisig_address)
             .service(get_chain)
-            .service(mine)
+            .service(get_transaction)
+            .service(get_snapshot_status)
+            .service(add_historical_block)
+            .service(validate_address)
+            .service(get_address_balance)
+            .service(get_address_transactions)
+            .service(get_tip)
+            .service(new_block)
+            .service(get_block_by_height)
+            .service(get_header_by_height)
+            .service(get_block_by_hash)
+            .service(get_header_by_hash)
+            .service(get_blocks)
+            .service(get_headers)
+            .service(mining_status)
+            .service(start_mining)
+            .service(stop_mining)
             .service(new_transaction)
+            .service(get_transactions_mempool)
+            .service(rpc::rpc)
+            .service(events::events)
     })
     .listen(listener)?
     .run();
@@ -0,0 +1,282 @@
+//! JSON-RPC 2.0 interface, served on `POST /rpc`.
+//!
+//! Methods take their parameters by position or by name, and map onto
+//! `Blockchain`. Batches are answered with an array of the responses, leaving out
+//! notifications. Chain failures carry the body the REST endpoints would return
+//! in the error's `data`, see `ApiError`.
+
+use crate::{block_at, height_of, lock_chain, ApiError};
+use chain::address::Address;
+use chain::block::Block;
+use chain::chain::{Blockchain, ChainError};
+use chain::hash::ParseHashError;
+use chain::transaction::Transaction;
+
+use actix_web::{http::header::ContentType, post, web, HttpResponse};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// Block or transaction rejected by consensus rules
+pub const VERIFY_REJECTED: i64 = -26;
+/// No block or transaction with the given identifier
+pub const NOT_FOUND: i64 = -5;
+/// The requested block has been pruned
+pub const PRUNED: i64 = -1;
+
+#[derive(Debug, Serialize)]
+pub struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<ChainError> for RpcError {
+    fn from(e: ChainError) -> Self {
+        let code = match e {
+            ChainError::InvalidBlock(_) | ChainError::ValidationError(_) => VERIFY_REJECTED,
+            ChainError::RetrieveBlockError(_) => NOT_FOUND,
+            ChainError::Pruned(_) => PRUNED,
+            ChainError::SerializeError(_) | ChainError::StoreError(_) => INTERNAL_ERROR,
+        };
+        let message = e.to_string();
+        Self {
+            code,
+            message,
+            data: serde_json::to_value(ApiError::from(e)).ok(),
+        }
+    }
+}
+
+impl From<ApiError> for RpcError {
+    fn from(e: ApiError) -> Self {
+        Self::new(INTERNAL_ERROR, e.to_string())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    #[serde(flatten)]
+    outcome: Outcome,
+    id: Value,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Result(Value),
+    Error(RpcError),
+}
+
+impl RpcResponse {
+    fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            outcome: match outcome {
+                Ok(result) => Outcome::Result(result),
+                Err(error) => Outcome::Error(error),
+            },
+            id,
+        }
+    }
+}
+
+/// Parameters of a call, given either by position or by name
+struct Params(Option<Value>);
+
+impl Params {
+    fn get(&self, index: usize, name: &str) -> Option<&Value> {
+        match &self.0 {
+            Some(Value::Array(values)) => values.get(index),
+            Some(Value::Object(values)) => values.get(name),
+            _ => None,
+        }
+    }
+
+    fn required(&self, index: usize, name: &str) -> Result<&Value, RpcError> {
+        self.get(index, name)
+            .ok_or_else(|| RpcError::invalid_params(format!("Missing parameter {}", name)))
+    }
+
+    fn parse<T: serde::de::DeserializeOwned>(
+        &self,
+        index: usize,
+        name: &str,
+    ) -> Result<T, RpcError> {
+        serde_json::from_value(self.required(index, name)?.clone())
+            .map_err(|e| RpcError::invalid_params(format!("Invalid parameter {}: {}", name, e)))
+    }
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+}
+
+fn dispatch(
+    chain: &Arc<Mutex<Blockchain>>,
+    method: &str,
+    params: Params,
+) -> Result<Value, RpcError> {
+    match method {
+        "getblockcount" => to_value(lock_chain(chain)?.height()?),
+        "getblock" => {
+            let chain = lock_chain(chain)?;
+            let height = match params.required(0, "block")? {
+                Value::Number(height) => height
+                    .as_u64()
+                    .ok_or_else(|| RpcError::invalid_params("Invalid block height"))?,
+                Value::String(hash) => {
+                    let hash = hash.parse().map_err(|e: ParseHashError| {
+                        RpcError::invalid_params(format!("Invalid parameter block: {}", e))
+                    })?;
+                    height_of(&chain, &hash)?
+                }
+                _ => return Err(RpcError::invalid_params("Expected a block hash or height")),
+            };
+            to_value(block_at(&chain, height)?)
+        }
+        "getbalance" => {
+            let address: Address = params.parse(0, "address")?;
+            to_value(lock_chain(chain)?.get_balance(&address))
+        }
+        "sendrawtransaction" => {
+            let transaction: Transaction = params.parse(0, "transaction")?;
+            to_value(lock_chain(chain)?.submit_transaction(transaction)?)
+        }
+        "getmempoolinfo" => {
+            let chain = lock_chain(chain)?;
+            let mempool = chain.mempool();
+            let bytes: usize = mempool
+                .iter()
+                .map(|tx| serde_json::to_string(&**tx).map(|json| json.len()))
+                .sum::<Result<_, _>>()
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))?;
+            Ok(json!({ "size": mempool.len(), "bytes": bytes }))
+        }
+        "getmininginfo" => {
+            let chain = lock_chain(chain)?;
+            Ok(json!({
+                "blocks": chain.height()?,
+                "difficulty": chain.difficulty(),
+                "pooledtx": chain.mempool().len(),
+                "address": chain.address(),
+            }))
+        }
+        "submitblock" => {
+            let block: Block = params.parse(0, "block")?;
+            // Checked without holding the lock, signatures are the slow part
+            let context = lock_chain(chain)?.block_context()?;
+            let block = context.check(block)?;
+            let block = lock_chain(chain)?.add_checked_block(block)?;
+            info!("Block submitted over RPC");
+            to_value(block.hash())
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Method {} not found", method),
+        )),
+    }
+}
+
+/// Answers a single request, or `None` for a notification
+fn handle(chain: &Arc<Mutex<Blockchain>>, request: Value) -> Option<RpcResponse> {
+    let Value::Object(mut request) = request else {
+        return Some(RpcResponse::new(
+            Value::Null,
+            Err(RpcError::new(INVALID_REQUEST, "Request must be an object")),
+        ));
+    };
+
+    let id = request.remove("id");
+    let valid_id = matches!(
+        id,
+        None | Some(Value::Null | Value::String(_) | Value::Number(_))
+    );
+    let params = request.remove("params");
+    let valid_params = matches!(params, None | Some(Value::Array(_) | Value::Object(_)));
+
+    let outcome = match (request.get("jsonrpc"), request.get("method")) {
+        (Some(Value::String(version)), Some(Value::String(method)))
+            if version == "2.0" && valid_id && valid_params =>
+        {
+            debug!("RPC call {}", method);
+            let outcome = dispatch(chain, method, Params(params));
+            // Notifications are carried out but never answered
+            id.as_ref()?;
+            outcome
+        }
+        _ => Err(RpcError::new(
+            INVALID_REQUEST,
+            "Invalid JSON-RPC 2.0 request",
+        )),
+    };
+
+    let id = if valid_id {
+        id.unwrap_or(Value::Null)
+    } else {
+        Value::Null
+    };
+    Some(RpcResponse::new(id, outcome))
+}
+
+/// Answers a request or a batch, `None` when there is nothing to answer
+fn respond(chain: &Arc<Mutex<Blockchain>>, req_body: &str) -> Option<Result<Value, RpcError>> {
+    match serde_json::from_str::<Value>(req_body) {
+        Err(e) => Some(to_value(RpcResponse::new(
+            Value::Null,
+            Err(RpcError::new(PARSE_ERROR, format!("Parse error: {}", e))),
+        ))),
+        Ok(Value::Array(requests)) if requests.is_empty() => Some(to_value(RpcResponse::new(
+            Value::Null,
+            Err(RpcError::new(INVALID_REQUEST, "Empty batch")),
+        ))),
+        Ok(Value::Array(requests)) => {
+            let responses: Vec<_> = requests
+                .into_iter()
+                .filter_map(|request| handle(chain, request))
+                .collect();
+            if responses.is_empty() {
+                return None;
+            }
+            Some(to_value(responses))
+        }
+        Ok(request) => handle(chain, request).map(to_value),
+    }
+}
+
+#[tracing::instrument(skip(data))]
+#[post("/rpc")]
+async fn rpc(req_body: String, data: web::Data<Arc<Mutex<Blockchain>>>) -> HttpResponse {
+    let chain = Arc::clone(&data);
+    // Methods lock the chain and submitblock checks whole blocks, keep them
+    // off the async workers
+    match web::block(move || respond(&chain, &req_body)).await {
+        Ok(Some(Ok(body))) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(body.to_string()),
+        Ok(None) => HttpResponse::NoContent().finish(),
+        Ok(Some(Err(_))) | Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -1,12 +1,448 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use api;
-use chain;
+use chain::address::Address;
+use chain::chain::Blockchain;
+use chain::export::ChainReader;
+use chain::hash::Hash256;
+use chain::hd::HdWallet;
+use chain::keystore::Keystore;
+use chain::snapshot::{ChainSnapshot, SnapshotStatus};
+use chain::store::DiskStore;
+use chain::transaction::NETWORK_ADDRESS;
+use chain::wallet::Wallet;
+
+const DEFAULT_DATADIR: &str = ".blocksmith";
+/// Keystore file in the datadir, mined rewards go to its address
+const KEYSTORE_FILE: &str = "keystore.json";
+/// Length of the mnemonic phrase of new wallets
+const MNEMONIC_WORDS: usize = 12;
+
+const USAGE: &str = "usage:
+    blocksmithd [--datadir DIR]                       run the node
+    blocksmithd export --out FILE [--datadir DIR]     write the chain to FILE
+    blocksmithd import --in FILE [--datadir DIR]      validate and add the blocks in FILE
+    blocksmithd snapshot --out FILE [--datadir DIR]   write a snapshot of the chain state to FILE
+    blocksmithd wallet create [--keystore FILE]       seal a new wallet under a passphrase and print its mnemonic
+    blocksmithd wallet restore [--keystore FILE]      seal the wallet of a mnemonic phrase
+    blocksmithd wallet address [--keystore FILE]      print the address of the keystore
+    blocksmithd wallet passwd [--keystore FILE]       change the passphrase of the keystore
+    blocksmithd wallet import [--keystore FILE]       seal a key read in Wallet Import Format
+    blocksmithd wallet export [--keystore FILE]       print the key of the keystore in Wallet Import Format
+
+options:
+    --prune DEPTH        only keep the bodies of the DEPTH most recent blocks
+    --snapshot FILE      start a node with an empty datadir from the snapshot in FILE
+    --snapshot-commitment HASH
+                         commitment the snapshot must have, as printed by the node that wrote it
+    --history FILE       chain file whose blocks are checked against the snapshot in the background
+    --mining-threads N   threads searching for nonces, one per core by default
+    --keystore FILE      wallet keystore, DIR/keystore.json by default
+    --reward-address ADDRESS
+                         address mined rewards go to, the address of the keystore by default
+
+passphrases and keys are read from stdin, one per line";
+
+enum Command {
+    Run {
+        /// Snapshot file and the commitment it is trusted with
+        snapshot: Option<(PathBuf, Hash256)>,
+        history: Option<PathBuf>,
+    },
+    Export {
+        out: PathBuf,
+    },
+    Import {
+        input: PathBuf,
+    },
+    Snapshot {
+        out: PathBuf,
+    },
+    Wallet {
+        action: WalletAction,
+    },
+}
+
+enum WalletAction {
+    Create,
+    Restore,
+    Address,
+    Passwd,
+    Import,
+    Export,
+}
+
+struct Args {
+    command: Command,
+    datadir: PathBuf,
+    keystore: Option<PathBuf>,
+    reward_address: Option<Address>,
+    prune: Option<u64>,
+    mining_threads: Option<usize>,
+}
+
+impl Args {
+    fn keystore_path(&self) -> PathBuf {
+        self.keystore
+            .clone()
+            .unwrap_or_else(|| self.datadir.join(KEYSTORE_FILE))
+    }
+
+    /// Address mined rewards go to: `--reward-address`, else the address of
+    /// the keystore
+    fn reward_address(&self) -> Result<Address, Box<dyn std::error::Error>> {
+        if let Some(address) = &self.reward_address {
+            return Ok(*address);
+        }
+        let path = self.keystore_path();
+        if !path.exists() {
+            return Err(format!(
+                "no keystore at {} to mine to, run `blocksmithd wallet create` first or pass --reward-address ADDRESS",
+                path.display()
+            )
+            .into());
+        }
+        Ok(Keystore::read_from(BufReader::new(File::open(&path)?))?.address())
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut command = None;
+    let mut datadir = PathBuf::from(DEFAULT_DATADIR);
+    let mut out = None;
+    let mut input = None;
+    let mut prune = None;
+    let mut snapshot = None;
+    let mut snapshot_commitment = None;
+    let mut history = None;
+    let mut mining_threads = None;
+    let mut keystore = None;
+    let mut reward_address = None;
+    let mut wallet_action = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("missing value for {}", flag));
+        match arg.as_str() {
+            "export" | "import" | "snapshot" if command.is_none() => command = Some(arg),
+            "wallet" if command.is_none() => {
+                wallet_action = Some(value("wallet")?);
+                command = Some(arg);
+            }
+            "--keystore" => keystore = Some(PathBuf::from(value("--keystore")?)),
+            "--reward-address" => {
+                let address = value("--reward-address")?;
+                reward_address = Some(
+                    address
+                        .parse::<Address>()
+                        .map_err(|e| format!("invalid reward address `{}`: {}", address, e))?,
+                );
+            }
+            "--snapshot" => snapshot = Some(PathBuf::from(value("--snapshot")?)),
+            "--snapshot-commitment" => {
+                let commitment = value("--snapshot-commitment")?;
+                snapshot_commitment = Some(
+                    commitment
+                        .parse::<Hash256>()
+                        .map_err(|_| format!("invalid snapshot commitment `{}`", commitment))?,
+                );
+            }
+            "--history" => history = Some(PathBuf::from(value("--history")?)),
+            "--datadir" => datadir = value("--datadir")?.into(),
+            "--out" => out = Some(PathBuf::from(value("--out")?)),
+            "--in" => input = Some(PathBuf::from(value("--in")?)),
+            "--prune" => {
+                let depth = value("--prune")?;
+                prune = Some(
+                    depth
+                        .parse()
+                        .map_err(|_| format!("invalid prune depth `{}`", depth))?,
+                );
+            }
+            "--mining-threads" => {
+                let threads = value("--mining-threads")?;
+                mining_threads = Some(
+                    threads
+                        .parse()
+                        .map_err(|_| format!("invalid thread count `{}`", threads))?,
+                );
+            }
+            other => return Err(format!("unexpected argument `{}`", other)),
+        }
+    }
+
+    let snapshot = match (snapshot, snapshot_commitment) {
+        (Some(path), Some(commitment)) => Some((path, commitment)),
+        (None, None) => None,
+        (Some(_), None) => return Err("--snapshot requires --snapshot-commitment HASH".into()),
+        (None, Some(_)) => return Err("--snapshot-commitment requires --snapshot FILE".into()),
+    };
+
+    let command = match command.as_deref() {
+        None => Command::Run { snapshot, history },
+        Some("export") => Command::Export {
+            out: out.ok_or("export requires --out FILE")?,
+        },
+        Some("snapshot") => Command::Snapshot {
+            out: out.ok_or("snapshot requires --out FILE")?,
+        },
+        Some("wallet") => Command::Wallet {
+            action: match wallet_action.as_deref() {
+                Some("create") => WalletAction::Create,
+                Some("restore") => WalletAction::Restore,
+                Some("address") => WalletAction::Address,
+                Some("passwd") => WalletAction::Passwd,
+                Some("import") => WalletAction::Import,
+                Some("export") => WalletAction::Export,
+                _ => {
+                    return Err(
+                        "wallet takes create, restore, address, passwd, import or export".into(),
+                    )
+                }
+            },
+        },
+        Some(_) => Command::Import {
+            input: input.ok_or("import requires --in FILE")?,
+        },
+    };
+
+    Ok(Args {
+        command,
+        datadir,
+        keystore,
+        reward_address,
+        prune,
+        mining_threads,
+    })
+}
+
+/// Opens the chain in the datadir, mining to `address`. Commands that never
+/// mine pass `NETWORK_ADDRESS`
+fn open_chain(
+    args: &Args,
+    address: Address,
+    snapshot: Option<&ChainSnapshot>,
+) -> Result<Blockchain, Box<dyn std::error::Error>> {
+    let store = Box::new(DiskStore::open(&args.datadir)?);
+
+    let mut blockchain = match snapshot {
+        Some(snapshot) => {
+            Blockchain::from_snapshot(address, chain::MINING_DIFFICULTY, store, snapshot)
+        }
+        None => Blockchain::with_store(address, chain::MINING_DIFFICULTY, store),
+    }
+    .map_err(|e| format!("Failed to open chain: {}", e))?;
+    blockchain
+        .set_prune_depth(args.prune)
+        .map_err(|e| format!("Failed to prune chain: {}", e))?;
+    if let Some(threads) = args.mining_threads {
+        blockchain.set_mining_threads(threads);
+    }
+
+    Ok(blockchain)
+}
+
+fn export(args: &Args, out: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let blockchain = open_chain(args, NETWORK_ADDRESS, None)?;
+    let file = BufWriter::new(File::create(out)?);
+
+    let count = chain::export::export_chain(&blockchain, file, |done, total| {
+        if done % 100 == 0 || done == total {
+            eprintln!("exported {}/{} blocks", done, total);
+        }
+    })?;
+    println!("Exported {} blocks to {}", count, out.display());
+    Ok(())
+}
+
+fn import(args: &Args, input: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut blockchain = open_chain(args, NETWORK_ADDRESS, None)?;
+    let file = BufReader::new(File::open(input)?);
+
+    let added = chain::export::import_chain(&mut blockchain, file, |done, total| {
+        if done % 100 == 0 || done == total {
+            eprintln!("processed {}/{} blocks", done, total);
+        }
+    })?;
+    println!(
+        "Imported {} new blocks, chain height is now {}",
+        added,
+        blockchain
+            .height()
+            .map_err(|e| format!("Failed to read height: {}", e))?
+    );
+    Ok(())
+}
+
+fn snapshot(args: &Args, out: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let blockchain = open_chain(args, NETWORK_ADDRESS, None)?;
+    let snapshot = blockchain
+        .snapshot()
+        .map_err(|e| format!("Failed to snapshot chain: {}", e))?;
+
+    snapshot.write_to(BufWriter::new(File::create(out)?))?;
+    println!(
+        "Wrote snapshot of height {} with commitment {} to {}",
+        snapshot.height(),
+        snapshot.commitment()?,
+        out.display()
+    );
+    Ok(())
+}
+
+/// Reads a line from stdin, prompting on stderr
+fn read_line(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    eprint!("{}: ", prompt);
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line)? == 0 {
+        return Err(format!("no {} on stdin", prompt).into());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Seals `wallet` in a new keystore at `path`, under a passphrase read twice
+fn create_keystore(path: &Path, wallet: Wallet) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = read_line("passphrase")?;
+    if passphrase.is_empty() {
+        return Err("the passphrase must not be empty".into());
+    }
+    if read_line("repeat passphrase")? != passphrase {
+        return Err("passphrases do not match".into());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Never overwrite a keystore, it may hold the only copy of a key
+    let file = File::create_new(path)
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+
+    let keystore = Keystore::create(wallet, &passphrase)?;
+    keystore.write_to(BufWriter::new(file))?;
+    println!("{}", keystore.address());
+    Ok(())
+}
+
+fn wallet(args: &Args, action: &WalletAction) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args.keystore_path();
+    match action {
+        WalletAction::Create => {
+            let hd_wallet = HdWallet::generate(MNEMONIC_WORDS, "")?;
+            create_keystore(&path, hd_wallet.receiving_wallet(0, 0)?)?;
+            eprintln!(
+                "Write down the mnemonic, it restores the wallet if the keystore is lost:\n{}",
+                hd_wallet.phrase()
+            );
+        }
+        WalletAction::Restore => {
+            let hd_wallet = HdWallet::from_phrase(read_line("mnemonic")?.trim(), "")
+                .map_err(|e| format!("Invalid mnemonic: {}", e))?;
+            create_keystore(&path, hd_wallet.receiving_wallet(0, 0)?)?;
+        }
+        WalletAction::Import => {
+            let wallet = Wallet::from_wif(read_line("WIF key")?.trim())
+                .map_err(|e| format!("Invalid WIF key: {}", e))?;
+            create_keystore(&path, wallet)?;
+        }
+        WalletAction::Export => {
+            let mut keystore = Keystore::read_from(BufReader::new(File::open(&path)?))?;
+            let wallet = keystore.unlock(&read_line("passphrase")?)?;
+            println!("{}", wallet.to_wif());
+        }
+        WalletAction::Address => {
+            let keystore = Keystore::read_from(BufReader::new(File::open(&path)?))?;
+            println!("{}", keystore.address());
+        }
+        WalletAction::Passwd => {
+            let mut keystore = Keystore::read_from(BufReader::new(File::open(&path)?))?;
+            let old = read_line("current passphrase")?;
+            let new = read_line("new passphrase")?;
+            if new.is_empty() {
+                return Err("the passphrase must not be empty".into());
+            }
+            keystore.change_passphrase(&old, &new)?;
+
+            // Written aside first, a failed write must not lose the key
+            let staged = path.with_extension("json.new");
+            keystore.write_to(BufWriter::new(File::create(&staged)?))?;
+            std::fs::rename(&staged, &path)?;
+            eprintln!("Passphrase of {} changed", keystore.address());
+        }
+    }
+    Ok(())
+}
+
+/// Checks the blocks of `history` against the snapshot the node started from,
+/// taking the chain lock one block at a time. Blocks may also come from peers
+/// meanwhile, see `POST /snapshot/block`.
+fn validate_snapshot_in_background(blockchain: Arc<Mutex<Blockchain>>, history: PathBuf) {
+    std::thread::spawn(move || {
+        let result = (|| -> Result<SnapshotStatus, Box<dyn std::error::Error>> {
+            let reader = ChainReader::new(BufReader::new(File::open(&history)?))?;
+            let mut status = SnapshotStatus::Unverified;
+            let lock = || blockchain.lock().map_err(|_| "Failed to lock chain");
+            for block in reader {
+                // Checked without the lock, which is only taken to replay it
+                let context = lock()?.historical_block_context()?;
+                let block = context.check(block?)?;
+                status = lock()?.add_checked_historical_block(block)?;
+                if status == SnapshotStatus::Validated {
+                    break;
+                }
+            }
+            Ok(status)
+        })();
+
+        match result {
+            Ok(status) => eprintln!("snapshot validation finished: {:?}", status),
+            Err(e) => eprintln!("snapshot validation stopped: {}", e),
+        }
+    });
+}
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
-    chain::run().unwrap();
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match &args.command {
+        Command::Export { out } => export(&args, out),
+        Command::Import { input } => import(&args, input),
+        Command::Snapshot { out } => snapshot(&args, out),
+        Command::Wallet { action } => wallet(&args, action),
+        Command::Run { snapshot, history } => {
+            let address = args.reward_address()?;
+            chain::run()?;
+
+            let snapshot = match snapshot {
+                Some((path, commitment)) => Some(ChainSnapshot::read_from(
+                    BufReader::new(File::open(path)?),
+                    commitment,
+                )?),
+                None => None,
+            };
+            let blockchain = open_chain(&args, address, snapshot.as_ref())?;
+            if let Some(SnapshotStatus::Invalid { reason }) = blockchain.snapshot_status() {
+                eprintln!(
+                    "snapshot was found invalid ({}), the chain starts over from genesis",
+                    reason
+                );
+            }
+            let blockchain = Arc::new(Mutex::new(blockchain));
+
+            if let Some(history) = history {
+                validate_snapshot_in_background(blockchain.clone(), history.clone());
+            }
 
-    let listener = TcpListener::bind("127.0.0.1:8080")?;
-    api::run(listener)?.await
+            let listener = TcpListener::bind("127.0.0.1:8080")?;
+            api::run_with_shared_chain(listener, blockchain)?.await?;
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,793 @@
+use chain::address::{Address, Encoding, TESTNET_VERSION};
+use chain::block::Block;
+use chain::chain::Blockchain;
+use chain::hash::Hash256;
+use chain::keystore::Keystore;
+use chain::multisig::Multisig;
+use chain::transaction::Transaction;
+use chain::wallet::Wallet;
+use std::net::TcpListener;
+
+/// Participants of the tests
+const MINER: Address = Address::new(TESTNET_VERSION, [1; 20]);
+const RECIPIENT: Address = Address::new(TESTNET_VERSION, [3; 20]);
+const OTHER: Address = Address::new(TESTNET_VERSION, [4; 20]);
+
+fn spawn_app_with(blockchain: Blockchain) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
+    let port = listener.local_addr().unwrap().port();
+
+    let server = api::run_with_chain(listener, blockchain).expect("failed to bind address");
+
+    drop(tokio::spawn(server));
+
+    format!("http://localhost:{}", port)
+}
+
+/// Chain whose first block rewarded `wallet`, so it has coins to spend
+fn funded_chain(wallet: &Wallet) -> Blockchain {
+    let mut blockchain = Blockchain::new(wallet.address(), 1);
+    blockchain.mine().unwrap();
+    blockchain
+}
+
+/// Payment from `wallet`, the `sequence`-th one it sends
+fn signed_transaction(
+    wallet: &Wallet,
+    recipient: Address,
+    value: f32,
+    sequence: u64,
+) -> Transaction {
+    let mut transaction =
+        Transaction::new(wallet.address(), recipient, value).with_sequence(sequence);
+    wallet.sign_transaction(&mut transaction);
+    transaction
+}
+
+fn pruned_chain() -> (Blockchain, Hash256) {
+    let wallet = Wallet::generate_new();
+    let mut blockchain = funded_chain(&wallet);
+    blockchain
+        .submit_transaction(signed_transaction(&wallet, RECIPIENT, 5.0, 0))
+        .unwrap();
+    blockchain.mine().unwrap();
+    let pruned_tx = blockchain.block_at_height(2).unwrap().transactions()[0].id();
+    for _ in 0..2 {
+        blockchain.mine().unwrap();
+    }
+    blockchain.set_prune_depth(Some(2)).unwrap();
+    (blockchain, pruned_tx)
+}
+
+#[tokio::test]
+async fn chain_ranges_in_pruned_territory_are_gone() {
+    let (blockchain, _) = pruned_chain();
+    let address = spawn_app_with(blockchain);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/chain?from=0", address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response.text().await.unwrap().contains("pruned"));
+
+    let response = client
+        .get(format!("{}/chain?from=3&to=4", address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn transaction_lookup_in_pruned_territory_is_gone() {
+    let (blockchain, pruned_tx) = pruned_chain();
+    let address = spawn_app_with(blockchain);
+
+    let response = reqwest::get(format!("{}/transaction/{}", address, pruned_tx))
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn address_history_is_paginated_newest_first() {
+    let mut blockchain = Blockchain::new(MINER, 1);
+    for _ in 0..3 {
+        blockchain.mine().unwrap();
+    }
+    let address = spawn_app_with(blockchain);
+
+    let balance: serde_json::Value = reqwest::get(format!("{}/address/{}/balance", address, MINER))
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(balance["balance"], 30.0);
+
+    let page: serde_json::Value = reqwest::get(format!(
+        "{}/address/{}/transactions?offset=1&limit=1",
+        address, MINER
+    ))
+    .await
+    .expect("Failed to execute request")
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(page["total"], 3);
+    assert_eq!(page["transactions"].as_array().unwrap().len(), 1);
+    assert_eq!(page["transactions"][0]["height"], 2);
+    assert_eq!(page["transactions"][0]["delta"], 10.0);
+}
+
+#[tokio::test]
+async fn new_wallets_come_as_sealed_keystores() {
+    let address = spawn_app_with(Blockchain::new(MINER, 1));
+    let client = reqwest::Client::new();
+
+    let response: serde_json::Value = client
+        .post(format!("{}/wallet/new", address))
+        .body(serde_json::json!({ "passphrase": "correct horse" }).to_string())
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert!(response["wallet"].get("private_key").is_none());
+    assert_eq!(
+        response["keystore"]["address"],
+        response["wallet"]["address"]
+    );
+
+    let mut keystore = Keystore::read_from(response["keystore"].to_string().as_bytes()).unwrap();
+    assert!(keystore.unlock("wrong horse").is_err());
+    assert_eq!(
+        keystore
+            .unlock("correct horse")
+            .unwrap()
+            .address()
+            .to_string(),
+        response["wallet"]["address"]
+    );
+
+    let empty = client
+        .post(format!("{}/wallet/new", address))
+        .body(serde_json::json!({ "passphrase": "" }).to_string())
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(empty.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn wallets_are_restored_from_their_mnemonic() {
+    let address = spawn_app_with(Blockchain::new(MINER, 1));
+    let client = reqwest::Client::new();
+    let post = |path: &str, body: serde_json::Value| {
+        client
+            .post(format!("{}/wallet/{}", address, path))
+            .body(body.to_string())
+            .send()
+    };
+
+    let created: serde_json::Value = post("new", serde_json::json!({ "passphrase": "pass" }))
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    let mnemonic = created["mnemonic"].as_str().unwrap();
+    assert_eq!(mnemonic.split_whitespace().count(), 12);
+
+    let restored: serde_json::Value = post(
+        "restore",
+        serde_json::json!({ "mnemonic": mnemonic, "passphrase": "other pass" }),
+    )
+    .await
+    .expect("Failed to execute request")
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(restored["wallet"]["address"], created["wallet"]["address"]);
+    assert!(restored.get("mnemonic").is_none());
+
+    let invalid = post(
+        "restore",
+        serde_json::json!({ "mnemonic": "not a mnemonic", "passphrase": "pass" }),
+    )
+    .await
+    .expect("Failed to execute request");
+    assert_eq!(invalid.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn keys_are_imported_and_exported_as_wif() {
+    let address = spawn_app_with(Blockchain::new(MINER, 1));
+    let client = reqwest::Client::new();
+    let post = |path: &str, body: serde_json::Value| {
+        client
+            .post(format!("{}/wallet/{}", address, path))
+            .body(body.to_string())
+            .send()
+    };
+
+    let wallet = Wallet::generate_new();
+    let imported: serde_json::Value = post(
+        "import",
+        serde_json::json!({ "wif": wallet.to_wif(), "passphrase": "pass" }),
+    )
+    .await
+    .expect("Failed to execute request")
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(imported["wallet"]["address"], wallet.address().to_string());
+
+    let exported: serde_json::Value = post(
+        "export",
+        serde_json::json!({ "keystore": imported["keystore"], "passphrase": "pass" }),
+    )
+    .await
+    .expect("Failed to execute request")
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(exported["wif"], wallet.to_wif());
+
+    let wrong_passphrase = post(
+        "export",
+        serde_json::json!({ "keystore": imported["keystore"], "passphrase": "guess" }),
+    )
+    .await
+    .expect("Failed to execute request");
+    assert_eq!(wrong_passphrase.status().as_u16(), 403);
+
+    let mainnet_key = post(
+        "import",
+        serde_json::json!({
+            "wif": "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn",
+            "passphrase": "pass",
+        }),
+    )
+    .await
+    .expect("Failed to execute request");
+    assert_eq!(mainnet_key.status().as_u16(), 400);
+    let body: serde_json::Value = mainnet_key.json().await.unwrap();
+    assert!(body["message"]
+        .as_str()
+        .unwrap()
+        .contains("another network"));
+}
+
+#[tokio::test]
+async fn signed_messages_prove_address_ownership() {
+    let address = spawn_app_with(Blockchain::new(MINER, 1));
+    let client = reqwest::Client::new();
+    let post = |path: &str, body: serde_json::Value| {
+        client
+            .post(format!("{}/{}", address, path))
+            .body(body.to_string())
+            .send()
+    };
+
+    let wallet = Wallet::generate_new();
+    let imported: serde_json::Value = post(
+        "wallet/import",
+        serde_json::json!({ "wif": wallet.to_wif(), "passphrase": "pass" }),
+    )
+    .await
+    .expect("Failed to execute request")
+    .json()
+    .await
+    .unwrap();
+
+    let signed: serde_json::Value = post(
+        "message/sign",
+        serde_json::json!({
+            "keystore": imported["keystore"],
+            "passphrase": "pass",
+            "message": "I own this address",
+        }),
+    )
+    .await
+    .expect("Failed to execute request")
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(signed["address"], wallet.address().to_string());
+
+    let verify = |signer: String, message: &str| {
+        post(
+            "message/verify",
+            serde_json::json!({
+                "address": signer,
+                "message": message,
+                "signature": signed["signature"],
+            }),
+        )
+    };
+    let verified: serde_json::Value = verify(wallet.address().to_string(), "I own this address")
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(verified["valid"], true);
+
+    let other: serde_json::Value = verify(OTHER.to_string(), "I own this address")
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(other["valid"], false);
+
+    let altered: serde_json::Value = verify(wallet.address().to_string(), "I own that address")
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(altered["valid"], false);
+
+    let malformed = post(
+        "message/verify",
+        serde_json::json!({
+            "address": wallet.address().to_string(),
+            "message": "I own this address",
+            "signature": "not a signature",
+        }),
+    )
+    .await
+    .expect("Failed to execute request");
+    assert_eq!(malformed.status().as_u16(), 400);
+
+    let wrong_passphrase = post(
+        "message/sign",
+        serde_json::json!({
+            "keystore": imported["keystore"],
+            "passphrase": "guess",
+            "message": "I own this address",
+        }),
+    )
+    .await
+    .expect("Failed to execute request");
+    assert_eq!(wrong_passphrase.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn multisig_accounts_spend_with_enough_cosigners() {
+    let cosigners: Vec<Wallet> = (0..3).map(|_| Wallet::generate_new()).collect();
+    let multisig = Multisig::new(
+        2,
+        cosigners.iter().map(|wallet| wallet.public_key()).collect(),
+    )
+    .unwrap();
+    let mut blockchain = Blockchain::new(multisig.address(), 1);
+    blockchain.mine().unwrap();
+    let address = spawn_app_with(blockchain);
+    let client = reqwest::Client::new();
+
+    let mut public_keys: Vec<_> = multisig.public_keys().to_vec();
+    public_keys.reverse();
+    let derived: serde_json::Value = client
+        .post(format!("{}/multisig/address", address))
+        .body(serde_json::json!({ "threshold": 2, "public_keys": public_keys }).to_string())
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(derived["address"], multisig.address().to_string());
+
+    let mut transaction = Transaction::new(multisig.address(), RECIPIENT, 10.0);
+    cosigners[0].sign_multisig(&mut transaction, &multisig);
+    let submit = |transaction: &Transaction| {
+        client
+            .post(format!("{}/transaction/new", address))
+            .body(serde_json::to_string(transaction).unwrap())
+            .send()
+    };
+
+    let response = submit(&transaction)
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["reason"], "missing_signatures");
+
+    cosigners[2].sign_multisig(&mut transaction, &multisig);
+    let response = submit(&transaction)
+        .await
+        .expect("Failed to execute request");
+    assert!(response.status().is_success());
+
+    // The account only held one reward, which is now pending
+    let mut overspend = Transaction::new(multisig.address(), RECIPIENT, 1.0).with_sequence(1);
+    cosigners[0].sign_multisig(&mut overspend, &multisig);
+    cosigners[1].sign_multisig(&mut overspend, &multisig);
+    let response = submit(&overspend).await.expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["reason"], "insufficient_funds");
+
+    let invalid = client
+        .post(format!("{}/multisig/address", address))
+        .body(serde_json::json!({ "threshold": 3, "public_keys": public_keys[..2] }).to_string())
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(invalid.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn addresses_are_validated_locally() {
+    let address = spawn_app_with(Blockchain::new(MINER, 1));
+
+    let validate = |candidate: String| {
+        let address = address.clone();
+        async move {
+            reqwest::get(format!("{}/address/{}/validate", address, candidate))
+                .await
+                .expect("Failed to execute request")
+                .json::<serde_json::Value>()
+                .await
+                .unwrap()
+        }
+    };
+
+    let valid = validate(MINER.to_string()).await;
+    assert_eq!(valid["is_valid"], true);
+    assert_eq!(valid["version"], TESTNET_VERSION);
+
+    let mainnet = validate(String::from("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa")).await;
+    assert_eq!(mainnet["is_valid"], true);
+    assert_eq!(mainnet["version"], 0);
+
+    let tampered = validate(String::from("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb")).await;
+    assert_eq!(tampered["is_valid"], false);
+    assert_eq!(tampered["reason"], "address checksum does not match");
+
+    let segwit = validate(String::from("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")).await;
+    assert_eq!(segwit["is_valid"], true);
+    assert_eq!(segwit["encoding"], "bech32");
+
+    let taproot = validate(String::from(
+        "tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c",
+    ))
+    .await;
+    assert_eq!(taproot["is_valid"], true);
+    assert_eq!(taproot["encoding"], "bech32m");
+}
+
+#[tokio::test]
+async fn bech32_addresses_are_accepted_everywhere() {
+    let wallet = Wallet::generate_new();
+    let address = spawn_app_with(funded_chain(&wallet));
+    let client = reqwest::Client::new();
+    let recipient = RECIPIENT.with_encoding(Encoding::Bech32);
+
+    let response = client
+        .post(format!("{}/transaction/new", address))
+        .body(serde_json::to_string(&signed_transaction(&wallet, recipient, 1.0, 0)).unwrap())
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert!(response.status().is_success());
+
+    let pending: serde_json::Value = reqwest::get(format!("{}/transactions/pending", address))
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(pending[0]["recipient_address"], recipient.to_string());
+
+    let balance: serde_json::Value =
+        reqwest::get(format!("{}/address/{}/balance", address, recipient))
+            .await
+            .expect("Failed to execute request")
+            .json()
+            .await
+            .unwrap();
+    assert_eq!(balance["address"], recipient.to_string());
+    assert_eq!(balance["balance"], 0.0);
+
+    // The pending payment uses up the first sequence of the sender
+    let balance: serde_json::Value =
+        reqwest::get(format!("{}/address/{}/balance", address, wallet.address()))
+            .await
+            .expect("Failed to execute request")
+            .json()
+            .await
+            .unwrap();
+    assert_eq!(balance["next_sequence"], 1);
+}
+
+#[tokio::test]
+async fn blocks_can_be_looked_up_by_height_and_hash() {
+    let mut blockchain = Blockchain::new(MINER, 1);
+    for _ in 0..3 {
+        blockchain.mine().unwrap();
+    }
+    let second = blockchain.hash_at_height(2).unwrap();
+    let address = spawn_app_with(blockchain);
+
+    let get = |path: String| async move {
+        reqwest::get(path)
+            .await
+            .expect("Failed to execute request")
+            .json::<serde_json::Value>()
+            .await
+            .unwrap()
+    };
+
+    let tip = get(format!("{}/tip", address)).await;
+    assert_eq!(tip["height"], 3);
+    assert!(tip["header"].is_object());
+
+    let by_height = get(format!("{}/block/height/2", address)).await;
+    assert_eq!(by_height["hash"], second.to_string());
+
+    let by_hash = get(format!("{}/block/{}", address, second)).await;
+    assert_eq!(by_hash["height"], 2);
+    assert_eq!(by_hash["block"], by_height["block"]);
+
+    let header = get(format!("{}/block/{}/header", address, second)).await;
+    assert!(header.get("block").is_none());
+    assert_eq!(header["header"]["transaction_count"], 1);
+
+    let page = get(format!("{}/blocks?from=1&limit=2", address)).await;
+    let heights: Vec<_> = page
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|block| block["height"].as_u64().unwrap())
+        .collect();
+    assert_eq!(heights, vec![1, 2]);
+
+    let headers = get(format!("{}/headers?from=3&limit=10", address)).await;
+    assert_eq!(headers.as_array().unwrap().len(), 1);
+
+    let missing = reqwest::get(format!("{}/block/{}", address, Hash256::digest("unknown")))
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(missing.status().as_u16(), 404);
+
+    let malformed = reqwest::get(format!("{}/block/unknown", address))
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(malformed.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn pending_transactions_are_listed() {
+    let wallet = Wallet::generate_new();
+    let mut blockchain = funded_chain(&wallet);
+    blockchain
+        .submit_transaction(signed_transaction(&wallet, RECIPIENT, 5.0, 0))
+        .unwrap();
+    let address = spawn_app_with(blockchain);
+
+    let pending: serde_json::Value = reqwest::get(format!("{}/transactions/pending", address))
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(pending.as_array().unwrap().len(), 1);
+    assert_eq!(pending[0]["recipient_address"], RECIPIENT.to_string());
+}
+
+#[tokio::test]
+async fn invalid_blocks_are_rejected_with_a_json_error() {
+    let address = spawn_app_with(Blockchain::new(MINER, 1));
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/block/new", address))
+        .body(
+            serde_json::json!({
+                "timestamp": 0,
+                "nonce": 0,
+                "previous_hash": Hash256::digest("not_the_tip"),
+                "transactions": [],
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "validation_failed");
+    assert_eq!(body["chain_error"], "ValidationError");
+    assert_eq!(body["reason"], "bad_previous_hash");
+
+    let response = client
+        .post(format!("{}/block/new", address))
+        .body("not json")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "bad_request");
+}
+
+#[tokio::test]
+async fn lookup_errors_carry_a_json_body() {
+    let (blockchain, pruned_tx) = pruned_chain();
+    let address = spawn_app_with(blockchain);
+
+    let missing = reqwest::get(format!("{}/block/height/99", address))
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(missing.status().as_u16(), 404);
+    let body: serde_json::Value = missing.json().await.unwrap();
+    assert_eq!(body["code"], "not_found");
+
+    let gone = reqwest::get(format!("{}/transaction/{}", address, pruned_tx))
+        .await
+        .expect("Failed to execute request");
+    let body: serde_json::Value = gone.json().await.unwrap();
+    assert_eq!(body["code"], "pruned");
+    assert_eq!(body["chain_error"], "Pruned");
+
+    let bad_query = reqwest::get(format!("{}/blocks?from=abc", address))
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(bad_query.status().as_u16(), 400);
+    let body: serde_json::Value = bad_query.json().await.unwrap();
+    assert_eq!(body["code"], "bad_request");
+}
+
+#[tokio::test]
+async fn events_are_streamed_filtered_by_address() {
+    let wallet = Wallet::generate_new();
+    let address = spawn_app_with(funded_chain(&wallet));
+    let client = reqwest::Client::new();
+
+    let mut stream = client
+        .get(format!("{}/events?address={}", address, RECIPIENT))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(
+        stream.headers()["content-type"].to_str().unwrap(),
+        "text/event-stream"
+    );
+
+    for (sequence, recipient) in [OTHER, RECIPIENT].into_iter().enumerate() {
+        let transaction = signed_transaction(&wallet, recipient, 1.0, sequence as u64);
+        client
+            .post(format!("{}/transaction/new", address))
+            .body(serde_json::to_string(&transaction).unwrap())
+            .send()
+            .await
+            .expect("Failed to execute request");
+    }
+    client
+        .post(format!("{}/mine/start", address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let mut received = String::new();
+    while received.matches("\n\n").count() < 2 {
+        let chunk = stream.chunk().await.unwrap().expect("Stream ended early");
+        received.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+
+    let frames: Vec<&str> = received.split("\n\n").collect();
+    assert!(frames[0].starts_with("event: transaction_accepted\n"));
+    assert!(frames[0].contains(&format!("\"recipient_address\":\"{}\"", RECIPIENT)));
+    assert!(frames[1].starts_with("event: block_connected\n"));
+}
+
+#[tokio::test]
+async fn mining_runs_in_the_background() {
+    let address = spawn_app_with(Blockchain::new(MINER, 1));
+    let client = reqwest::Client::new();
+
+    let status: serde_json::Value = client
+        .post(format!("{}/mine/start", address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["running"], true);
+
+    // The chain stays reachable while the miner works
+    let mut height = 0;
+    while height < 2 {
+        let tip: serde_json::Value = reqwest::get(format!("{}/tip", address))
+            .await
+            .expect("Failed to execute request")
+            .json()
+            .await
+            .unwrap();
+        height = tip["height"].as_u64().unwrap();
+    }
+
+    let status: serde_json::Value = client
+        .post(format!("{}/mine/stop", address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["running"], false);
+    assert!(status["blocks_mined"].as_u64().unwrap() >= 2);
+
+    let status: serde_json::Value = reqwest::get(format!("{}/mine", address))
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["running"], false);
+}
+
+#[tokio::test]
+async fn historical_blocks_from_peers_validate_the_snapshot() {
+    let mut source = Blockchain::new(MINER, 1);
+    source.mine().unwrap();
+    source.mine().unwrap();
+    let restored = Blockchain::from_snapshot(
+        OTHER,
+        1,
+        Box::new(chain::store::MemoryStore::new()),
+        &source.snapshot().unwrap(),
+    )
+    .unwrap();
+    let address = spawn_app_with(restored);
+    let client = reqwest::Client::new();
+    let post = |block: &Block| {
+        client
+            .post(format!("{}/snapshot/block", address))
+            .body(serde_json::to_string(block).unwrap())
+            .send()
+    };
+
+    let mut other = Blockchain::new(OTHER, 1);
+    other.mine().unwrap();
+    let foreign = post(&other.block_at_height(1).unwrap())
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(foreign.status().as_u16(), 422);
+    let body: serde_json::Value = foreign.json().await.unwrap();
+    assert_eq!(body["code"], "snapshot_block_rejected");
+
+    let mut status = serde_json::Value::Null;
+    for block in source.chain().unwrap() {
+        status = post(&block)
+            .await
+            .expect("Failed to execute request")
+            .json()
+            .await
+            .unwrap();
+    }
+    assert_eq!(status["status"], "validated");
+
+    let reported: serde_json::Value = reqwest::get(format!("{}/snapshot/status", address))
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(reported["status"], "validated");
+
+    let done = post(&source.block_at_height(0).unwrap())
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(done.status().as_u16(), 409);
+}
@@ -1,6 +1,4 @@
-use api;
 mod tests {
-    use super::*;
     use std::net::TcpListener;
 
     fn spawn_app() -> String {
@@ -9,7 +7,7 @@ mod tests {
 
         let server = api::run(listener).expect("failed to bind address");
 
-        let _ = tokio::spawn(server);
+        drop(tokio::spawn(server));
 
         format!("http://localhost:{}", port)
     }
@@ -0,0 +1,166 @@
+use chain::address::{Address, TESTNET_VERSION};
+use chain::chain::Blockchain;
+use chain::hash::Hash256;
+use chain::transaction::Transaction;
+use chain::wallet::Wallet;
+use serde_json::{json, Value};
+use std::net::TcpListener;
+
+/// Participants of the tests
+const MINER: Address = Address::new(TESTNET_VERSION, [1; 20]);
+const RECIPIENT: Address = Address::new(TESTNET_VERSION, [3; 20]);
+
+fn spawn_app_with(blockchain: Blockchain) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
+    let port = listener.local_addr().unwrap().port();
+
+    let server = api::run_with_chain(listener, blockchain).expect("failed to bind address");
+
+    drop(tokio::spawn(server));
+
+    format!("http://localhost:{}/rpc", port)
+}
+
+async fn call(address: &str, body: String) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(address)
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn call_json(address: &str, request: Value) -> Value {
+    call(address, request.to_string())
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn methods_map_onto_the_chain() {
+    let wallet = Wallet::generate_new();
+    let mut blockchain = Blockchain::new(wallet.address(), 1);
+    blockchain.mine().unwrap();
+    let tip = blockchain.hash_at_height(1).unwrap();
+    let address = spawn_app_with(blockchain);
+
+    let count = call_json(
+        &address,
+        json!({ "jsonrpc": "2.0", "method": "getblockcount", "id": 1 }),
+    )
+    .await;
+    assert_eq!(count, json!({ "jsonrpc": "2.0", "result": 1, "id": 1 }));
+
+    let block = call_json(
+        &address,
+        json!({ "jsonrpc": "2.0", "method": "getblock", "params": [tip], "id": "b" }),
+    )
+    .await;
+    assert_eq!(block["result"]["height"], 1);
+    assert_eq!(block["id"], "b");
+
+    let balance = call_json(
+        &address,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "getbalance",
+            "params": { "address": wallet.address() },
+            "id": 2,
+        }),
+    )
+    .await;
+    assert_eq!(balance["result"], 10.0);
+
+    let mut transaction = Transaction::new(wallet.address(), RECIPIENT, 1.0);
+    wallet.sign_transaction(&mut transaction);
+    let sent = call_json(
+        &address,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "sendrawtransaction",
+            "params": [transaction],
+            "id": 3,
+        }),
+    )
+    .await;
+    assert!(sent["result"].is_string());
+
+    let mempool = call_json(
+        &address,
+        json!({ "jsonrpc": "2.0", "method": "getmempoolinfo", "id": 4 }),
+    )
+    .await;
+    assert_eq!(mempool["result"]["size"], 1);
+}
+
+#[tokio::test]
+async fn batches_answer_every_call_but_notifications() {
+    let address = spawn_app_with(Blockchain::new(MINER, 1));
+
+    let responses = call_json(
+        &address,
+        json!([
+            { "jsonrpc": "2.0", "method": "getblockcount", "id": 1 },
+            { "jsonrpc": "2.0", "method": "getmininginfo" },
+            { "jsonrpc": "2.0", "method": "nosuchmethod", "id": 2 },
+            { "jsonrpc": "1.0", "method": "getblockcount", "id": 3 },
+        ]),
+    )
+    .await;
+
+    let responses = responses.as_array().unwrap();
+    assert_eq!(responses.len(), 3);
+    assert_eq!(responses[0]["result"], 0);
+    assert_eq!(responses[1]["error"]["code"], -32601);
+    assert_eq!(responses[2]["error"]["code"], -32600);
+
+    let notification = call(
+        &address,
+        json!({ "jsonrpc": "2.0", "method": "getblockcount" }).to_string(),
+    )
+    .await;
+    assert_eq!(notification.status().as_u16(), 204);
+}
+
+#[tokio::test]
+async fn errors_use_the_standard_codes() {
+    let address = spawn_app_with(Blockchain::new(MINER, 1));
+
+    let parse_error: Value = call(&address, String::from("{ not json"))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(parse_error["error"]["code"], -32700);
+    assert_eq!(parse_error["id"], Value::Null);
+
+    let empty_batch = call_json(&address, json!([])).await;
+    assert_eq!(empty_batch["error"]["code"], -32600);
+
+    let missing_params = call_json(
+        &address,
+        json!({ "jsonrpc": "2.0", "method": "getbalance", "id": 1 }),
+    )
+    .await;
+    assert_eq!(missing_params["error"]["code"], -32602);
+
+    let rejected = call_json(
+        &address,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "submitblock",
+            "params": [{
+                "timestamp": 0,
+                "nonce": 0,
+                "previous_hash": Hash256::digest("not_the_tip"),
+                "transactions": [],
+            }],
+            "id": 2,
+        }),
+    )
+    .await;
+    assert_eq!(rejected["error"]["code"], -26);
+    assert_eq!(rejected["error"]["data"]["reason"], "bad_previous_hash");
+}
@@ -0,0 +1,79 @@
+//! Hashes per second of a nonce attempt, before and after midstate hashing.
+//!
+//! `json` is how blocks used to be hashed: serializing the whole block to JSON
+//! for every nonce. `header` hashes the 80 byte header from scratch, as
+//! `Block::hash` does once per nonce, and `midstate` only the timestamp and
+//! nonce on top of a precomputed state, as the miner does. Criterion reports
+//! the throughput in hashes (elements) per second.
+//!
+//!     cargo bench -p chain --bench hashing
+
+use chain::address::{Address, TESTNET_VERSION};
+use chain::block::Block;
+use chain::transaction::Transaction;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use sha2::Digest;
+use std::sync::Arc;
+
+fn block_with(transaction_count: usize) -> Block {
+    let transactions = (0..transaction_count)
+        .map(|i| {
+            Arc::new(Transaction::new(
+                Address::new(TESTNET_VERSION, [i as u8; 20]),
+                Address::new(TESTNET_VERSION, [!(i as u8); 20]),
+                i as f32,
+            ))
+        })
+        .collect();
+    Block::create_from(transactions, 0, Block::genesis().hash())
+}
+
+fn nonce_attempts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nonce_attempt");
+    group.throughput(Throughput::Elements(1));
+
+    for transaction_count in [1, 100, 1000] {
+        let mut block = block_with(transaction_count);
+
+        group.bench_with_input(
+            BenchmarkId::new("json", transaction_count),
+            &transaction_count,
+            |b, _| {
+                b.iter(|| {
+                    block.increment_nonce();
+                    let block_json = serde_json::to_string(&block).unwrap();
+                    black_box(sha2::Sha256::digest(block_json.as_bytes()))
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("header", transaction_count),
+            &transaction_count,
+            |b, _| {
+                b.iter(|| {
+                    block.increment_nonce();
+                    black_box(block.hash())
+                })
+            },
+        );
+
+        let hasher = block.header().hasher();
+        let mut nonce = 0;
+        group.bench_with_input(
+            BenchmarkId::new("midstate", transaction_count),
+            &transaction_count,
+            |b, _| {
+                b.iter(|| {
+                    nonce += 1;
+                    black_box(hasher.hash(nonce))
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, nonce_attempts);
+criterion_main!(benches);
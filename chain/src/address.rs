@@ -0,0 +1,646 @@
+//! Pay-to-pubkey-hash, pay-to-script-hash and taproot addresses.
+//!
+//! An address is a version byte followed by the RIPEMD-160 of the SHA-256 of a
+//! public key, written in base58 with a 4 byte checksum appended, the first
+//! bytes of the double SHA-256 of the rest. Multisig accounts hash their policy
+//! instead, under version bytes of their own so that the two kinds of address
+//! never collide.
+//!
+//! The same hash can also be written as a native segwit address: a
+//! human-readable prefix naming the network (`bc` or `tb`), a witness version
+//! and the hash as program, in bech32. Each encoding is an address of its own,
+//! funds sent to one are not spendable from the other.
+//!
+//! Taproot addresses hold no hash but the 32 byte x-only public key itself, as
+//! a witness version 1 program written in bech32m (BIP350), and are spent with
+//! BIP340 Schnorr signatures only.
+//!
+//! The same key hashes to a different address depending on how it is
+//! serialized. New wallets use the 33 byte compressed form, as standard tools
+//! do, and legacy addresses of the 65 byte uncompressed form stay valid.
+
+use bech32::{hrp, segwit, Fe32, Hrp};
+use ripemd::Digest;
+use secp256k1::PublicKey;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Version byte of mainnet addresses
+pub const MAINNET_VERSION: u8 = 0x00;
+/// Version byte of testnet addresses, the ones wallets generate
+pub const TESTNET_VERSION: u8 = 0x6f;
+/// Version byte of mainnet script hash addresses
+pub const MAINNET_SCRIPT_VERSION: u8 = 0x05;
+/// Version byte of testnet script hash addresses, the ones multisig accounts get
+pub const TESTNET_SCRIPT_VERSION: u8 = 0xc4;
+
+/// Version byte, hash and checksum
+const ENCODED_LEN: usize = 25;
+
+/// How an address is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// Base58 with a version byte and checksum, what wallets hand out
+    #[default]
+    Base58Check,
+    /// Witness version 0, bech32 checksum
+    Bech32,
+    /// Witness version 1, bech32m checksum, for taproot keys only
+    Bech32m,
+}
+
+impl Encoding {
+    fn witness_version(&self) -> Option<Fe32> {
+        match self {
+            Self::Base58Check => None,
+            Self::Bech32 => Some(segwit::VERSION_0),
+            Self::Bech32m => Some(segwit::VERSION_1),
+        }
+    }
+}
+
+/// What an address commits to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressKind {
+    /// The hash of a single public key
+    KeyHash,
+    /// The hash of the policy of a multisig account
+    ScriptHash,
+    /// A single x-only public key
+    Taproot,
+}
+
+/// How a public key is serialized before being hashed into an address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyFormat {
+    /// 33 bytes, the parity of y then x
+    #[default]
+    Compressed,
+    /// 65 bytes, 0x04 then x and y, as legacy addresses were derived
+    Uncompressed,
+}
+
+impl KeyFormat {
+    pub fn serialize(&self, public_key: &PublicKey) -> Vec<u8> {
+        match self {
+            Self::Compressed => public_key.serialize().to_vec(),
+            Self::Uncompressed => public_key.serialize_uncompressed().to_vec(),
+        }
+    }
+
+    /// Format of a serialized public key, told apart by its length
+    pub fn of_serialized(public_key: &[u8]) -> Option<Self> {
+        match public_key.len() {
+            secp256k1::constants::PUBLIC_KEY_SIZE => Some(Self::Compressed),
+            secp256k1::constants::UNCOMPRESSED_PUBLIC_KEY_SIZE => Some(Self::Uncompressed),
+            _ => None,
+        }
+    }
+}
+
+/// What an address pays to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Program {
+    /// RIPEMD-160 of the SHA-256 of a public key or multisig policy
+    Hash([u8; 20]),
+    /// BIP340 x-only public key of a taproot address
+    Key([u8; 32]),
+}
+
+impl Program {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Hash(hash) => hash,
+            Self::Key(key) => key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address {
+    version: u8,
+    program: Program,
+    encoding: Encoding,
+}
+
+impl Address {
+    /// Base58check address
+    pub const fn new(version: u8, hash: [u8; 20]) -> Self {
+        Self {
+            version,
+            program: Program::Hash(hash),
+            encoding: Encoding::Base58Check,
+        }
+    }
+
+    /// The same network and hash written in `encoding`. Script hashes only
+    /// have a base58check form, segwit programs of 20 bytes being key hashes,
+    /// and taproot keys only a bech32m one, which hashes never get
+    pub const fn with_encoding(self, encoding: Encoding) -> Self {
+        match (self.version, self.program, encoding) {
+            (MAINNET_SCRIPT_VERSION | TESTNET_SCRIPT_VERSION, _, _)
+            | (_, Program::Key(_), _)
+            | (_, _, Encoding::Bech32m) => self,
+            _ => Self { encoding, ..self },
+        }
+    }
+
+    /// Testnet address of `public_key` serialized in `format`
+    pub fn from_public_key(public_key: &PublicKey, format: KeyFormat) -> Self {
+        Self::new(TESTNET_VERSION, hash160(format.serialize(public_key)))
+    }
+
+    /// Testnet taproot address of `public_key`, its x-only form as program
+    pub fn taproot(public_key: &PublicKey) -> Self {
+        Self {
+            version: TESTNET_VERSION,
+            program: Program::Key(public_key.x_only_public_key().0.serialize()),
+            encoding: Encoding::Bech32m,
+        }
+    }
+
+    /// Whether this is the address of `public_key` serialized in `format`, in
+    /// whichever encoding. Segwit addresses only pay to compressed keys, while
+    /// taproot ones pay to the key however it is serialized
+    pub fn is_derived_from(&self, public_key: &PublicKey, format: KeyFormat) -> bool {
+        match self.program {
+            Program::Key(_) => Self::taproot(public_key) == *self,
+            Program::Hash(_) => {
+                (self.encoding == Encoding::Base58Check || format == KeyFormat::Compressed)
+                    && Self::from_public_key(public_key, format).with_encoding(self.encoding)
+                        == *self
+            }
+        }
+    }
+
+    /// Decodes a base58check or bech32(m) address, checking its length,
+    /// checksum and network without going to the network
+    pub fn parse(address: &str) -> Result<Self, AddressError> {
+        let lowercase = address.to_ascii_lowercase();
+        if lowercase.starts_with("bc1") || lowercase.starts_with("tb1") {
+            return Self::parse_segwit(address);
+        }
+
+        let decoded = bs58::decode(address)
+            .into_vec()
+            .map_err(|_| AddressError::InvalidBase58(address.to_string()))?;
+        if decoded.len() != ENCODED_LEN {
+            return Err(AddressError::InvalidLength(decoded.len()));
+        }
+
+        let (payload, checksum) = decoded.split_at(ENCODED_LEN - 4);
+        if Self::checksum(payload) != checksum {
+            return Err(AddressError::BadChecksum);
+        }
+
+        let version = payload[0];
+        if !matches!(
+            version,
+            MAINNET_VERSION | TESTNET_VERSION | MAINNET_SCRIPT_VERSION | TESTNET_SCRIPT_VERSION
+        ) {
+            return Err(AddressError::UnknownVersion(version));
+        }
+
+        Ok(Self::new(
+            version,
+            payload[1..].try_into().expect("Wrong length"),
+        ))
+    }
+
+    fn parse_segwit(address: &str) -> Result<Self, AddressError> {
+        let (prefix, witness_version, program) =
+            segwit::decode(address).map_err(|err| AddressError::InvalidBech32(err.to_string()))?;
+
+        let version = if prefix == hrp::BC {
+            MAINNET_VERSION
+        } else if prefix == hrp::TB {
+            TESTNET_VERSION
+        } else {
+            return Err(AddressError::UnknownPrefix(prefix.to_string()));
+        };
+        let (program, encoding) = match witness_version {
+            segwit::VERSION_0 => (Program::Hash(fixed_length(program)?), Encoding::Bech32),
+            segwit::VERSION_1 => (Program::Key(fixed_length(program)?), Encoding::Bech32m),
+            other => return Err(AddressError::UnknownWitnessVersion(other.to_u8())),
+        };
+
+        Ok(Self {
+            version,
+            program,
+            encoding,
+        })
+    }
+
+    /// Version byte of the network, also for segwit addresses
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn kind(&self) -> AddressKind {
+        match (self.version, self.program) {
+            (_, Program::Key(_)) => AddressKind::Taproot,
+            (MAINNET_SCRIPT_VERSION | TESTNET_SCRIPT_VERSION, _) => AddressKind::ScriptHash,
+            _ => AddressKind::KeyHash,
+        }
+    }
+
+    /// RIPEMD-160 of the SHA-256 of the public key or multisig policy, `None`
+    /// for taproot addresses, which hold the key itself
+    pub fn hash(&self) -> Option<&[u8; 20]> {
+        match &self.program {
+            Program::Hash(hash) => Some(hash),
+            Program::Key(_) => None,
+        }
+    }
+
+    fn checksum(payload: &[u8]) -> [u8; 4] {
+        let double_hashed = sha2::Sha256::digest(sha2::Sha256::digest(payload));
+        double_hashed[0..4].try_into().expect("Wrong length")
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(witness_version) = self.encoding.witness_version() {
+            let prefix: Hrp = if self.version == MAINNET_VERSION {
+                hrp::BC
+            } else {
+                hrp::TB
+            };
+            return segwit::encode_lower_to_fmt_unchecked(
+                f,
+                prefix,
+                witness_version,
+                self.program.as_bytes(),
+            );
+        }
+
+        let mut encoded = Vec::with_capacity(ENCODED_LEN);
+        encoded.push(self.version);
+        encoded.extend_from_slice(self.program.as_bytes());
+        let checksum = Self::checksum(&encoded);
+        encoded.extend_from_slice(&checksum);
+
+        write!(f, "{}", bs58::encode(encoded).into_string())
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        Self::parse(address)
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A witness program of the length its version requires
+fn fixed_length<const N: usize>(program: Vec<u8>) -> Result<[u8; N], AddressError> {
+    program
+        .try_into()
+        .map_err(|program: Vec<u8>| AddressError::InvalidLength(program.len()))
+}
+
+/// RIPEMD-160 of the SHA-256 of `data`, what addresses are made of
+pub(crate) fn hash160(data: impl AsRef<[u8]>) -> [u8; 20] {
+    ripemd::Ripemd160::digest(sha2::Sha256::digest(data)).into()
+}
+
+#[cfg(test)]
+impl Address {
+    /// Address standing for a named participant of a test
+    pub(crate) fn named(name: &str) -> Self {
+        let hash = sha2::Sha256::digest(name);
+        Self::new(
+            TESTNET_VERSION,
+            hash[..20].try_into().expect("Wrong length"),
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum AddressError {
+    #[error("{0} is not base58 encoded")]
+    InvalidBase58(String),
+    #[error("invalid bech32 address: {0}")]
+    InvalidBech32(String),
+    #[error("address decodes to {0} bytes, which is neither a key hash nor a taproot key")]
+    InvalidLength(usize),
+    #[error("address checksum does not match")]
+    BadChecksum,
+    #[error("unknown address version byte {0:#04x}")]
+    UnknownVersion(u8),
+    #[error("unknown address prefix {0}")]
+    UnknownPrefix(String),
+    #[error("unsupported witness version {0}")]
+    UnknownWitnessVersion(u8),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrips_through_base58check() {
+        let address = Address::new(TESTNET_VERSION, [7; 20]);
+        let encoded = address.to_string();
+
+        assert!(encoded.starts_with('m') || encoded.starts_with('n'));
+        assert_eq!(encoded.parse::<Address>().unwrap(), address);
+        assert_eq!(
+            serde_json::to_string(&address).unwrap(),
+            format!("\"{}\"", encoded)
+        );
+
+        let mut tampered = encoded.into_bytes();
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'2' { b'3' } else { b'2' };
+        assert_eq!(
+            String::from_utf8(tampered).unwrap().parse::<Address>(),
+            Err(AddressError::BadChecksum)
+        );
+        assert!(matches!(
+            "my_address".parse::<Address>(),
+            Err(AddressError::InvalidBase58(_))
+        ));
+    }
+
+    fn hash160(hex: &str) -> [u8; 20] {
+        hex::decode(hex).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn parses_known_addresses() {
+        let vectors = [
+            (
+                "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+                MAINNET_VERSION,
+                "62e907b15cbf27d5425399ebf6f0fb50ebb88f18",
+            ),
+            (
+                "1EHNa6Q4Jz2uvNExL497mE43ikXhwF6kZm",
+                MAINNET_VERSION,
+                "91b24bf9f5288532960ac687abb035127b1d28a5",
+            ),
+            (
+                "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn",
+                TESTNET_VERSION,
+                "243f1394f44554f4ce3fd68649c19adc483ce924",
+            ),
+        ];
+
+        for (encoded, version, hash) in vectors {
+            let address = Address::parse(encoded).unwrap();
+            assert_eq!(address, Address::new(version, hash160(hash)));
+            assert_eq!(address.to_string(), encoded);
+        }
+    }
+
+    #[test]
+    fn script_hashes_have_their_own_version() {
+        let mainnet = Address::parse("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy").unwrap();
+        assert_eq!(mainnet.version(), MAINNET_SCRIPT_VERSION);
+        assert_eq!(mainnet.kind(), AddressKind::ScriptHash);
+        assert_eq!(mainnet.to_string(), "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy");
+
+        let testnet = Address::new(TESTNET_SCRIPT_VERSION, [7; 20]);
+        assert!(testnet.to_string().starts_with('2'));
+        assert_eq!(testnet.to_string().parse::<Address>().unwrap(), testnet);
+        assert_ne!(testnet, Address::new(TESTNET_VERSION, [7; 20]));
+        assert_eq!(
+            Address::new(TESTNET_VERSION, [7; 20]).kind(),
+            AddressKind::KeyHash
+        );
+        assert_eq!(testnet.with_encoding(Encoding::Bech32), testnet);
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        assert_eq!(
+            Address::parse("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb"),
+            Err(AddressError::BadChecksum)
+        );
+        let litecoin = Address::new(0x30, [7; 20]).to_string();
+        assert_eq!(
+            Address::parse(&litecoin),
+            Err(AddressError::UnknownVersion(0x30))
+        );
+        assert_eq!(Address::parse("1111"), Err(AddressError::InvalidLength(4)));
+        assert_eq!(Address::parse(""), Err(AddressError::InvalidLength(0)));
+        assert!(matches!(
+            Address::parse("0OIl"),
+            Err(AddressError::InvalidBase58(_))
+        ));
+    }
+
+    #[test]
+    fn parses_segwit_addresses() {
+        let hash = hash160("751e76e8199196d454941c45d1b3a323f1433bd6");
+        let vectors = [
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                MAINNET_VERSION,
+            ),
+            (
+                "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+                TESTNET_VERSION,
+            ),
+        ];
+        for (encoded, version) in vectors {
+            let address = Address::parse(encoded).unwrap();
+            assert_eq!(
+                address,
+                Address::new(version, hash).with_encoding(Encoding::Bech32)
+            );
+            assert_eq!(address.to_string(), encoded);
+            assert_eq!(Address::parse(&encoded.to_uppercase()).unwrap(), address);
+        }
+        assert_ne!(
+            Address::parse(vectors[1].0).unwrap(),
+            Address::new(TESTNET_VERSION, hash)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_segwit_addresses() {
+        assert!(matches!(
+            Address::parse("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsy"),
+            Err(AddressError::InvalidBech32(_))
+        ));
+        assert!(matches!(
+            Address::parse("tb1qw508d6qejxtdg4y5r3zarvary0c5XW7kxpjzsx"),
+            Err(AddressError::InvalidBech32(_))
+        ));
+
+        let unknown_prefix = segwit::encode_v0(Hrp::parse("tb1x").unwrap(), &[1; 20]).unwrap();
+        assert_eq!(
+            Address::parse(&unknown_prefix),
+            Err(AddressError::UnknownPrefix(String::from("tb1x")))
+        );
+        let version_2 = segwit::encode(hrp::TB, Fe32::Z, &[1; 20]).unwrap();
+        assert_eq!(
+            Address::parse(&version_2),
+            Err(AddressError::UnknownWitnessVersion(2))
+        );
+        let taproot_script = segwit::encode_v1(hrp::TB, &[1; 40]).unwrap();
+        assert_eq!(
+            Address::parse(&taproot_script),
+            Err(AddressError::InvalidLength(40))
+        );
+        let script_hash = segwit::encode_v0(hrp::TB, &[1; 32]).unwrap();
+        assert_eq!(
+            Address::parse(&script_hash),
+            Err(AddressError::InvalidLength(32))
+        );
+    }
+
+    #[test]
+    fn taproot_addresses_hold_the_x_only_key() {
+        // Secret key 1, whose public key is the generator point
+        let mut secret = [0; 32];
+        secret[31] = 1;
+        let private_key = secp256k1::SecretKey::from_slice(&secret).unwrap();
+        let public_key = private_key.public_key(secp256k1::SECP256K1);
+
+        let address = Address::taproot(&public_key);
+        assert_eq!(address.kind(), AddressKind::Taproot);
+        assert_eq!(address.encoding(), Encoding::Bech32m);
+        assert_eq!(address.hash(), None);
+        assert_eq!(
+            address.to_string(),
+            segwit::encode_v1(hrp::TB, &public_key.x_only_public_key().0.serialize()).unwrap()
+        );
+        assert!(address.to_string().starts_with("tb1p"));
+        assert_eq!(address.to_string().parse::<Address>().unwrap(), address);
+        assert_eq!(address.with_encoding(Encoding::Base58Check), address);
+        assert!(address.is_derived_from(&public_key, KeyFormat::Uncompressed));
+
+        // Hashes never turn into keys
+        let key_hash = Address::from_public_key(&public_key, KeyFormat::Compressed);
+        assert_eq!(key_hash.with_encoding(Encoding::Bech32m), key_hash);
+        assert_ne!(key_hash.with_encoding(Encoding::Bech32), address);
+    }
+
+    /// Valid addresses of BIP350, with their version and program. Programs of
+    /// other lengths than the supported ones are valid but not understood here
+    #[test]
+    fn parses_bip350_addresses() {
+        let key = |version, hex: &str| Address {
+            version,
+            program: Program::Key(hex::decode(hex).unwrap().try_into().unwrap()),
+            encoding: Encoding::Bech32m,
+        };
+        let supported = [
+            (
+                "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+                Address::new(
+                    MAINNET_VERSION,
+                    hash160("751e76e8199196d454941c45d1b3a323f1433bd6"),
+                )
+                .with_encoding(Encoding::Bech32),
+            ),
+            (
+                "tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c",
+                key(
+                    TESTNET_VERSION,
+                    "000000c4a5cad46221b2a187905e5266362b99d5e91c6ce24d165dab93e86433",
+                ),
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                key(
+                    MAINNET_VERSION,
+                    "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                ),
+            ),
+        ];
+        for (encoded, expected) in supported {
+            let address = Address::parse(encoded).unwrap();
+            assert_eq!(address, expected);
+            assert_eq!(address.to_string(), encoded.to_lowercase());
+        }
+
+        let unsupported = [
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                AddressError::InvalidLength(32),
+            ),
+            (
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y",
+                AddressError::InvalidLength(40),
+            ),
+            ("BC1SW50QGDZ25J", AddressError::UnknownWitnessVersion(16)),
+            (
+                "bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs",
+                AddressError::UnknownWitnessVersion(2),
+            ),
+            (
+                "tb1qqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesrxh6hy",
+                AddressError::InvalidLength(32),
+            ),
+        ];
+        for (encoded, error) in unsupported {
+            assert_eq!(Address::parse(encoded), Err(error), "{}", encoded);
+        }
+    }
+
+    /// Invalid addresses of BIP350: wrong checksum variant for the witness
+    /// version, bad characters, program lengths and padding
+    #[test]
+    fn rejects_bip350_invalid_addresses() {
+        let invalid = [
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd",
+            "tb1z0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqglt7rf",
+            "BC1S0XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ54WELL",
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh",
+            "tb1q0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq24jc47",
+            "bc1p38j9r5y49hruaue7wxjce0updqjuyyx0kh56v8s25huc6995vvpql3jow4",
+            "BC130XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ7ZWS8R",
+            "bc1pw5dgrnzv",
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v8n0nx0muaewav253zgeav",
+            "BC1QR508D6QEJXTDG4Y5R3ZARVARYV98GJ9P",
+            "tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq47Zagq",
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v07qwwzcrf",
+            "tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vpggkg4j",
+        ];
+        for encoded in invalid {
+            assert!(
+                matches!(Address::parse(encoded), Err(AddressError::InvalidBech32(_))),
+                "{}",
+                encoded
+            );
+        }
+        // Another network's prefix isn't even taken for bech32
+        assert!(
+            Address::parse("tc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq5zuyut")
+                .is_err()
+        );
+    }
+}
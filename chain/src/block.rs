@@ -1,18 +1,37 @@
+use crate::hash::Hash256;
 use crate::transaction::Transaction;
-use ripemd::digest::generic_array::GenericArray;
+use rayon::prelude::*;
 
 use serde::ser::SerializeStruct;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Serialize, Serializer};
 use sha2::Digest;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
+/// Value of the one reward transaction a block may hold
+pub const MINING_REWARD: f32 = 10.0;
+
+/// Largest serialized block accepted
+pub const MAX_BLOCK_SIZE: usize = 1_000_000;
+
+/// Size of the header a block hash is computed over, see `BlockHeader::to_bytes`
+pub const HEADER_SIZE: usize = 80;
+
+/// SHA-256 of a block header
+pub type BlockHash = Hash256;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "BlockData")]
 pub struct Block {
     timestamp: i64,
     nonce: i64,
-    previous_hash: String,
+    previous_hash: BlockHash,
+    /// Root of the merkle tree of the transaction ids
+    merkle_root: Hash256,
     transactions: Vec<Arc<Transaction>>,
+    /// Computed on first use, reset whenever the header changes
+    hash: OnceLock<BlockHash>,
 }
 
 impl Serialize for Block {
@@ -20,10 +39,11 @@ impl Serialize for Block {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("Block", 4)?;
+        let mut state = serializer.serialize_struct("Block", 5)?;
         state.serialize_field("timestamp", &self.timestamp)?;
         state.serialize_field("nonce", &self.nonce)?;
         state.serialize_field("previous_hash", &self.previous_hash)?;
+        state.serialize_field("merkle_root", &self.merkle_root)?;
 
         // Serialize each transaction by dereferencing the Arc.
         // This will serialize the data pointed to by the Arc, not the Arc itself.
@@ -36,17 +56,166 @@ impl Serialize for Block {
     }
 }
 
+/// What is kept of a block once its body has been pruned
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlockHeader {
+    timestamp: i64,
+    nonce: i64,
+    previous_hash: BlockHash,
+    merkle_root: Hash256,
+    transaction_count: usize,
+}
+
+impl BlockHeader {
+    /// Fixed-size form the block hash is computed over: the previous hash and the
+    /// merkle root, followed by the timestamp and the nonce in little endian
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[..32].copy_from_slice(self.previous_hash.as_bytes());
+        bytes[32..64].copy_from_slice(self.merkle_root.as_bytes());
+        bytes[64..72].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[72..].copy_from_slice(&self.nonce.to_le_bytes());
+        bytes
+    }
+
+    /// Hasher for this header with any nonce
+    pub fn hasher(&self) -> HeaderHasher {
+        let mut midstate = sha2::Sha256::new();
+        midstate.update(self.previous_hash.as_bytes());
+        midstate.update(self.merkle_root.as_bytes());
+        HeaderHasher {
+            midstate,
+            timestamp: self.timestamp,
+        }
+    }
+
+    /// Hash of the header, which is the hash of its block
+    pub fn hash(&self) -> BlockHash {
+        Hash256::digest(self.to_bytes())
+    }
+
+    pub fn merkle_root(&self) -> Hash256 {
+        self.merkle_root
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn nonce(&self) -> i64 {
+        self.nonce
+    }
+
+    pub fn previous_hash(&self) -> BlockHash {
+        self.previous_hash
+    }
+
+    pub fn transaction_count(&self) -> usize {
+        self.transaction_count
+    }
+}
+
+/// SHA-256 state after the first 64 bytes of a header, the previous hash and the
+/// merkle root. They don't change while searching for a nonce, so each attempt
+/// only hashes the last 16 bytes instead of the whole block.
+#[derive(Debug, Clone)]
+pub struct HeaderHasher {
+    midstate: sha2::Sha256,
+    timestamp: i64,
+}
+
+impl HeaderHasher {
+    pub fn set_timestamp(&mut self, timestamp: i64) {
+        self.timestamp = timestamp;
+    }
+
+    pub fn hash(&self, nonce: i64) -> [u8; 32] {
+        let mut hasher = self.midstate.clone();
+        hasher.update(self.timestamp.to_le_bytes());
+        hasher.update(nonce.to_le_bytes());
+        hasher.finalize().into()
+    }
+}
+
+/// Whether `hash` starts with `difficulty` zero hex digits
+pub fn meets_difficulty(hash: &[u8], difficulty: usize) -> bool {
+    let (bytes, nibble) = (difficulty / 2, difficulty % 2);
+    if hash.len() * 2 < difficulty {
+        return false;
+    }
+    hash[..bytes].iter().all(|byte| *byte == 0) && (nibble == 0 || hash[bytes] < 0x10)
+}
+
+/// Root of the merkle tree over the transaction ids, the leaves being the
+/// SHA-256 of the 32 id bytes and each level hashing pairs of nodes, pairing
+/// the last node with itself when odd. A block without transactions has the
+/// all-zero root.
+pub fn merkle_root(transactions: &[Arc<Transaction>]) -> Hash256 {
+    let mut level: Vec<[u8; 32]> = transactions
+        .iter()
+        .map(|transaction| sha2::Sha256::digest(transaction.id().as_bytes()).into())
+        .collect();
+    if level.is_empty() {
+        return Hash256::default();
+    }
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut hasher = sha2::Sha256::new();
+                hasher.update(pair[0]);
+                hasher.update(pair.get(1).unwrap_or(&pair[0]));
+                hasher.finalize().into()
+            })
+            .collect();
+    }
+    Hash256::from(level[0])
+}
+
+/// Owned mirror of `Block` used for deserialization, since the transactions
+/// are shared behind `Arc`s once they are part of a block.
+#[derive(Deserialize)]
+struct BlockData {
+    timestamp: i64,
+    nonce: i64,
+    previous_hash: BlockHash,
+    /// Computed from the transactions when missing, checked by `Block::check` otherwise
+    #[serde(default)]
+    merkle_root: Option<Hash256>,
+    transactions: Vec<Transaction>,
+}
+
+impl From<BlockData> for Block {
+    fn from(data: BlockData) -> Self {
+        let transactions: Vec<Arc<Transaction>> =
+            data.transactions.into_iter().map(Arc::new).collect();
+        Self {
+            timestamp: data.timestamp,
+            nonce: data.nonce,
+            previous_hash: data.previous_hash,
+            merkle_root: data
+                .merkle_root
+                .unwrap_or_else(|| merkle_root(&transactions)),
+            transactions,
+            hash: OnceLock::new(),
+        }
+    }
+}
+
 impl Block {
     pub fn genesis() -> Self {
         let timestamp = 1706493690000;
         let nonce = 0;
-        let previous_hash = String::from("");
+        let previous_hash = BlockHash::default();
 
         Self {
             timestamp,
             nonce,
             previous_hash,
+            merkle_root: merkle_root(&[]),
             transactions: vec![],
+            hash: OnceLock::new(),
         }
     }
 
@@ -60,57 +229,154 @@ impl Block {
 
     pub fn increment_nonce(&mut self) {
         self.nonce += 1;
+        self.hash.take();
     }
 
-    /// testing purposes
-    pub fn previous_hash(&self) -> String {
-        self.previous_hash.clone()
+    pub fn set_nonce(&mut self, nonce: i64) {
+        self.nonce = nonce;
+        self.hash.take();
     }
 
-    pub fn hash_raw(&self) -> Result<GenericArray<u8, typenum::U32>, BlockError> {
-        let block_json = serde_json::to_string(&self)?;
-        Ok(sha2::Sha256::digest(block_json.as_bytes()))
+    /// Moves the timestamp to now, or a millisecond forward if the clock hasn't
+    /// moved, giving the block a fresh nonce space
+    pub fn roll_timestamp(&mut self) {
+        self.timestamp = Self::generate_timestamp().max(self.timestamp + 1);
+        self.hash.take();
     }
 
-    pub fn hash(&self) -> Result<String, BlockError> {
+    pub fn previous_hash(&self) -> BlockHash {
+        self.previous_hash
+    }
+
+    pub fn merkle_root(&self) -> Hash256 {
+        self.merkle_root
+    }
+
+    /// SHA-256 of the header, see `BlockHeader::to_bytes`. Only computed once
+    /// until the nonce or the timestamp change.
+    pub fn hash(&self) -> BlockHash {
+        *self.hash.get_or_init(|| self.header().hash())
+    }
+
+    /// Checks everything that doesn't depend on the chain the block extends
+    pub fn check(&self, difficulty: usize) -> Result<(), BlockError> {
         let block_json = serde_json::to_string(&self)?;
-        // let hash = sha2::Sha256::digest(block_json.as_bytes());
-        // hex::encode(hash)
-        Ok(format!(
-            "{:02x}",
-            sha2::Sha256::digest(block_json.as_bytes())
-        ))
+        if block_json.len() > MAX_BLOCK_SIZE {
+            return Err(BlockError::OversizeBlock {
+                size: block_json.len(),
+                max: MAX_BLOCK_SIZE,
+            });
+        }
+
+        let now = Self::generate_timestamp();
+        if self.timestamp > now {
+            return Err(BlockError::TimestampTooNew {
+                timestamp: self.timestamp,
+                now,
+            });
+        }
+
+        let mut txids = HashSet::new();
+        for transaction in self.transactions.iter() {
+            let txid = transaction.id();
+            if !txids.insert(txid) {
+                return Err(BlockError::DuplicateTransaction { txid });
+            }
+        }
+
+        let expected = merkle_root(&self.transactions);
+        if self.merkle_root != expected {
+            return Err(BlockError::BadMerkleRoot {
+                expected,
+                found: self.merkle_root,
+            });
+        }
+
+        self.check_proof_of_work(difficulty)?;
+        self.check_reward()?;
+        self.check_signatures()
+    }
+
+    /// At most one reward transaction, of exactly `MINING_REWARD`
+    fn check_reward(&self) -> Result<(), BlockError> {
+        let mut rewards = self.transactions.iter().filter(|tx| tx.is_reward());
+        if let Some(reward) = rewards.next() {
+            if reward.value() != MINING_REWARD {
+                return Err(BlockError::InvalidReward {
+                    txid: reward.id(),
+                    value: reward.value(),
+                });
+            }
+        }
+        match rewards.next() {
+            Some(extra) => Err(BlockError::ExtraReward { txid: extra.id() }),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `check` and records that it passed, so the block can be connected
+    /// without checking it again, typically outside of the chain lock
+    pub fn into_checked(self, difficulty: usize) -> Result<CheckedBlock, BlockError> {
+        self.check(difficulty)?;
+        Ok(CheckedBlock {
+            block: self,
+            difficulty,
+        })
+    }
+
+    /// Verifies on rayon's thread pool that every transaction but the reward
+    /// is signed by its sender.
+    ///
+    /// ECDSA and Schnorr signatures are both accepted. libsecp256k1 has no
+    /// batch verification for either, so every signature is verified on its
+    /// own and only the work is spread across threads.
+    fn check_signatures(&self) -> Result<(), BlockError> {
+        match self
+            .transactions
+            .par_iter()
+            .find_any(|transaction| !transaction.is_reward() && !transaction.has_valid_signature())
+        {
+            Some(transaction) => Err(BlockError::BadSignature {
+                txid: transaction.id(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn check_proof_of_work(&self, difficulty: usize) -> Result<(), BlockError> {
+        let hash = self.hash();
+        if meets_difficulty(hash.as_bytes(), difficulty) {
+            return Ok(());
+        }
+        Err(BlockError::InvalidProofOfWork { hash, difficulty })
     }
 
     pub fn check_timestamp(timestamp: i64) -> bool {
         let now = Self::generate_timestamp();
 
-        return timestamp <= now;
+        timestamp <= now
     }
     pub fn create_from(
         transactions: Vec<Arc<Transaction>>,
         nonce: i64,
-        previous_hash: String,
+        previous_hash: BlockHash,
     ) -> Self {
         let timestamp = Self::generate_timestamp();
-        Self {
-            timestamp,
-            nonce,
-            previous_hash,
-            transactions,
-        }
+        Self::new(timestamp, transactions, nonce, previous_hash)
     }
     pub fn new(
         timestamp: i64,
         transactions: Vec<Arc<Transaction>>,
         nonce: i64,
-        previous_hash: String,
+        previous_hash: BlockHash,
     ) -> Self {
         Self {
             timestamp,
             nonce,
             previous_hash,
+            merkle_root: merkle_root(&transactions),
             transactions,
+            hash: OnceLock::new(),
         }
     }
 
@@ -118,13 +384,23 @@ impl Block {
         self.transactions.clone()
     }
 
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            timestamp: self.timestamp,
+            nonce: self.nonce,
+            previous_hash: self.previous_hash,
+            merkle_root: self.merkle_root,
+            transaction_count: self.transactions.len(),
+        }
+    }
+
     pub fn generate_timestamp() -> i64 {
         let start = SystemTime::now();
         let since_the_epoch = start
             .duration_since(UNIX_EPOCH)
             .expect("Time went backwards");
 
-        return since_the_epoch.as_millis() as i64;
+        since_the_epoch.as_millis() as i64
     }
 }
 
@@ -135,46 +411,307 @@ impl std::fmt::Display for Block {
     }
 }
 
+/// A block that passed `Block::check` against `difficulty`
+#[derive(Debug, Clone)]
+pub struct CheckedBlock {
+    block: Block,
+    difficulty: usize,
+}
+
+impl CheckedBlock {
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+
+    pub fn difficulty(&self) -> usize {
+        self.difficulty
+    }
+
+    pub fn into_inner(self) -> Block {
+        self.block
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::block::Block;
+    use crate::address::{Address, TESTNET_VERSION};
+    use crate::block::{
+        meets_difficulty, merkle_root, Block, BlockError, BlockHash, HEADER_SIZE, MINING_REWARD,
+    };
+    use crate::hash::Hash256;
+    use crate::transaction::{SignatureType, Transaction};
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+
+    fn address(byte: u8) -> Address {
+        Address::new(TESTNET_VERSION, [byte; 20])
+    }
 
     #[test]
     fn test_block_hash() {
         let block = Block::genesis();
-        let hash = block.hash_raw().unwrap();
-        assert_eq!(hash.len(), 32);
+        let hash = block.hash();
+        assert_eq!(hash.as_bytes().len(), 32);
     }
 
     #[test]
     fn test_raw_hash_converted_is_same() {
         let block = Block::genesis();
         let hash_raw: String = block
-            .hash_raw()
+            .hash()
+            .as_bytes()
             .iter()
             .map(|b| format!("{:02x}", b))
             .collect::<String>();
 
-        let hash = block.hash().unwrap();
+        let hash = block.hash().to_string();
         assert_eq!(hash, hash_raw);
     }
+
+    #[test]
+    fn check_rejects_duplicate_transactions() {
+        let transaction = Arc::new(Transaction::new(address(1), address(2), 1.0));
+        let block = Block::create_from(
+            vec![transaction.clone(), transaction.clone()],
+            0,
+            BlockHash::default(),
+        );
+
+        assert!(matches!(
+            block.check(0),
+            Err(BlockError::DuplicateTransaction { txid }) if txid == transaction.id()
+        ));
+    }
+
+    #[test]
+    fn check_verifies_signatures() {
+        let wallet = Wallet::generate_new();
+        let mut signed = Transaction::new(wallet.address(), address(2), 1.0);
+        wallet.sign_transaction(&mut signed);
+        let reward = Transaction::reward(address(1), MINING_REWARD, 1);
+
+        let block = Block::create_from(
+            vec![Arc::new(signed.clone()), Arc::new(reward)],
+            0,
+            BlockHash::default(),
+        );
+        let checked = block.clone().into_checked(0).unwrap();
+        assert_eq!(checked.difficulty(), 0);
+        assert_eq!(checked.block().hash(), block.hash());
+
+        // Only the reward goes without a signature
+        let unsigned = Transaction::new(address(1), address(2), 1.0);
+        let block = Block::create_from(
+            vec![Arc::new(signed.clone()), Arc::new(unsigned.clone())],
+            0,
+            BlockHash::default(),
+        );
+        assert!(matches!(
+            block.check(0),
+            Err(BlockError::BadSignature { txid }) if txid == unsigned.id()
+        ));
+
+        let mut json = serde_json::to_value(&signed).unwrap();
+        json["value"] = serde_json::json!(1000.0);
+        let forged: Transaction = serde_json::from_value(json).unwrap();
+        let block = Block::create_from(vec![Arc::new(forged.clone())], 0, BlockHash::default());
+
+        assert!(matches!(
+            block.into_checked(0),
+            Err(BlockError::BadSignature { txid }) if txid == forged.id()
+        ));
+    }
+
+    #[test]
+    fn check_allows_one_reward_of_the_mining_reward() {
+        let reward = Transaction::reward(address(1), MINING_REWARD, 1);
+        let block = Block::create_from(vec![Arc::new(reward.clone())], 0, BlockHash::default());
+        assert!(block.check(0).is_ok());
+
+        let inflated = Transaction::reward(address(1), 1000.0, 1);
+        let block = Block::create_from(vec![Arc::new(inflated.clone())], 0, BlockHash::default());
+        assert!(matches!(
+            block.check(0),
+            Err(BlockError::InvalidReward { txid, .. }) if txid == inflated.id()
+        ));
+
+        let second = Transaction::reward(address(2), MINING_REWARD, 1);
+        let block = Block::create_from(
+            vec![Arc::new(reward), Arc::new(second.clone())],
+            0,
+            BlockHash::default(),
+        );
+        assert!(matches!(
+            block.check(0),
+            Err(BlockError::ExtraReward { txid }) if txid == second.id()
+        ));
+    }
+
+    #[test]
+    fn check_accepts_both_signature_schemes() {
+        let wallet = Wallet::generate_new();
+        let mut ecdsa = Transaction::new(wallet.address(), address(2), 1.0);
+        wallet.sign_transaction(&mut ecdsa);
+        let mut schnorr = Transaction::new(wallet.address(), address(3), 1.0);
+        wallet.sign_transaction_with(&mut schnorr, SignatureType::Schnorr);
+
+        let block = Block::create_from(
+            vec![Arc::new(ecdsa), Arc::new(schnorr.clone())],
+            0,
+            BlockHash::default(),
+        );
+        assert!(block.check(0).is_ok());
+
+        let mut json = serde_json::to_value(&schnorr).unwrap();
+        json["recipient_address"] = serde_json::json!(address(1));
+        let forged: Transaction = serde_json::from_value(json).unwrap();
+        let block = Block::create_from(vec![Arc::new(forged.clone())], 0, BlockHash::default());
+        assert!(matches!(
+            block.check(0),
+            Err(BlockError::BadSignature { txid }) if txid == forged.id()
+        ));
+
+        // Marking a transaction Schnorr does not exempt it from a signature
+        let mut json =
+            serde_json::to_value(Transaction::new(wallet.address(), address(2), 1.0)).unwrap();
+        json["signature_type"] = serde_json::json!("schnorr");
+        let unsigned: Transaction = serde_json::from_value(json).unwrap();
+        let block = Block::create_from(vec![Arc::new(unsigned.clone())], 0, BlockHash::default());
+        assert!(matches!(
+            block.check(0),
+            Err(BlockError::BadSignature { txid }) if txid == unsigned.id()
+        ));
+    }
+
+    #[test]
+    fn midstate_hash_matches_the_header_hash() {
+        let transactions = vec![
+            Arc::new(Transaction::new(address(1), address(2), 1.0)),
+            Arc::new(Transaction::new(address(2), address(3), 2.0)),
+            Arc::new(Transaction::new(address(3), address(1), 3.0)),
+        ];
+        let mut block = Block::create_from(transactions, 0, Block::genesis().hash());
+        let hasher = block.header().hasher();
+
+        for nonce in 0..16 {
+            block.set_nonce(nonce);
+            assert_eq!(BlockHash::from(hasher.hash(nonce)), block.hash());
+            assert_eq!(block.header().to_bytes().len(), HEADER_SIZE);
+        }
+    }
+
+    #[test]
+    fn cached_hash_follows_the_header() {
+        let mut block = Block::create_from(vec![], 0, Block::genesis().hash());
+        let hash = block.hash();
+        assert_eq!(block.clone().hash(), hash);
+
+        block.increment_nonce();
+        let incremented = block.hash();
+        assert_ne!(incremented, hash);
+        assert_eq!(incremented, block.header().hash());
+
+        block.set_nonce(0);
+        assert_eq!(block.hash(), hash);
+        block.roll_timestamp();
+        assert_ne!(block.hash(), hash);
+    }
+
+    #[test]
+    fn difficulty_counts_leading_zero_digits() {
+        let mut hash = [0u8; 32];
+        hash[2] = 0x0f;
+        assert!(meets_difficulty(&hash, 5));
+        assert!(!meets_difficulty(&hash, 6));
+        assert!(meets_difficulty(&hash, 0));
+        assert!(!meets_difficulty(&[0; 2], 5));
+    }
+
+    #[test]
+    fn merkle_root_commits_to_the_transactions() {
+        let transaction = Arc::new(Transaction::new(address(1), address(2), 1.0));
+        let block = Block::create_from(vec![transaction.clone()], 0, BlockHash::default());
+
+        let mut json: serde_json::Value = serde_json::to_value(&block).unwrap();
+        json["transactions"][0]["value"] = serde_json::json!(1000.0);
+        let tampered: Block = serde_json::from_value(json).unwrap();
+
+        assert!(matches!(
+            tampered.check(0),
+            Err(BlockError::BadMerkleRoot { found, .. }) if found == block.merkle_root()
+        ));
+        assert!(!merkle_root(std::slice::from_ref(&transaction)).is_zero());
+        assert_ne!(
+            merkle_root(&[transaction.clone(), transaction.clone()]),
+            merkle_root(&[transaction])
+        );
+    }
+
+    #[test]
+    fn merkle_leaves_hash_the_id_bytes() {
+        use sha2::Digest;
+
+        let transactions: Vec<Arc<Transaction>> = (1..=3)
+            .map(|value| Arc::new(Transaction::new(address(1), address(2), value as f32)))
+            .collect();
+        let sha256 = |parts: &[&[u8]]| -> [u8; 32] {
+            let mut hasher = sha2::Sha256::new();
+            for part in parts {
+                hasher.update(part);
+            }
+            hasher.finalize().into()
+        };
+        let leaves: Vec<[u8; 32]> = transactions
+            .iter()
+            .map(|transaction| sha256(&[transaction.id().as_bytes()]))
+            .collect();
+        let left = sha256(&[&leaves[0], &leaves[1]]);
+        let right = sha256(&[&leaves[2], &leaves[2]]);
+
+        assert_eq!(
+            merkle_root(&transactions),
+            Hash256::from(sha256(&[&left, &right]))
+        );
+        assert_eq!(merkle_root(&transactions[..1]), Hash256::from(leaves[0]));
+    }
 }
 
+/// Why a block is invalid on its own, regardless of the chain it extends
 #[derive(thiserror::Error, Debug)]
 pub enum BlockError {
-    SerializeError(serde_json::Error),
+    #[error("serialization error: {0}")]
+    SerializeError(#[from] serde_json::Error),
+    #[error("block hash {hash} does not meet difficulty {difficulty}")]
+    InvalidProofOfWork { hash: BlockHash, difficulty: usize },
+    #[error("block timestamp {timestamp} is in the future, now is {now}")]
+    TimestampTooNew { timestamp: i64, now: i64 },
+    #[error("transaction {txid} appears more than once in the block")]
+    DuplicateTransaction { txid: Hash256 },
+    #[error("merkle root is {found} but the transactions hash to {expected}")]
+    BadMerkleRoot { expected: Hash256, found: Hash256 },
+    #[error("block is {size} bytes, more than the {max} allowed")]
+    OversizeBlock { size: usize, max: usize },
+    #[error("transaction {txid} has an invalid signature")]
+    BadSignature { txid: Hash256 },
+    #[error("reward transaction {txid} pays {value} instead of {}", MINING_REWARD)]
+    InvalidReward { txid: Hash256, value: f32 },
+    #[error("transaction {txid} pays a second reward in the block")]
+    ExtraReward { txid: Hash256 },
 }
 
-impl std::fmt::Display for BlockError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl BlockError {
+    /// Stable, snake_case name of the failure, for clients to match on
+    pub fn kind(&self) -> &'static str {
         match self {
-            BlockError::SerializeError(e) => write!(f, "{}", e.to_string()),
+            BlockError::SerializeError(_) => "serialize_error",
+            BlockError::InvalidProofOfWork { .. } => "invalid_proof_of_work",
+            BlockError::TimestampTooNew { .. } => "timestamp_too_new",
+            BlockError::DuplicateTransaction { .. } => "duplicate_transaction",
+            BlockError::BadMerkleRoot { .. } => "bad_merkle_root",
+            BlockError::OversizeBlock { .. } => "oversize_block",
+            BlockError::BadSignature { .. } => "bad_signature",
+            BlockError::InvalidReward { .. } => "invalid_reward",
+            BlockError::ExtraReward { .. } => "extra_reward",
         }
     }
 }
-
-impl From<serde_json::Error> for BlockError {
-    fn from(e: serde_json::Error) -> Self {
-        BlockError::SerializeError(e)
-    }
-}
@@ -1,34 +1,446 @@
-use crate::block::{Block, BlockError};
+use crate::address::Address;
+use crate::block::{Block, BlockError, BlockHash, BlockHeader, CheckedBlock, MINING_REWARD};
+use crate::events::{ChainEvent, EventBus};
+use crate::hash::Hash256;
+use crate::miner;
+use crate::snapshot::{
+    ChainSnapshot, CheckedHistoricalBlock, HistoricalContext, SnapshotError, SnapshotStatus,
+    SnapshotValidator,
+};
+use crate::state::{BlockDelta, ChainState, HistoryEntry, Spending, StateCheckpoint};
+use crate::store::{ChainStore, MemoryStore, StoreError};
 use crate::transaction::Transaction;
-use ripemd::digest::generic_array::GenericArray;
 
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 
-const MINING_REWARD: f32 = 10.0;
+/// Metadata key of the lowest height whose block body is still stored
+const PRUNED_HEIGHT_KEY: &str = "pruned_height";
+/// Metadata key of the snapshot the chain started from
+const SNAPSHOT_KEY: &str = "snapshot";
+/// Blocks between two checkpoints of the whole chain state, only block deltas
+/// are written in between
+const STATE_CHECKPOINT_INTERVAL: u64 = 1000;
+
+/// Expected number of hashes to find a block, each leading zero being a hex digit
+pub fn block_work(difficulty: usize) -> u128 {
+    16u128.pow(difficulty as u32)
+}
+
+/// The tip a block is validated against, copied out of the chain so that checking
+/// the block doesn't require holding the chain lock
+#[derive(Debug, Clone)]
+pub struct BlockContext {
+    previous_hash: BlockHash,
+    previous_header: BlockHeader,
+    difficulty: usize,
+}
+
+impl BlockContext {
+    /// Checks that `block` extends the tip, then runs the context-free checks,
+    /// signatures included
+    pub fn check(&self, block: Block) -> Result<CheckedBlock, ChainError> {
+        Blockchain::verify_extends(&block, &self.previous_hash, &self.previous_header)?;
+        Ok(block.into_checked(self.difficulty)?)
+    }
+}
+
+/// Persisted record of the snapshot a chain started from
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotRecord {
+    height: u64,
+    commitment: Hash256,
+    status: SnapshotStatus,
+}
 
 /// There should be only one blockchain instance per node
 #[derive(Debug)]
 pub struct Blockchain {
-    address: String,
-    chain: Vec<Block>,
+    address: Address,
+    store: Box<dyn ChainStore>,
+    state: ChainState,
     mempool: Vec<Arc<Transaction>>,
     difficulty: usize,
+    /// Number of most recent block bodies to keep, `None` keeps everything
+    prune_depth: Option<u64>,
+    /// Bodies of the blocks below this height have been deleted
+    pruned_height: u64,
+    cumulative_work: u128,
+    /// Hash of the last block of the active chain, mirroring the store
+    tip: BlockHash,
+    /// Height of every block of the active chain, by hash
+    heights_by_hash: HashMap<BlockHash, u64>,
+    /// Height of the last checkpoint of the chain state
+    checkpoint_height: u64,
+    /// Set when the node was started from a snapshot
+    snapshot_status: Option<SnapshotStatus>,
+    /// Checks historical blocks until the snapshot is validated or found invalid
+    snapshot_validator: Option<SnapshotValidator>,
+    events: EventBus,
+    /// Threads searching for nonces when mining
+    mining_threads: usize,
 }
 
 impl Blockchain {
-    pub fn new(address: String, difficulty: usize) -> Self {
-        let mut chain = Vec::new();
+    /// Chain kept entirely in memory
+    pub fn new(address: Address, difficulty: usize) -> Self {
+        Self::with_store(address, difficulty, Box::new(MemoryStore::new()))
+            .expect("Failed to initialize in-memory store")
+    }
+
+    /// Opens a chain on top of `store`, writing the genesis block if the store is empty.
+    pub fn with_store(
+        address: Address,
+        difficulty: usize,
+        mut store: Box<dyn ChainStore>,
+    ) -> Result<Self, ChainError> {
         let genesis_block = Block::genesis();
-        chain.push(genesis_block);
-        let mempool = vec![];
-        let difficulty = difficulty;
+        let genesis_hash = genesis_block.hash();
+
+        match store.hash_at_height(0)? {
+            Some(hash) if hash != genesis_hash => {
+                return Err(ValidationError::GenesisMismatch.into());
+            }
+            Some(_) => {}
+            None => {
+                store.put_block(&genesis_hash, &genesis_block)?;
+                store.put_header(&genesis_hash, &genesis_block.header())?;
+                store.set_hash_at_height(0, &genesis_hash)?;
+                store.set_tip(&genesis_hash)?;
+            }
+        }
 
-        Self {
+        let pruned_height = match store.get_meta(PRUNED_HEIGHT_KEY)? {
+            Some(height) => height.parse().map_err(|_| {
+                ChainError::RetrieveBlockError(format!("Invalid pruned height {}", height))
+            })?,
+            None => 0,
+        };
+
+        let mut blockchain = Self {
             address,
-            chain,
-            mempool,
+            store,
+            state: ChainState::new(),
+            mempool: vec![],
             difficulty,
+            prune_depth: None,
+            pruned_height,
+            cumulative_work: 0,
+            tip: BlockHash::default(),
+            heights_by_hash: HashMap::new(),
+            checkpoint_height: 0,
+            snapshot_status: None,
+            snapshot_validator: None,
+            events: EventBus::new(),
+            mining_threads: std::thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1),
+        };
+
+        for height in 0..=blockchain.height()? {
+            let hash = blockchain.hash_at_height(height)?;
+            blockchain.heights_by_hash.insert(hash, height);
+        }
+        blockchain.tip = blockchain
+            .store
+            .tip()?
+            .ok_or(ChainError::RetrieveBlockError(
+                "No blocks in the chain".into(),
+            ))?;
+
+        blockchain.load_state()?;
+        if let Some(record) = blockchain.store.get_meta(SNAPSHOT_KEY)? {
+            blockchain.load_snapshot(serde_json::from_str(&record)?)?;
+        }
+
+        Ok(blockchain)
+    }
+
+    /// Starts a chain from a snapshot instead of replaying every block since genesis.
+    ///
+    /// `store` must be empty. Only the header of the snapshot tip is known, every
+    /// block up to the tip is treated as pruned. The snapshot stays unverified until
+    /// historical blocks are checked against it, see `add_historical_block`.
+    pub fn from_snapshot(
+        address: Address,
+        difficulty: usize,
+        mut store: Box<dyn ChainStore>,
+        snapshot: &ChainSnapshot,
+    ) -> Result<Self, ChainError> {
+        if store.block_count()? != 0 {
+            return Err(ValidationError::StoreNotEmpty.into());
+        }
+
+        let genesis_block = Block::genesis();
+        let genesis_hash = genesis_block.hash();
+        if snapshot.block_hashes().first() != Some(&genesis_hash) {
+            return Err(ValidationError::GenesisMismatch.into());
+        }
+
+        store.put_block(&genesis_hash, &genesis_block)?;
+        store.put_header(&genesis_hash, &genesis_block.header())?;
+        for (height, hash) in (0u64..).zip(snapshot.block_hashes()) {
+            store.set_hash_at_height(height, hash)?;
+        }
+        store.put_header(&snapshot.tip_hash(), snapshot.tip_header())?;
+        store.set_tip(&snapshot.tip_hash())?;
+
+        let pruned_height = snapshot.height() + 1;
+        store.put_checkpoint(&StateCheckpoint {
+            height: snapshot.height(),
+            hash: snapshot.tip_hash(),
+            cumulative_work: snapshot.cumulative_work(),
+            state: snapshot.state().clone(),
+        })?;
+        store.put_meta(PRUNED_HEIGHT_KEY, &pruned_height.to_string())?;
+        let record = SnapshotRecord {
+            height: snapshot.height(),
+            commitment: snapshot
+                .commitment()
+                .expect("Snapshot serialization cannot fail"),
+            status: SnapshotStatus::Unverified,
+        };
+        store.put_meta(SNAPSHOT_KEY, &serde_json::to_string(&record)?)?;
+
+        Self::with_store(address, difficulty, store)
+    }
+
+    /// Captures the current state, tip and block hashes of the chain
+    pub fn snapshot(&self) -> Result<ChainSnapshot, ChainError> {
+        let block_hashes = (0..=self.height()?)
+            .map(|height| self.hash_at_height(height))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ChainSnapshot::new(
+            block_hashes,
+            self.header_by_hash(&self.tip)?,
+            self.cumulative_work,
+            self.state.clone(),
+        ))
+    }
+
+    pub fn cumulative_work(&self) -> u128 {
+        self.cumulative_work
+    }
+
+    /// `None` unless the chain was started from a snapshot
+    pub fn snapshot_status(&self) -> Option<SnapshotStatus> {
+        self.snapshot_status.clone()
+    }
+
+    /// Resumes checking the snapshot the chain started from. Progress is not
+    /// persisted, historical blocks are checked from genesis again
+    fn load_snapshot(&mut self, record: SnapshotRecord) -> Result<(), ChainError> {
+        let status = match record.status {
+            SnapshotStatus::Validating { .. } => SnapshotStatus::Unverified,
+            status => status,
+        };
+        if status == SnapshotStatus::Unverified {
+            let block_hashes = (0..=record.height)
+                .map(|height| self.hash_at_height(height))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.snapshot_validator = Some(SnapshotValidator::resume(
+                block_hashes,
+                record.commitment,
+                self.difficulty,
+            ));
+        }
+        self.snapshot_status = Some(status);
+        Ok(())
+    }
+
+    /// What the next historical block is checked against, so that it can be
+    /// checked without holding the chain lock, see `HistoricalContext::check`
+    pub fn historical_block_context(&self) -> Result<HistoricalContext, SnapshotError> {
+        self.snapshot_validator
+            .as_ref()
+            .ok_or(SnapshotError::NotValidating)?
+            .context()
+    }
+
+    /// Checks the next historical block against the snapshot the chain started
+    /// from, in height order starting at genesis, whether read from a file or
+    /// received from a peer.
+    ///
+    /// Blocks of another chain are only refused. Should a block of the
+    /// snapshotted chain be invalid or the replayed state differ, the snapshot
+    /// is discarded and the chain rolled back to genesis, so none of its state
+    /// is served any longer.
+    pub fn add_historical_block(&mut self, block: &Block) -> Result<SnapshotStatus, SnapshotError> {
+        let block = self.historical_block_context()?.check(block.clone())?;
+        self.add_checked_historical_block(block)
+    }
+
+    /// Like `add_historical_block`, for a block already checked by
+    /// `HistoricalContext::check`
+    pub fn add_checked_historical_block(
+        &mut self,
+        block: CheckedHistoricalBlock,
+    ) -> Result<SnapshotStatus, SnapshotError> {
+        let validator = self
+            .snapshot_validator
+            .as_mut()
+            .ok_or(SnapshotError::NotValidating)?;
+
+        match validator.add_checked_block(block) {
+            Ok(status) => {
+                if status == SnapshotStatus::Validated {
+                    self.snapshot_validator = None;
+                }
+                self.record_snapshot_status(status.clone())?;
+                Ok(status)
+            }
+            Err(e @ (SnapshotError::InvalidBlock { .. } | SnapshotError::StateMismatch)) => {
+                self.discard_snapshot(e.to_string())?;
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn record_snapshot_status(&mut self, status: SnapshotStatus) -> Result<(), ChainError> {
+        if let Some(record) = self.store.get_meta(SNAPSHOT_KEY)? {
+            let mut record: SnapshotRecord = serde_json::from_str(&record)?;
+            record.status = status.clone();
+            self.store
+                .put_meta(SNAPSHOT_KEY, &serde_json::to_string(&record)?)?;
+        }
+        self.snapshot_status = Some(status);
+        Ok(())
+    }
+
+    /// Rolls the chain back to genesis, dropping the state of an invalid snapshot
+    /// and every block connected on top of it, which subscribers are told about
+    /// along with the evicted mempool
+    fn discard_snapshot(&mut self, reason: String) -> Result<(), ChainError> {
+        // Only the blocks above the pruned height have a body to announce
+        let mut disconnected = Vec::new();
+        for height in (self.pruned_height.max(1)..=self.height()?).rev() {
+            disconnected.push((
+                height,
+                self.hash_at_height(height)?,
+                self.block_at_height(height)?,
+            ));
+        }
+
+        let genesis_hash = self.hash_at_height(0)?;
+        // The tip moves before the index shrinks, see `ChainStore`
+        self.store.set_tip(&genesis_hash)?;
+        self.store.truncate_heights(1)?;
+        self.tip = genesis_hash;
+        self.heights_by_hash.retain(|hash, _| *hash == genesis_hash);
+        self.pruned_height = 0;
+        self.store.put_meta(PRUNED_HEIGHT_KEY, "0")?;
+
+        self.state = self.replay_state()?;
+        self.cumulative_work = 0;
+        self.write_checkpoint()?;
+
+        for (height, hash, block) in disconnected {
+            self.events.publish(ChainEvent::BlockDisconnected {
+                height,
+                hash,
+                block,
+            });
+        }
+        for transaction in std::mem::take(&mut self.mempool) {
+            self.events.publish(ChainEvent::TransactionEvicted {
+                txid: transaction.id(),
+                transaction: (*transaction).clone(),
+            });
+        }
+
+        self.snapshot_validator = None;
+        self.record_snapshot_status(SnapshotStatus::Invalid { reason })
+    }
+
+    /// Loads the checkpoint of the chain state and applies the deltas of the
+    /// blocks above it. The state is rebuilt from the blocks instead when the
+    /// checkpoint is no longer on the active chain or a delta is missing.
+    fn load_state(&mut self) -> Result<(), ChainError> {
+        let height = self.height()?;
+        if let Some(checkpoint) = self.store.get_checkpoint()? {
+            let checkpoint_height = checkpoint.height;
+            let cumulative_work = checkpoint.cumulative_work;
+            if let Some(state) = self.replay_deltas(checkpoint)? {
+                self.state = state;
+                self.cumulative_work = cumulative_work
+                    + (height - checkpoint_height) as u128 * block_work(self.difficulty);
+                self.checkpoint_height = checkpoint_height;
+                return Ok(());
+            }
+        }
+
+        self.state = self.replay_state()?;
+        self.cumulative_work = height as u128 * block_work(self.difficulty);
+        self.write_checkpoint()
+    }
+
+    /// The state of `checkpoint` brought up to the tip, `None` if it can't be
+    fn replay_deltas(&self, checkpoint: StateCheckpoint) -> Result<Option<ChainState>, ChainError> {
+        if self.store.hash_at_height(checkpoint.height)? != Some(checkpoint.hash) {
+            return Ok(None);
+        }
+
+        let mut state = checkpoint.state;
+        for height in checkpoint.height + 1..=self.height()? {
+            match self.store.get_delta(&self.hash_at_height(height)?)? {
+                Some(delta) => state.apply(&delta),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(state))
+    }
+
+    /// Rebuilds the chain state from the stored blocks, which requires all of them.
+    fn replay_state(&self) -> Result<ChainState, ChainError> {
+        let mut state = ChainState::new();
+        for height in 0..=self.height()? {
+            state.apply_block(height, &self.block_at_height(height)?);
+        }
+        Ok(state)
+    }
+
+    /// Keeps only the bodies of the `depth` most recent blocks, deleting older
+    /// ones now and as new blocks are added. `None` disables pruning.
+    pub fn set_prune_depth(&mut self, depth: Option<u64>) -> Result<(), ChainError> {
+        self.prune_depth = depth;
+        self.prune()
+    }
+
+    pub fn prune_depth(&self) -> Option<u64> {
+        self.prune_depth
+    }
+
+    /// Lowest height whose block body is still available
+    pub fn pruned_height(&self) -> u64 {
+        self.pruned_height
+    }
+
+    fn prune(&mut self) -> Result<(), ChainError> {
+        let depth = match self.prune_depth {
+            Some(depth) => depth,
+            None => return Ok(()),
+        };
+
+        let keep_from = (self.height()? + 1).saturating_sub(depth);
+        if keep_from <= self.pruned_height {
+            return Ok(());
         }
+
+        for height in self.pruned_height..keep_from {
+            if let Some(hash) = self.store.hash_at_height(height)? {
+                self.store.delete_block(&hash)?;
+            }
+        }
+        self.pruned_height = keep_from;
+        self.store
+            .put_meta(PRUNED_HEIGHT_KEY, &keep_from.to_string())?;
+
+        Ok(())
     }
 
     /// For testing
@@ -36,184 +448,649 @@ impl Blockchain {
         self.mempool.clone() // Shallow copy
     }
 
-    pub fn last_block(&self) -> Result<&Block, ChainError> {
-        if self.chain.len() == 0 {
-            return Err(ChainError::RetrieveBlockError(
+    pub fn difficulty(&self) -> usize {
+        self.difficulty
+    }
+
+    /// Height of the tip, genesis being at height 0
+    pub fn height(&self) -> Result<u64, ChainError> {
+        match self.store.block_count()? {
+            0 => Err(ChainError::RetrieveBlockError(
                 "No blocks in the chain".into(),
-            ));
+            )),
+            count => Ok(count - 1),
         }
-        self.chain.last().ok_or(ChainError::RetrieveBlockError(
-            "Failed to retrieve block ( `.last()` call failed ) ".into(),
-        ))
     }
 
-    /// For testing
-    pub fn chain(&self) -> Vec<Block> {
-        self.chain.clone() // Deep copy
+    pub fn hash_at_height(&self, height: u64) -> Result<BlockHash, ChainError> {
+        self.store
+            .hash_at_height(height)?
+            .ok_or_else(|| ChainError::RetrieveBlockError(format!("No block at height {}", height)))
     }
 
-    pub fn add_transaction(
-        &mut self,
-        sender_address: &str,
-        recipient_address: &str,
-        value: f32,
-    ) -> &mut Self {
-        let transaction = Transaction::new(
-            String::from(sender_address),
-            String::from(recipient_address),
-            value,
-        );
+    pub fn block_at_height(&self, height: u64) -> Result<Block, ChainError> {
+        let hash = self.hash_at_height(height)?;
+        if height < self.pruned_height {
+            return Err(ChainError::Pruned(format!(
+                "Block at height {} has been pruned",
+                height
+            )));
+        }
+        self.block_by_hash(&hash)
+    }
 
-        self.mempool.push(Arc::new(transaction));
+    /// Height of a block of the active chain, `None` for unknown blocks or blocks
+    /// of abandoned branches
+    pub fn height_of(&self, hash: &BlockHash) -> Option<u64> {
+        self.heights_by_hash.get(hash).copied()
+    }
 
-        self
+    /// Headers from height `from` to `to`, both included. Unlike blocks,
+    /// headers are available below the pruned height.
+    pub fn headers_in_range(&self, from: u64, to: u64) -> Result<Vec<BlockHeader>, ChainError> {
+        let height = self.height()?;
+        if from > to || to > height {
+            return Err(ChainError::RetrieveBlockError(format!(
+                "Invalid range {}..={}, chain height is {}",
+                from, to, height
+            )));
+        }
+
+        (from..=to)
+            .map(|height| self.header_by_hash(&self.hash_at_height(height)?))
+            .collect()
     }
 
-    pub fn proof_of_work(&self) -> Result<Block, ChainError> {
-        let previous_hash = match self.last_block()?.hash_raw() {
-            Ok(hash) => hash,
-            Err(e) => return Err(e.into()),
-        };
+    pub fn block_by_hash(&self, hash: &BlockHash) -> Result<Block, ChainError> {
+        if let Some(block) = self.store.get_block(hash)? {
+            return Ok(block);
+        }
+        let below_pruned_height = self
+            .height_of(hash)
+            .is_some_and(|height| height < self.pruned_height);
+        if below_pruned_height || self.store.get_header(hash)?.is_some() {
+            return Err(ChainError::Pruned(format!(
+                "Block {} has been pruned",
+                hash
+            )));
+        }
+        Err(ChainError::RetrieveBlockError(format!(
+            "Block {} not found",
+            hash
+        )))
+    }
 
-        let mut nonce = 0;
+    /// Headers outlive pruning, for stores written before headers were kept
+    /// separately it falls back to the block body.
+    pub fn header_by_hash(&self, hash: &BlockHash) -> Result<BlockHeader, ChainError> {
+        match self.store.get_header(hash)? {
+            Some(header) => Ok(header),
+            None => Ok(self.block_by_hash(hash)?.header()),
+        }
+    }
 
-        let previous_hash_str = format!("{:x}", previous_hash);
+    pub fn last_block(&self) -> Result<Block, ChainError> {
+        self.block_by_hash(&self.tip)
+    }
 
-        let mut guess_block = Block::create_from(self.mempool(), nonce, previous_hash_str);
-        while Self::valid_proof(&guess_block, self.difficulty).is_err() {
-            guess_block.increment_nonce();
+    /// For testing
+    pub fn chain(&self) -> Result<Vec<Block>, ChainError> {
+        self.blocks_in_range(0, self.height()?)
+    }
+
+    /// Blocks from height `from` to `to`, both included
+    pub fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, ChainError> {
+        let height = self.height()?;
+        if from > to || to > height {
+            return Err(ChainError::RetrieveBlockError(format!(
+                "Invalid range {}..={}, chain height is {}",
+                from, to, height
+            )));
         }
-        Ok(guess_block)
+        if from < self.pruned_height {
+            return Err(ChainError::Pruned(format!(
+                "Blocks below height {} have been pruned",
+                self.pruned_height
+            )));
+        }
+
+        (from..=to)
+            .map(|height| self.block_at_height(height))
+            .collect()
     }
 
-    pub fn get_balance(&self, address: &str) -> f32 {
-        let mut balance = 0.0;
+    /// Looks a transaction up by id, newest blocks first, returning it along
+    /// with the height of the block including it.
+    pub fn find_transaction(&self, id: &Hash256) -> Result<(Arc<Transaction>, u64), ChainError> {
+        for height in (self.pruned_height..=self.height()?).rev() {
+            let block = self.block_at_height(height)?;
+            if let Some(transaction) = block.transactions().into_iter().find(|tx| tx.id() == *id) {
+                return Ok((transaction, height));
+            }
+        }
 
-        for block in self.chain.iter() {
-            for transaction in block.transactions().iter() {
-                if transaction.sender_address() == address {
-                    balance -= transaction.value();
-                }
-                if transaction.recipient_address() == address {
-                    balance += transaction.value();
+        if self.pruned_height > 0 {
+            return Err(ChainError::Pruned(format!(
+                "Transaction {} not found, blocks below height {} have been pruned",
+                id, self.pruned_height
+            )));
+        }
+        Err(ChainError::RetrieveBlockError(format!(
+            "Transaction {} not found",
+            id
+        )))
+    }
+
+    /// Adds a transaction as received, signature included, returning its id.
+    /// It must be signed by its sender, with exactly the threshold of
+    /// cosigners for multisig senders, and funded and carry the next sequence
+    /// of its sender once the pending transactions are applied, so that
+    /// transactions already in the chain can't be submitted again. Rewards
+    /// only come with mined blocks.
+    pub fn submit_transaction(&mut self, transaction: Transaction) -> Result<Hash256, ChainError> {
+        let txid = transaction.id();
+        if transaction.is_reward() || !transaction.has_valid_signature() {
+            if let Some(multisig) = transaction.multisig() {
+                let found = transaction.multisig_signers();
+                if multisig.address() == transaction.sender_address()
+                    && found < multisig.threshold()
+                {
+                    return Err(ValidationError::MissingSignatures {
+                        txid,
+                        required: multisig.threshold(),
+                        found,
+                    }
+                    .into());
                 }
             }
+            return Err(ValidationError::BadSignature { txid }.into());
+        }
+
+        if self.mempool.iter().any(|pending| pending.id() == txid) {
+            return Ok(txid);
+        }
+        self.pending_spending()?.apply(&transaction)?;
+
+        self.accept_transaction(Arc::new(transaction));
+        Ok(txid)
+    }
+
+    /// The state with the pending transactions applied, as in the next block
+    fn pending_spending(&self) -> Result<Spending<'_>, ChainError> {
+        let mut spending = self.state.spending(self.height()? + 1);
+        for pending in &self.mempool {
+            // Left out of blocks when unfunded, as after a reorganization
+            spending.apply(pending).ok();
+        }
+        Ok(spending)
+    }
+
+    /// Sequence the next transaction sent by `address` has to carry, counting
+    /// its pending transactions
+    pub fn next_sequence(&self, address: &Address) -> Result<u64, ChainError> {
+        Ok(self.pending_spending()?.sequence(address))
+    }
+
+    fn accept_transaction(&mut self, transaction: Arc<Transaction>) {
+        // Blocks can't hold the same transaction twice
+        let txid = transaction.id();
+        if self.mempool.iter().any(|tx| tx.id() == txid) {
+            return;
         }
-        balance
+
+        self.mempool.push(transaction.clone());
+        self.events.publish(ChainEvent::TransactionAccepted {
+            txid,
+            transaction: (*transaction).clone(),
+        });
+    }
+
+    /// Receives every `ChainEvent` published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.events.subscribe()
+    }
+
+    /// Block holding the funded transactions of the mempool and the mining
+    /// reward on top of the tip, its nonce is still to be found
+    pub fn block_template(&self) -> Result<Block, ChainError> {
+        let height = self.height()? + 1;
+        let mut spending = self.state.spending(height);
+        let mut transactions: Vec<Arc<Transaction>> = self
+            .mempool
+            .iter()
+            .filter(|transaction| spending.apply(transaction).is_ok())
+            .cloned()
+            .collect();
+        transactions.push(Arc::new(Transaction::reward(
+            self.address,
+            MINING_REWARD,
+            height,
+        )));
+
+        Ok(Block::create_from(transactions, 0, self.tip))
+    }
+
+    pub fn proof_of_work(&self) -> Result<Block, ChainError> {
+        let search = miner::search(
+            self.block_template()?,
+            self.difficulty,
+            self.mining_threads,
+            |_| false,
+        );
+        Ok(search
+            .block
+            .expect("A search that is never cancelled finds a block"))
+    }
+
+    pub fn mining_threads(&self) -> usize {
+        self.mining_threads
+    }
+
+    /// Sets how many threads search for nonces, at least one
+    pub fn set_mining_threads(&mut self, threads: usize) {
+        self.mining_threads = threads.max(1);
+    }
+
+    pub fn get_balance(&self, address: &Address) -> f32 {
+        self.state.balance(address)
+    }
+
+    /// Transactions touching `address`, newest first
+    pub fn get_history(&self, address: &Address, offset: usize, limit: usize) -> Vec<HistoryEntry> {
+        self.state.history(address, offset, limit)
     }
 
-    pub fn address(&self) -> String {
-        self.address.clone()
+    pub fn get_history_len(&self, address: &Address) -> usize {
+        self.state.history_len(address)
     }
-    pub fn mine(&mut self) -> Result<&Block, ChainError> {
-        let self_address = self.address();
-        self.add_transaction("the_network", &self_address, 1.0);
 
+    pub fn address(&self) -> Address {
+        self.address
+    }
+    pub fn mine(&mut self) -> Result<Block, ChainError> {
         let new_block = self.proof_of_work()?;
 
         self.verify_and_add_block(new_block)
     }
 
-    fn valid_proof(
-        // nonce: i64,
-        // previous_hash: GenericArray<u8, typenum::U32>,
-        // transactions: Vec<Arc<Transaction>>,
-        guess_block: &Block,
+    /// What a block extending the current tip is checked against.
+    /// Only relies on the tip header, so it works on pruned chains too.
+    pub fn block_context(&self) -> Result<BlockContext, ChainError> {
+        Ok(BlockContext {
+            previous_hash: self.tip,
+            previous_header: self.header_by_hash(&self.tip)?,
+            difficulty: self.difficulty,
+        })
+    }
+
+    /// Checks that `block` can be appended on top of the block identified by
+    /// `previous_block_hash` and `previous_header`.
+    pub(crate) fn verify_successor(
+        block: &Block,
+        previous_block_hash: &BlockHash,
+        previous_header: &BlockHeader,
         difficulty: usize,
     ) -> Result<(), ChainError> {
-        let zeros = "0".repeat(difficulty);
+        Self::verify_extends(block, previous_block_hash, previous_header)?;
+        block.check(difficulty)?;
+        Ok(())
+    }
 
-        // let hash = guess_block.hash();
-        let hash = guess_block.hash()?;
-        if hash.starts_with(&zeros) {
-            return Ok(());
+    /// The checks of `verify_successor` that depend on the parent, cheap enough to
+    /// repeat under the lock
+    fn verify_extends(
+        block: &Block,
+        previous_block_hash: &BlockHash,
+        previous_header: &BlockHeader,
+    ) -> Result<(), ChainError> {
+        if block.previous_hash() != *previous_block_hash {
+            return Err(ValidationError::BadPreviousHash {
+                expected: *previous_block_hash,
+                found: block.previous_hash(),
+            }
+            .into());
         }
-        Err(ChainError::ValidationError(("Invalid proof").into()))
+
+        if block.timestamp() < previous_header.timestamp() {
+            return Err(ValidationError::TimestampTooOld {
+                timestamp: block.timestamp(),
+                previous: previous_header.timestamp(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    pub fn verify_and_add_block(&mut self, block: Block) -> Result<Block, ChainError> {
+        let block = self.block_context()?.check(block)?;
+        self.add_checked_block(block)
     }
-    fn verify_block(&self, block: &Block) -> Result<(), ChainError> {
-        let previous_block = self
-            .chain
-            .last()
-            .ok_or(ChainError::RetrieveBlockError(
-                "Failed to get last block".into(),
-            ))?
-            .clone();
 
-        let now = crate::block::Block::generate_timestamp();
+    /// Connects a block checked by `BlockContext::check`, which may have run
+    /// without the lock. Only the parent is verified again, as the tip may have
+    /// moved meanwhile.
+    pub fn add_checked_block(&mut self, block: CheckedBlock) -> Result<Block, ChainError> {
+        let block = if block.difficulty() == self.difficulty {
+            block.into_inner()
+        } else {
+            block
+                .into_inner()
+                .into_checked(self.difficulty)?
+                .into_inner()
+        };
+        let block = self.connect_block(block)?;
 
-        if block.timestamp() < previous_block.timestamp() || block.timestamp() > now {
-            return Err(ChainError::ValidationError("Invalid timestamp".into()));
+        let mined: HashSet<Hash256> = block.transactions().iter().map(|tx| tx.id()).collect();
+        for transaction in std::mem::take(&mut self.mempool) {
+            let txid = transaction.id();
+            if !mined.contains(&txid) {
+                self.events.publish(ChainEvent::TransactionEvicted {
+                    txid,
+                    transaction: (*transaction).clone(),
+                });
+            }
         }
 
-        let previous_block_hash = previous_block.hash()?;
+        Ok(block)
+    }
 
-        let previous_hash = block.previous_hash();
+    /// Makes `block` the new tip, updating the chain state. The block must have
+    /// passed `Block::check` already, only its parent and that its transactions
+    /// are funded and in sequence are verified here.
+    fn connect_block(&mut self, block: Block) -> Result<Block, ChainError> {
+        let previous_header = self.header_by_hash(&self.tip)?;
+        Self::verify_extends(&block, &self.tip, &previous_header)?;
+        let height = self.height()? + 1;
+        let mut spending = self.state.spending(height);
+        for transaction in block.transactions() {
+            spending.apply(&transaction)?;
+        }
 
-        if previous_block_hash != previous_hash {
-            return Err(ChainError::ValidationError(
-                "Previous hash does not match".into(),
-            ));
+        let hash = block.hash();
+        let delta = BlockDelta::of(height, &block);
+        self.store.put_block(&hash, &block)?;
+        self.store.put_header(&hash, &block.header())?;
+        self.store.put_delta(&hash, &delta)?;
+        self.store.set_hash_at_height(height, &hash)?;
+        self.store.set_tip(&hash)?;
+        self.tip = hash;
+        self.heights_by_hash.insert(hash, height);
+
+        self.state.apply(&delta);
+        self.cumulative_work += block_work(self.difficulty);
+        if height % STATE_CHECKPOINT_INTERVAL == 0 {
+            self.write_checkpoint()?;
         }
 
-        Self::valid_proof(block, self.difficulty)
+        self.prune()?;
+
+        self.events.publish(ChainEvent::BlockConnected {
+            height,
+            hash,
+            block: block.clone(),
+        });
+
+        Ok(block)
     }
-    pub fn verify_and_add_block(&mut self, block: Block) -> Result<&Block, ChainError> {
-        self.verify_block(&block)?;
-        self.chain.push(block);
 
-        self.mempool.clear();
+    /// Removes the tip from the active chain, rolling the chain state back.
+    /// The block stays in the store.
+    fn disconnect_block(&mut self) -> Result<Block, ChainError> {
+        let height = self.height()?;
+        if height == 0 {
+            return Err(ValidationError::DisconnectGenesis.into());
+        }
 
-        match self.chain.last() {
-            Some(block) => Ok(block),
-            None => Err(ChainError::RetrieveBlockError(
-                "Failed to retrieve block".into(),
-            )),
+        let block = self.block_at_height(height)?;
+        let hash = self.hash_at_height(height)?;
+        let previous_hash = self.hash_at_height(height - 1)?;
+
+        // The tip moves before the index shrinks, see `ChainStore`
+        self.store.set_tip(&previous_hash)?;
+        self.store.truncate_heights(height)?;
+        self.tip = previous_hash;
+        self.heights_by_hash.remove(&hash);
+
+        self.state.revert_block(height, &block);
+        self.cumulative_work -= block_work(self.difficulty);
+        // The checkpoint has to stay on the active chain
+        if height <= self.checkpoint_height {
+            self.write_checkpoint()?;
         }
+
+        self.events.publish(ChainEvent::BlockDisconnected {
+            height,
+            hash,
+            block: block.clone(),
+        });
+
+        Ok(block)
     }
 
-    // pub fn add_block(&mut self) -> Result<&Block, ChainError> {
-    //     let previous_hash = self.last_block()?.hash()?;
-    //     let nonce = 0;
-    //     self.create_block(nonce, previous_hash)
-    // }
+    /// Saves the whole chain state as of the tip
+    fn write_checkpoint(&mut self) -> Result<(), ChainError> {
+        let height = self.height()?;
+        self.store.put_checkpoint(&StateCheckpoint {
+            height,
+            hash: self.tip,
+            cumulative_work: self.cumulative_work,
+            state: self.state.clone(),
+        })?;
+        self.checkpoint_height = height;
+        Ok(())
+    }
 
-    fn create_block(&mut self, nonce: i64, previous_hash: String) -> Block {
-        let block = Block::create_from(self.mempool(), nonce, previous_hash);
-        return block;
+    /// Puts the transactions of disconnected blocks back in the mempool,
+    /// leaving out mining rewards and anything in `exclude`.
+    fn resurrect_transactions(&mut self, blocks: &[Block], exclude: &[Hash256]) {
+        for block in blocks.iter().rev() {
+            for transaction in block.transactions() {
+                if !transaction.is_reward() && !exclude.contains(&transaction.id()) {
+                    self.accept_transaction(transaction);
+                }
+            }
+        }
+    }
+
+    /// Disconnects the tip, returning its transactions to the mempool
+    pub fn disconnect_tip(&mut self) -> Result<Block, ChainError> {
+        let block = self.disconnect_block()?;
+        self.resurrect_transactions(std::slice::from_ref(&block), &[]);
+        Ok(block)
+    }
+
+    /// Switches the active chain to the branch made of `blocks` on top of the
+    /// block at `fork_height`, provided the branch holds more work.
+    ///
+    /// If a block of the branch turns out invalid, the original chain is restored
+    /// and the error returned. Transactions only present in the abandoned blocks go
+    /// back to the mempool.
+    pub fn reorganize(&mut self, fork_height: u64, blocks: Vec<Block>) -> Result<(), ChainError> {
+        let height = self.height()?;
+        if fork_height > height {
+            return Err(ChainError::RetrieveBlockError(format!(
+                "Fork height {} is above the tip at {}",
+                fork_height, height
+            )));
+        }
+        if fork_height + 1 < self.pruned_height {
+            return Err(ChainError::Pruned(format!(
+                "Cannot reorganize below height {}, older blocks have been pruned",
+                self.pruned_height
+            )));
+        }
+        if (blocks.len() as u64) <= height - fork_height {
+            return Err(ValidationError::InsufficientWork {
+                branch: blocks.len() as u64,
+                active: height - fork_height,
+            }
+            .into());
+        }
+
+        // Checked up front and in parallel, an invalid block leaves the chain untouched
+        let difficulty = self.difficulty;
+        let blocks = blocks
+            .into_par_iter()
+            .map(|block| block.into_checked(difficulty).map(CheckedBlock::into_inner))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut disconnected = Vec::new();
+        while self.height()? > fork_height {
+            disconnected.push(self.disconnect_block()?);
+        }
+
+        let mut connected_ids = Vec::new();
+        for (connected, block) in blocks.into_iter().enumerate() {
+            let ids: Vec<Hash256> = block.transactions().iter().map(|tx| tx.id()).collect();
+
+            if let Err(e) = self.connect_block(block) {
+                for _ in 0..connected {
+                    self.disconnect_block()?;
+                }
+                for block in disconnected.into_iter().rev() {
+                    self.connect_block(block)?;
+                }
+                return Err(e);
+            }
+            connected_ids.extend(ids);
+        }
+
+        self.mempool
+            .retain(|transaction| !connected_ids.contains(&transaction.id()));
+        self.resurrect_transactions(&disconnected, &connected_ids);
+
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::multisig::Multisig;
+    use crate::wallet::Wallet;
     use std::collections::HashSet;
 
+    /// Signs a payment from `wallet` and submits it
+    fn pay(
+        blockchain: &mut Blockchain,
+        wallet: &Wallet,
+        recipient: Address,
+        value: f32,
+    ) -> Hash256 {
+        let sequence = blockchain.next_sequence(&wallet.address()).unwrap();
+        let mut transaction =
+            Transaction::new(wallet.address(), recipient, value).with_sequence(sequence);
+        wallet.sign_transaction(&mut transaction);
+        blockchain.submit_transaction(transaction).unwrap()
+    }
+
+    /// Block holding `transactions` and the reward on top of the tip, mined
+    fn mined(blockchain: &Blockchain, transactions: Vec<Transaction>) -> Block {
+        let mut transactions: Vec<Arc<Transaction>> =
+            transactions.into_iter().map(Arc::new).collect();
+        transactions.push(Arc::new(Transaction::reward(
+            blockchain.address,
+            MINING_REWARD,
+            blockchain.height().unwrap() + 1,
+        )));
+        let block = Block::create_from(transactions, 0, blockchain.tip);
+        miner::search(block, blockchain.difficulty, 1, |_| false)
+            .block
+            .unwrap()
+    }
+
     #[test]
     fn cannot_insert_block_without_pow() {
-        let mut blockchain = Blockchain::new(String::from("my_address"), 3);
-        let new_block = blockchain.last_block().unwrap().clone();
+        let mut blockchain = Blockchain::new(Address::named("my_address"), 3);
+        let new_block = blockchain.last_block().unwrap();
 
         assert!(blockchain.verify_and_add_block(new_block).is_err());
     }
 
+    #[test]
+    fn rejected_blocks_report_why() {
+        let mut blockchain = Blockchain::new(Address::named("my_address"), 3);
+        let tip = blockchain.last_block().unwrap().hash();
+
+        let orphan = Block::create_from(vec![], 0, Hash256::digest("not_the_tip"));
+        assert!(matches!(
+            blockchain.verify_and_add_block(orphan),
+            Err(ChainError::ValidationError(ValidationError::BadPreviousHash { expected, .. }))
+                if expected == tip
+        ));
+
+        let stale = Block::new(0, vec![], 0, tip);
+        assert!(matches!(
+            blockchain.verify_and_add_block(stale),
+            Err(ChainError::ValidationError(
+                ValidationError::TimestampTooOld { .. }
+            ))
+        ));
+
+        let unmined = Block::create_from(vec![], 0, tip);
+        let err = blockchain.verify_and_add_block(unmined).unwrap_err();
+        assert!(matches!(
+            err,
+            ChainError::InvalidBlock(BlockError::InvalidProofOfWork { difficulty: 3, .. })
+        ));
+        assert!(err.to_string().contains("does not meet difficulty 3"));
+    }
+
+    #[test]
+    fn blocks_checked_without_the_chain_are_connected() {
+        let wallet = Wallet::generate_new();
+        let mut blockchain = Blockchain::new(wallet.address(), 1);
+        blockchain.mine().unwrap();
+        let mut transaction = Transaction::new(wallet.address(), Address::named("b"), 1.0);
+        wallet.sign_transaction(&mut transaction);
+
+        let mut forged = serde_json::to_value(&transaction).unwrap();
+        forged["recipient_address"] = serde_json::json!(Address::named("thief"));
+        let forged: Transaction = serde_json::from_value(forged).unwrap();
+        assert!(matches!(
+            blockchain.submit_transaction(forged),
+            Err(ChainError::ValidationError(
+                ValidationError::BadSignature { .. }
+            ))
+        ));
+        let txid = blockchain.submit_transaction(transaction).unwrap();
+
+        let context = blockchain.block_context().unwrap();
+        let block = blockchain.proof_of_work().unwrap();
+        let checked = std::thread::spawn(move || context.check(block))
+            .join()
+            .unwrap()
+            .unwrap();
+        let stale = checked.clone();
+        blockchain.add_checked_block(checked).unwrap();
+        assert_eq!(blockchain.find_transaction(&txid).unwrap().1, 2);
+
+        // The tip moved since the block was checked
+        assert!(matches!(
+            blockchain.add_checked_block(stale),
+            Err(ChainError::ValidationError(
+                ValidationError::BadPreviousHash { .. }
+            ))
+        ));
+    }
+
     #[test]
     fn block_added_references_previous_block() {
-        let mut blockchain = Blockchain::new(String::from("my_address"), 3);
+        let mut blockchain = Blockchain::new(Address::named("my_address"), 3);
 
         for _ in 0..10 {
             blockchain.mine().unwrap();
             assert_eq!(
-                blockchain.last_block().unwrap().hash().unwrap(),
-                blockchain.chain()[blockchain.chain().len() - 1]
-                    .hash()
-                    .unwrap()
+                blockchain.last_block().unwrap().hash(),
+                blockchain.chain().unwrap()[blockchain.chain().unwrap().len() - 1].hash()
             );
         }
     }
 
     #[test]
     fn hashes_are_unique() {
-        let mut blockchain = Blockchain::new(String::from("my_address"), 3);
+        let mut blockchain = Blockchain::new(Address::named("my_address"), 3);
 
         for _ in 0..10 {
             blockchain.mine().unwrap();
@@ -221,17 +1098,25 @@ mod tests {
 
         let set: HashSet<_> = blockchain
             .chain()
+            .unwrap()
             .iter()
-            .map(|block| block.hash().unwrap())
+            .map(|block| block.hash())
             .collect();
-        assert_eq!(blockchain.chain().len(), set.len());
+        assert_eq!(blockchain.chain().unwrap().len(), set.len());
     }
 
     #[test]
     fn mempool_empty_after_block_created() {
-        let mut blockchain = Blockchain::new(String::from("my_address"), 3);
+        let miner = Wallet::generate_new();
+        let mut blockchain = Blockchain::new(miner.address(), 3);
+        blockchain.mine().unwrap();
 
-        blockchain.add_transaction("sender_address", "recipient_address", 100.0); // there must be a way to avoid this..
+        pay(
+            &mut blockchain,
+            &miner,
+            Address::named("recipient_address"),
+            5.0,
+        );
 
         assert_eq!(blockchain.mempool().len(), 1);
 
@@ -239,19 +1124,518 @@ mod tests {
 
         assert_eq!(blockchain.mempool().len(), 0);
     }
+
+    #[test]
+    fn subscribers_see_blocks_and_mempool_changes() {
+        let sender = Wallet::generate_new();
+        let mut blockchain = Blockchain::new(sender.address(), 1);
+        blockchain.mine().unwrap();
+        let mut events = blockchain.subscribe();
+
+        pay(
+            &mut blockchain,
+            &sender,
+            Address::named("recipient_address"),
+            3.0,
+        );
+        blockchain.mine().unwrap();
+        pay(
+            &mut blockchain,
+            &sender,
+            Address::named("other_address"),
+            1.0,
+        );
+        let orphan = blockchain.proof_of_work().unwrap();
+        blockchain.mine().unwrap();
+        blockchain.disconnect_tip().unwrap();
+        pay(
+            &mut blockchain,
+            &sender,
+            Address::named("evicted_address"),
+            2.0,
+        );
+        assert!(blockchain.verify_and_add_block(orphan).is_ok());
+
+        let kinds: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|event| event.kind())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "transaction_accepted",
+                "block_connected",
+                "transaction_accepted",
+                "block_connected",
+                "block_disconnected",
+                "transaction_accepted",
+                "transaction_accepted",
+                "block_connected",
+                "transaction_evicted",
+            ]
+        );
+    }
+
+    #[test]
+    fn disk_backed_chain_survives_reopen() {
+        let dir =
+            std::env::temp_dir().join(format!("blocksmith-chain-{}", Block::generate_timestamp()));
+
+        let tip = {
+            let store = crate::store::DiskStore::open(&dir).unwrap();
+            let mut blockchain =
+                Blockchain::with_store(Address::named("my_address"), 3, Box::new(store)).unwrap();
+            blockchain.mine().unwrap();
+            blockchain.mine().unwrap();
+            blockchain.last_block().unwrap().hash()
+        };
+
+        let store = crate::store::DiskStore::open(&dir).unwrap();
+        let blockchain =
+            Blockchain::with_store(Address::named("my_address"), 3, Box::new(store)).unwrap();
+        assert_eq!(blockchain.height().unwrap(), 2);
+        assert_eq!(blockchain.last_block().unwrap().hash(), tip);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn state_is_loaded_from_the_checkpoint_and_deltas() {
+        let dir =
+            std::env::temp_dir().join(format!("blocksmith-state-{}", Block::generate_timestamp()));
+        let open = || crate::store::DiskStore::open(&dir).unwrap();
+        let wallet = Wallet::generate_new();
+
+        let (state, tip) = {
+            let mut blockchain =
+                Blockchain::with_store(wallet.address(), 1, Box::new(open())).unwrap();
+            blockchain.mine().unwrap();
+            pay(&mut blockchain, &wallet, Address::named("b"), 4.0);
+            blockchain.mine().unwrap();
+            (blockchain.state.clone(), blockchain.tip)
+        };
+
+        // Only deltas were written since the checkpoint of the genesis block
+        assert_eq!(open().get_checkpoint().unwrap().unwrap().height, 0);
+        let blockchain = Blockchain::with_store(wallet.address(), 1, Box::new(open())).unwrap();
+        assert_eq!(blockchain.state, state);
+        assert_eq!(blockchain.cumulative_work(), 2 * block_work(1));
+
+        // A checkpoint off the active chain is replaced by a rebuilt state
+        open()
+            .put_checkpoint(&StateCheckpoint {
+                height: 2,
+                hash: BlockHash::digest(b"abandoned"),
+                cumulative_work: 0,
+                state: ChainState::new(),
+            })
+            .unwrap();
+        let blockchain = Blockchain::with_store(wallet.address(), 1, Box::new(open())).unwrap();
+        assert_eq!(blockchain.state, state);
+        assert_eq!(blockchain.cumulative_work(), 2 * block_work(1));
+        assert_eq!(open().get_checkpoint().unwrap().unwrap().hash, tip);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pruned_chain_keeps_headers_and_balances() {
+        let miner = Wallet::generate_new();
+        let mut blockchain = Blockchain::new(miner.address(), 2);
+        blockchain.set_prune_depth(Some(2)).unwrap();
+
+        blockchain.mine().unwrap();
+        let pruned_tx = pay(
+            &mut blockchain,
+            &miner,
+            Address::named("recipient_address"),
+            4.0,
+        );
+        for _ in 0..4 {
+            blockchain.mine().unwrap();
+        }
+
+        assert_eq!(blockchain.height().unwrap(), 5);
+        assert_eq!(blockchain.pruned_height(), 4);
+        assert!(matches!(
+            blockchain.block_at_height(1),
+            Err(ChainError::Pruned(_))
+        ));
+        assert!(matches!(
+            blockchain.blocks_in_range(0, 5),
+            Err(ChainError::Pruned(_))
+        ));
+        assert_eq!(blockchain.blocks_in_range(4, 5).unwrap().len(), 2);
+        assert!(matches!(
+            blockchain.find_transaction(&pruned_tx),
+            Err(ChainError::Pruned(_))
+        ));
+
+        let hash = blockchain.hash_at_height(1).unwrap();
+        assert!(blockchain.header_by_hash(&hash).is_ok());
+        assert_eq!(
+            blockchain.get_balance(&Address::named("recipient_address")),
+            4.0
+        );
+        assert_eq!(
+            blockchain.get_balance(&miner.address()),
+            5.0 * MINING_REWARD - 4.0
+        );
+
+        // Blocks keep building on top of the pruned tip
+        blockchain.mine().unwrap();
+        assert_eq!(blockchain.height().unwrap(), 6);
+    }
+
+    #[test]
+    fn spends_need_the_funds_of_the_sender() {
+        let wallet = Wallet::generate_new();
+        let mut blockchain = Blockchain::new(wallet.address(), 1);
+        blockchain.mine().unwrap();
+
+        pay(&mut blockchain, &wallet, Address::named("b"), 6.0);
+        // The pending spend counts against the balance
+        let mut overspend =
+            Transaction::new(wallet.address(), Address::named("c"), 6.0).with_sequence(1);
+        wallet.sign_transaction(&mut overspend);
+        let err = blockchain
+            .submit_transaction(overspend.clone())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ChainError::ValidationError(ValidationError::InsufficientFunds { balance, value, .. })
+                if balance == 4.0 && value == 6.0
+        ));
+        assert!(err.to_string().contains("cannot spend 6"));
+
+        // Nor can a block spend it
+        blockchain.mempool.clear();
+        let mut first = Transaction::new(wallet.address(), Address::named("b"), 6.0);
+        wallet.sign_transaction(&mut first);
+        let block = mined(&blockchain, vec![first, overspend]);
+        assert!(matches!(
+            blockchain.verify_and_add_block(block),
+            Err(ChainError::ValidationError(
+                ValidationError::InsufficientFunds { .. }
+            ))
+        ));
+
+        let mut negative = Transaction::new(wallet.address(), Address::named("b"), -5.0);
+        wallet.sign_transaction(&mut negative);
+        assert!(matches!(
+            blockchain.submit_transaction(negative),
+            Err(ChainError::ValidationError(
+                ValidationError::InvalidValue { .. }
+            ))
+        ));
+        assert_eq!(blockchain.height().unwrap(), 1);
+        assert_eq!(blockchain.get_balance(&wallet.address()), MINING_REWARD);
+    }
+
+    #[test]
+    fn mined_transactions_cannot_be_replayed() {
+        let wallet = Wallet::generate_new();
+        let mut blockchain = Blockchain::new(wallet.address(), 1);
+        blockchain.mine().unwrap();
+        let txid = pay(&mut blockchain, &wallet, Address::named("b"), 1.0);
+        blockchain.mine().unwrap();
+        assert_eq!(blockchain.next_sequence(&wallet.address()).unwrap(), 1);
+
+        let (mined_transaction, _) = blockchain.find_transaction(&txid).unwrap();
+        let replayed = (*mined_transaction).clone();
+        assert!(matches!(
+            blockchain.submit_transaction(replayed.clone()),
+            Err(ChainError::ValidationError(
+                ValidationError::InvalidSequence {
+                    expected: 1,
+                    found: 0,
+                    ..
+                }
+            ))
+        ));
+        let block = mined(&blockchain, vec![replayed]);
+        assert!(matches!(
+            blockchain.verify_and_add_block(block),
+            Err(ChainError::ValidationError(
+                ValidationError::InvalidSequence { .. }
+            ))
+        ));
+
+        // Nor can a reward be claimed at another height
+        let tip = blockchain.tip;
+        let stale_reward = Transaction::reward(blockchain.address, MINING_REWARD, 1);
+        let block = Block::create_from(vec![Arc::new(stale_reward)], 0, tip);
+        let block = miner::search(block, 1, 1, |_| false).block.unwrap();
+        assert!(matches!(
+            blockchain.verify_and_add_block(block),
+            Err(ChainError::ValidationError(
+                ValidationError::InvalidSequence { expected: 3, .. }
+            ))
+        ));
+        assert_eq!(blockchain.get_balance(&Address::named("b")), 1.0);
+    }
+
+    #[test]
+    fn unsigned_schnorr_transactions_are_rejected() {
+        let wallet = Wallet::generate_new();
+        let mut blockchain = Blockchain::new(wallet.address(), 1);
+        blockchain.mine().unwrap();
+
+        let mut unsigned =
+            serde_json::to_value(Transaction::new(wallet.address(), Address::named("b"), 1.0))
+                .unwrap();
+        unsigned["signature_type"] = serde_json::json!("schnorr");
+        let unsigned: Transaction = serde_json::from_value(unsigned).unwrap();
+        assert!(matches!(
+            blockchain.submit_transaction(unsigned),
+            Err(ChainError::ValidationError(
+                ValidationError::BadSignature { .. }
+            ))
+        ));
+        assert!(blockchain.mempool.is_empty());
+    }
+
+    #[test]
+    fn multisig_accounts_spend_with_enough_cosigners() {
+        let cosigners: Vec<Wallet> = (0..3).map(|_| Wallet::generate_new()).collect();
+        let multisig = Multisig::new(
+            2,
+            cosigners.iter().map(|wallet| wallet.public_key()).collect(),
+        )
+        .unwrap();
+        let treasury = multisig.address();
+        let mut blockchain = Blockchain::new(treasury, 1);
+        blockchain.mine().unwrap();
+
+        let mut transaction = Transaction::new(treasury, Address::named("recipient"), 10.0);
+        cosigners[1].sign_multisig(&mut transaction, &multisig);
+        let err = blockchain
+            .submit_transaction(transaction.clone())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ChainError::ValidationError(ValidationError::MissingSignatures {
+                required: 2,
+                found: 1,
+                ..
+            })
+        ));
+        assert!(err.to_string().contains("signed by 1 of the 2 keys"));
+
+        // A policy that is not the sender's is no account at all
+        let other = Multisig::new(1, vec![cosigners[1].public_key()]).unwrap();
+        let mut impostor = Transaction::new(treasury, Address::named("thief"), 10.0);
+        cosigners[1].sign_multisig(&mut impostor, &other);
+        assert!(matches!(
+            blockchain.submit_transaction(impostor),
+            Err(ChainError::ValidationError(
+                ValidationError::BadSignature { .. }
+            ))
+        ));
+
+        cosigners[0].sign_multisig(&mut transaction, &multisig);
+        let txid = blockchain.submit_transaction(transaction).unwrap();
+        blockchain.mine().unwrap();
+        assert_eq!(blockchain.find_transaction(&txid).unwrap().1, 2);
+        assert_eq!(blockchain.get_balance(&Address::named("recipient")), 10.0);
+        assert_eq!(
+            blockchain.get_balance(&treasury),
+            2.0 * MINING_REWARD - 10.0
+        );
+    }
+
+    #[test]
+    fn multisig_spends_below_the_threshold_are_rejected() {
+        let cosigners: Vec<Wallet> = (0..3).map(|_| Wallet::generate_new()).collect();
+        let multisig = Multisig::new(
+            2,
+            cosigners.iter().map(|wallet| wallet.public_key()).collect(),
+        )
+        .unwrap();
+        let treasury = multisig.address();
+        let mut blockchain = Blockchain::new(treasury, 1);
+        blockchain.mine().unwrap();
+
+        let unsigned = Transaction::new(treasury, Address::named("thief"), 5.0);
+        let mut one_of_two = Transaction::new(treasury, Address::named("thief"), 6.0);
+        cosigners[2].sign_multisig(&mut one_of_two, &multisig);
+
+        for transaction in [unsigned, one_of_two] {
+            let txid = transaction.id();
+            assert!(matches!(
+                blockchain.submit_transaction(transaction.clone()),
+                Err(ChainError::ValidationError(
+                    ValidationError::BadSignature { .. }
+                        | ValidationError::MissingSignatures { .. }
+                ))
+            ));
+
+            let block = mined(&blockchain, vec![transaction]);
+            assert!(matches!(
+                blockchain.verify_and_add_block(block),
+                Err(ChainError::InvalidBlock(BlockError::BadSignature { txid: bad }))
+                    if bad == txid
+            ));
+        }
+        assert_eq!(blockchain.height().unwrap(), 1);
+        assert_eq!(blockchain.get_balance(&Address::named("thief")), 0.0);
+    }
+
+    #[test]
+    fn reorganization_rolls_the_address_index_back() {
+        let miner = Wallet::generate_new();
+        let mut blockchain = Blockchain::new(miner.address(), 2);
+        blockchain.mine().unwrap();
+
+        // Branch mined by another node from the same fork point
+        let mut other = Blockchain::new(Address::named("other_address"), 2);
+        other
+            .verify_and_add_block(blockchain.block_at_height(1).unwrap())
+            .unwrap();
+        other.mine().unwrap();
+        other.mine().unwrap();
+
+        pay(
+            &mut blockchain,
+            &miner,
+            Address::named("recipient_address"),
+            3.0,
+        );
+        blockchain.mine().unwrap();
+        assert_eq!(
+            blockchain.get_balance(&Address::named("recipient_address")),
+            3.0
+        );
+        assert_eq!(blockchain.get_history_len(&miner.address()), 3);
+
+        let abandoned = blockchain.last_block().unwrap().hash();
+        blockchain
+            .reorganize(1, other.blocks_in_range(2, 3).unwrap())
+            .unwrap();
+        assert_eq!(blockchain.height_of(&abandoned), None);
+
+        assert_eq!(blockchain.height().unwrap(), 3);
+        assert_eq!(
+            blockchain.get_balance(&Address::named("recipient_address")),
+            0.0
+        );
+        assert_eq!(blockchain.get_balance(&miner.address()), MINING_REWARD);
+        assert_eq!(blockchain.get_history_len(&miner.address()), 1);
+        assert_eq!(
+            blockchain.get_balance(&Address::named("other_address")),
+            2.0 * MINING_REWARD
+        );
+        let new_tip = other.last_block().unwrap().hash();
+        assert_eq!(blockchain.height_of(&new_tip), Some(3));
+        assert_eq!(
+            blockchain.get_history(&Address::named("other_address"), 0, 10)[0].height,
+            3
+        );
+        // The transfer only lived in the abandoned block
+        assert_eq!(blockchain.mempool().len(), 1);
+    }
+
+    #[test]
+    fn invalid_branch_leaves_the_chain_untouched() {
+        let mut blockchain = Blockchain::new(Address::named("my_address"), 2);
+        blockchain.mine().unwrap();
+        let tip = blockchain.last_block().unwrap().hash();
+
+        let unmined = Block::create_from(vec![], 0, Block::genesis().hash());
+        let result = blockchain.reorganize(0, vec![unmined.clone(), unmined]);
+
+        assert!(result.is_err());
+        assert_eq!(blockchain.last_block().unwrap().hash(), tip);
+        assert_eq!(
+            blockchain.get_balance(&Address::named("my_address")),
+            MINING_REWARD
+        );
+    }
 }
 
-#[derive(Debug)]
+#[derive(thiserror::Error, Debug)]
 pub enum ChainError {
-    ValidationError(String),
-    SerializeError(serde_json::Error),
+    #[error("invalid block: {0}")]
+    InvalidBlock(BlockError),
+    #[error("validation failed: {0}")]
+    ValidationError(#[from] ValidationError),
+    #[error("serialization error: {0}")]
+    SerializeError(#[from] serde_json::Error),
+    #[error("{0}")]
     RetrieveBlockError(String),
+    #[error("store error: {0}")]
+    StoreError(#[from] StoreError),
+    /// The requested data lives in blocks whose bodies have been pruned
+    #[error("{0}")]
+    Pruned(String),
+}
+
+/// Why a block, transaction or branch doesn't fit the chain it is checked against.
+/// Failures of a block on its own are reported as `ChainError::InvalidBlock`.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    #[error("block extends {found} but the tip is {expected}")]
+    BadPreviousHash {
+        expected: BlockHash,
+        found: BlockHash,
+    },
+    #[error("block timestamp {timestamp} is older than its parent's {previous}")]
+    TimestampTooOld { timestamp: i64, previous: i64 },
+    #[error("transaction {txid} moves {value}, only positive values can be sent")]
+    InvalidValue { txid: Hash256, value: f32 },
+    #[error("transaction {txid} has sequence {found} where {expected} is expected")]
+    InvalidSequence {
+        txid: Hash256,
+        expected: u64,
+        found: u64,
+    },
+    #[error("{address} has a balance of {balance}, cannot spend {value}")]
+    InsufficientFunds {
+        address: Address,
+        balance: f32,
+        value: f32,
+    },
+    #[error("branch of {branch} blocks does not have more work than the {active} it replaces")]
+    InsufficientWork { branch: u64, active: u64 },
+    #[error("chain has a different genesis block")]
+    GenesisMismatch,
+    #[error("store already holds a chain")]
+    StoreNotEmpty,
+    #[error("cannot disconnect the genesis block")]
+    DisconnectGenesis,
+    #[error("transaction {txid} has an invalid signature")]
+    BadSignature { txid: Hash256 },
+    #[error("transaction {txid} is signed by {found} of the {required} keys its sender requires")]
+    MissingSignatures {
+        txid: Hash256,
+        required: usize,
+        found: usize,
+    },
+}
+
+impl ValidationError {
+    /// Stable, snake_case name of the failure, for clients to match on
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ValidationError::BadPreviousHash { .. } => "bad_previous_hash",
+            ValidationError::TimestampTooOld { .. } => "timestamp_too_old",
+            ValidationError::InvalidValue { .. } => "invalid_value",
+            ValidationError::InvalidSequence { .. } => "invalid_sequence",
+            ValidationError::InsufficientFunds { .. } => "insufficient_funds",
+            ValidationError::InsufficientWork { .. } => "insufficient_work",
+            ValidationError::GenesisMismatch => "genesis_mismatch",
+            ValidationError::StoreNotEmpty => "store_not_empty",
+            ValidationError::DisconnectGenesis => "disconnect_genesis",
+            ValidationError::BadSignature { .. } => "bad_signature",
+            ValidationError::MissingSignatures { .. } => "missing_signatures",
+        }
+    }
 }
 
 impl From<BlockError> for ChainError {
     fn from(e: BlockError) -> Self {
         match e {
             BlockError::SerializeError(e) => ChainError::SerializeError(e),
+            e => ChainError::InvalidBlock(e),
         }
     }
 }
@@ -0,0 +1,98 @@
+//! Notifications of what happens to the chain and the mempool.
+//!
+//! `Blockchain` publishes a `ChainEvent` on its `EventBus` for every block it
+//! connects or disconnects and every transaction entering or leaving the mempool
+//! without being mined. Subscribers that fall more than `EVENT_BUFFER` events
+//! behind miss the oldest ones, see `tokio::sync::broadcast`.
+
+use crate::address::Address;
+use crate::block::{Block, BlockHash};
+use crate::hash::Hash256;
+use crate::transaction::Transaction;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events kept for each subscriber before the slowest start missing some
+pub const EVENT_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ChainEvent {
+    BlockConnected {
+        height: u64,
+        hash: BlockHash,
+        block: Block,
+    },
+    /// The block left the active chain, during a reorganization for instance
+    BlockDisconnected {
+        height: u64,
+        hash: BlockHash,
+        block: Block,
+    },
+    TransactionAccepted {
+        txid: Hash256,
+        transaction: Transaction,
+    },
+    /// The transaction was dropped from the mempool without being mined
+    TransactionEvicted {
+        txid: Hash256,
+        transaction: Transaction,
+    },
+}
+
+impl ChainEvent {
+    /// Name of the event, as used for its `event` tag
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ChainEvent::BlockConnected { .. } => "block_connected",
+            ChainEvent::BlockDisconnected { .. } => "block_disconnected",
+            ChainEvent::TransactionAccepted { .. } => "transaction_accepted",
+            ChainEvent::TransactionEvicted { .. } => "transaction_evicted",
+        }
+    }
+
+    /// Whether the event involves a transaction sent from or to `address`
+    pub fn touches(&self, address: &Address) -> bool {
+        let involves = |transaction: &Transaction| {
+            transaction.sender_address() == *address || transaction.recipient_address() == *address
+        };
+
+        match self {
+            ChainEvent::BlockConnected { block, .. }
+            | ChainEvent::BlockDisconnected { block, .. } => {
+                block.transactions().iter().any(|tx| involves(tx))
+            }
+            ChainEvent::TransactionAccepted { transaction, .. }
+            | ChainEvent::TransactionEvicted { transaction, .. } => involves(transaction),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ChainEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Sends `event` to the current subscribers, if any
+    pub fn publish(&self, event: ChainEvent) {
+        // Failing only means nobody is listening
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,274 @@
+//! Portable chain file format, used to move chains between nodes and to seed fixtures.
+//!
+//! ```text
+//! magic "BSMC" | version: u8 | block count: u64 LE
+//! then for every block, genesis first:
+//! length: u32 LE | block JSON | checksum: first 4 bytes of SHA-256(SHA-256(block JSON))
+//! ```
+
+use crate::block::Block;
+use crate::chain::{Blockchain, ChainError};
+
+use sha2::Digest;
+use std::io::{Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"BSMC";
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Upper bound on a single frame, so a corrupted length can't make us allocate gigabytes.
+const MAX_FRAME_LEN: u32 = 64 * 1024 * 1024;
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let double_hashed = sha2::Sha256::digest(sha2::Sha256::digest(payload));
+    double_hashed[0..4].try_into().expect("Wrong length")
+}
+
+pub struct ChainWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChainWriter<W> {
+    /// Writes the file header announcing `count` blocks.
+    pub fn new(mut inner: W, count: u64) -> Result<Self, ExportError> {
+        inner.write_all(&MAGIC)?;
+        inner.write_all(&[FORMAT_VERSION])?;
+        inner.write_all(&count.to_le_bytes())?;
+        Ok(Self { inner })
+    }
+
+    pub fn write_block(&mut self, block: &Block) -> Result<(), ExportError> {
+        let payload = serde_json::to_vec(block)?;
+        let len = u32::try_from(payload.len())
+            .ok()
+            .filter(|len| *len <= MAX_FRAME_LEN)
+            .ok_or(ExportError::FrameTooLarge(payload.len()))?;
+
+        self.inner.write_all(&len.to_le_bytes())?;
+        self.inner.write_all(&payload)?;
+        self.inner.write_all(&checksum(&payload))?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, ExportError> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Streams blocks out of a chain file, checking every frame's checksum.
+pub struct ChainReader<R: Read> {
+    inner: R,
+    count: u64,
+    read: u64,
+}
+
+impl<R: Read> ChainReader<R> {
+    /// Reads and checks the file header.
+    pub fn new(mut inner: R) -> Result<Self, ExportError> {
+        let mut magic = [0u8; 4];
+        inner.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(ExportError::BadMagic(magic));
+        }
+
+        let mut version = [0u8; 1];
+        inner.read_exact(&mut version)?;
+        if version[0] != FORMAT_VERSION {
+            return Err(ExportError::UnsupportedVersion(version[0]));
+        }
+
+        let mut count = [0u8; 8];
+        inner.read_exact(&mut count)?;
+
+        Ok(Self {
+            inner,
+            count: u64::from_le_bytes(count),
+            read: 0,
+        })
+    }
+
+    /// Number of blocks announced in the header
+    pub fn block_count(&self) -> u64 {
+        self.count
+    }
+
+    fn read_block(&mut self) -> Result<Block, ExportError> {
+        let mut len = [0u8; 4];
+        self.inner.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len);
+        if len > MAX_FRAME_LEN {
+            return Err(ExportError::FrameTooLarge(len as usize));
+        }
+
+        let mut payload = vec![0u8; len as usize];
+        self.inner.read_exact(&mut payload)?;
+
+        let mut expected = [0u8; 4];
+        self.inner.read_exact(&mut expected)?;
+        if checksum(&payload) != expected {
+            return Err(ExportError::ChecksumMismatch { index: self.read });
+        }
+
+        Ok(serde_json::from_slice(&payload)?)
+    }
+}
+
+impl<R: Read> Iterator for ChainReader<R> {
+    type Item = Result<Block, ExportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.read >= self.count {
+            return None;
+        }
+        let block = self.read_block();
+        self.read += 1;
+        Some(block)
+    }
+}
+
+/// Writes the whole active chain to `out`, calling `progress(written, total)` after each block.
+pub fn export_chain<W: Write>(
+    chain: &Blockchain,
+    out: W,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<u64, ExportError> {
+    let count = chain.height()? + 1;
+    let mut writer = ChainWriter::new(out, count)?;
+
+    for height in 0..count {
+        writer.write_block(&chain.block_at_height(height)?)?;
+        progress(height + 1, count);
+    }
+    writer.finish()?;
+
+    Ok(count)
+}
+
+/// Replays the blocks of a chain file on top of `chain`, each one going through
+/// `Blockchain::verify_and_add_block`.
+///
+/// Blocks the chain already has at the same height are skipped, so importing a file
+/// that extends the local chain only adds the missing blocks. Returns the number of
+/// blocks added, calling `progress(processed, total)` after each block.
+pub fn import_chain<R: Read>(
+    chain: &mut Blockchain,
+    input: R,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<u64, ExportError> {
+    let reader = ChainReader::new(input)?;
+    let count = reader.block_count();
+    let mut added = 0;
+
+    for (height, block) in (0u64..).zip(reader) {
+        let block = block?;
+
+        if height <= chain.height()? {
+            if chain.hash_at_height(height)? != block.hash() {
+                return Err(ExportError::Diverged { height });
+            }
+        } else {
+            chain.verify_and_add_block(block)?;
+            added += 1;
+        }
+
+        progress(height + 1, count);
+    }
+
+    Ok(added)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serialization error: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("not a chain file, magic bytes are {0:?}")]
+    BadMagic([u8; 4]),
+    #[error("unsupported chain file version {0}")]
+    UnsupportedVersion(u8),
+    #[error("frame of {0} bytes exceeds the maximum frame size")]
+    FrameTooLarge(usize),
+    #[error("checksum mismatch in block {index}")]
+    ChecksumMismatch { index: u64 },
+    #[error("block at height {height} differs from the local chain")]
+    Diverged { height: u64 },
+    #[error("chain error: {0}")]
+    Chain(ChainError),
+}
+
+impl From<ChainError> for ExportError {
+    fn from(e: ChainError) -> Self {
+        ExportError::Chain(e)
+    }
+}
+
+impl From<crate::block::BlockError> for ExportError {
+    fn from(e: crate::block::BlockError) -> Self {
+        ExportError::Chain(e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+
+    fn mined_chain(blocks: usize) -> Blockchain {
+        let mut chain = Blockchain::new(Address::named("my_address"), 2);
+        for _ in 0..blocks {
+            chain.mine().unwrap();
+        }
+        chain
+    }
+
+    #[test]
+    fn export_then_import_reproduces_chain() {
+        let source = mined_chain(3);
+        let mut file = Vec::new();
+        let mut reported = Vec::new();
+        export_chain(&source, &mut file, |done, total| {
+            reported.push((done, total))
+        })
+        .unwrap();
+        assert_eq!(reported.last(), Some(&(4, 4)));
+
+        let mut target = Blockchain::new(Address::named("other_address"), 2);
+        let added = import_chain(&mut target, file.as_slice(), |_, _| {}).unwrap();
+
+        assert_eq!(added, 3);
+        assert_eq!(
+            target.last_block().unwrap().hash(),
+            source.last_block().unwrap().hash()
+        );
+
+        // Importing again is a no-op
+        let added = import_chain(&mut target, file.as_slice(), |_, _| {}).unwrap();
+        assert_eq!(added, 0);
+    }
+
+    #[test]
+    fn corrupted_frame_is_rejected() {
+        let source = mined_chain(1);
+        let mut file = Vec::new();
+        export_chain(&source, &mut file, |_, _| {}).unwrap();
+
+        let last = file.len() - 10;
+        file[last] ^= 0xff;
+
+        let mut target = Blockchain::new(Address::named("other_address"), 2);
+        assert!(matches!(
+            import_chain(&mut target, file.as_slice(), |_, _| {}),
+            Err(ExportError::ChecksumMismatch { index: 1 })
+        ));
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        let file = b"NOPE\x01\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        assert!(matches!(
+            ChainReader::new(file.as_slice()),
+            Err(ExportError::BadMagic(_))
+        ));
+    }
+}
@@ -0,0 +1,93 @@
+//! 32 byte SHA-256 digests: block hashes, transaction ids, merkle roots and
+//! snapshot commitments. They are written as 64 lowercase hex digits.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Digest;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hash256([u8; 32]);
+
+impl Hash256 {
+    /// SHA-256 of `data`
+    pub fn digest(data: impl AsRef<[u8]>) -> Self {
+        Self(sha2::Sha256::digest(data).into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Whether every byte is zero, as for the parent of genesis
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 32]
+    }
+}
+
+impl From<[u8; 32]> for Hash256 {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl fmt::Display for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl FromStr for Hash256 {
+    type Err = ParseHashError;
+
+    fn from_str(hash: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0; 32];
+        hex::decode_to_slice(hash, &mut bytes).map_err(|_| ParseHashError(hash.to_string()))?;
+        Ok(Self(bytes))
+    }
+}
+
+impl Serialize for Hash256 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Hash256 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("{0} is not a hex encoded 32 byte hash")]
+pub struct ParseHashError(pub String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_only_64_hex_digits() {
+        let hash = Hash256::digest(b"blocksmith");
+        assert_eq!(hash.to_string().len(), 64);
+        assert_eq!(hash.to_string().parse::<Hash256>().unwrap(), hash);
+        assert_eq!(
+            serde_json::from_str::<Hash256>(&serde_json::to_string(&hash).unwrap()).unwrap(),
+            hash
+        );
+
+        assert!("".parse::<Hash256>().is_err());
+        assert!("abcd".parse::<Hash256>().is_err());
+        assert!("zz".repeat(32).parse::<Hash256>().is_err());
+        assert!(Hash256::default().is_zero());
+    }
+}
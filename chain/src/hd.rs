@@ -0,0 +1,634 @@
+//! Hierarchical deterministic wallets: BIP39 mnemonic phrases and BIP32 key derivation.
+//!
+//! A phrase stretches into a 64 byte seed, the seed into a master extended key,
+//! and every wallet key is a child of that master along a derivation path such
+//! as `m/44'/1'/0'/0/5`. Backing up the phrase backs up every address derived
+//! from it.
+//!
+//! Hardened children (`'`) are only derivable from the private key. Normal
+//! children can also be derived from the extended public key, which lets a
+//! watch-only wallet follow the addresses of an account.
+
+use crate::address::{hash160, Address, KeyFormat, MAINNET_VERSION, TESTNET_VERSION};
+use crate::wallet::Wallet;
+
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, Scalar, SecretKey, SECP256K1};
+use std::fmt;
+use std::str::FromStr;
+
+/// Index offset of hardened children
+pub const HARDENED: u32 = 1 << 31;
+
+/// Coin type of BIP44 paths for the networks of `Address`
+const MAINNET_COIN_TYPE: u32 = 0;
+const TESTNET_COIN_TYPE: u32 = 1;
+
+/// Version, depth, parent fingerprint, child number, chain code and key
+const SERIALIZED_LEN: usize = 78;
+
+const MAINNET_PRIVATE: [u8; 4] = [0x04, 0x88, 0xad, 0xe4];
+const MAINNET_PUBLIC: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TESTNET_PRIVATE: [u8; 4] = [0x04, 0x35, 0x83, 0x94];
+const TESTNET_PUBLIC: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChildNumber {
+    Normal(u32),
+    Hardened(u32),
+}
+
+impl ChildNumber {
+    /// Index as serialized, with the top bit set for hardened children
+    pub fn to_u32(&self) -> Result<u32, HdError> {
+        match *self {
+            Self::Normal(index) if index < HARDENED => Ok(index),
+            Self::Hardened(index) if index < HARDENED => Ok(index | HARDENED),
+            Self::Normal(index) | Self::Hardened(index) => Err(HdError::InvalidIndex(index)),
+        }
+    }
+
+    pub fn is_hardened(&self) -> bool {
+        matches!(self, Self::Hardened(_))
+    }
+}
+
+impl From<u32> for ChildNumber {
+    fn from(index: u32) -> Self {
+        if index & HARDENED == 0 {
+            Self::Normal(index)
+        } else {
+            Self::Hardened(index ^ HARDENED)
+        }
+    }
+}
+
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal(index) => write!(f, "{}", index),
+            Self::Hardened(index) => write!(f, "{}'", index),
+        }
+    }
+}
+
+/// Children to follow from the master key, written `m/44'/1'/0'/0/5`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DerivationPath(Vec<ChildNumber>);
+
+impl DerivationPath {
+    /// `m/44'/coin'/account'/change/index`, for base58check addresses
+    pub fn bip44(version: u8, account: u32, change: bool, index: u32) -> Self {
+        Self::standard(44, version, account, change, index)
+    }
+
+    /// `m/84'/coin'/account'/change/index`, for bech32 addresses
+    pub fn bip84(version: u8, account: u32, change: bool, index: u32) -> Self {
+        Self::standard(84, version, account, change, index)
+    }
+
+    fn standard(purpose: u32, version: u8, account: u32, change: bool, index: u32) -> Self {
+        let coin_type = if version == MAINNET_VERSION {
+            MAINNET_COIN_TYPE
+        } else {
+            TESTNET_COIN_TYPE
+        };
+        Self(vec![
+            ChildNumber::Hardened(purpose),
+            ChildNumber::Hardened(coin_type),
+            ChildNumber::Hardened(account),
+            ChildNumber::Normal(change as u32),
+            ChildNumber::Normal(index),
+        ])
+    }
+
+    pub fn children(&self) -> &[ChildNumber] {
+        &self.0
+    }
+}
+
+impl From<Vec<ChildNumber>> for DerivationPath {
+    fn from(children: Vec<ChildNumber>) -> Self {
+        Self(children)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for child in &self.0 {
+            write!(f, "/{}", child)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = HdError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let invalid = || HdError::InvalidPath(path.to_string());
+
+        let mut parts = path.split('/');
+        if parts.next() != Some("m") {
+            return Err(invalid());
+        }
+        parts
+            .map(|part| {
+                let (index, hardened) = match part.strip_suffix(['\'', 'h']) {
+                    Some(index) => (index, true),
+                    None => (part, false),
+                };
+                let index: u32 = index.parse().map_err(|_| invalid())?;
+                if index >= HARDENED {
+                    return Err(HdError::InvalidIndex(index));
+                }
+                Ok(if hardened {
+                    ChildNumber::Hardened(index)
+                } else {
+                    ChildNumber::Normal(index)
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// Fields shared by extended private and public keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeyInfo {
+    /// Address version byte of the network the key serializes for
+    version: u8,
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: ChildNumber,
+    chain_code: [u8; 32],
+}
+
+impl KeyInfo {
+    fn child(&self, parent_key: &PublicKey, child_number: ChildNumber, chain_code: &[u8]) -> Self {
+        Self {
+            version: self.version,
+            depth: self.depth.saturating_add(1),
+            parent_fingerprint: fingerprint(parent_key),
+            child_number,
+            chain_code: chain_code.try_into().expect("Wrong length"),
+        }
+    }
+
+    fn serialize(&self, prefix: [u8; 4], key: &[u8; 33]) -> Result<String, fmt::Error> {
+        let mut serialized = Vec::with_capacity(SERIALIZED_LEN);
+        serialized.extend_from_slice(&prefix);
+        serialized.push(self.depth);
+        serialized.extend_from_slice(&self.parent_fingerprint);
+        let child_number = self.child_number.to_u32().map_err(|_| fmt::Error)?;
+        serialized.extend_from_slice(&child_number.to_be_bytes());
+        serialized.extend_from_slice(&self.chain_code);
+        serialized.extend_from_slice(key);
+        Ok(bs58::encode(serialized).with_check().into_string())
+    }
+
+    /// Splits a serialized key into its prefix, fields and 33 byte key
+    fn deserialize(encoded: &str) -> Result<([u8; 4], Self, [u8; 33]), HdError> {
+        let invalid = |reason: &str| HdError::InvalidExtendedKey(reason.to_string());
+
+        let decoded = bs58::decode(encoded)
+            .with_check(None)
+            .into_vec()
+            .map_err(|err| HdError::InvalidExtendedKey(err.to_string()))?;
+        if decoded.len() != SERIALIZED_LEN {
+            return Err(invalid("wrong length"));
+        }
+
+        let prefix: [u8; 4] = decoded[0..4].try_into().expect("Wrong length");
+        let version = match prefix {
+            MAINNET_PRIVATE | MAINNET_PUBLIC => MAINNET_VERSION,
+            TESTNET_PRIVATE | TESTNET_PUBLIC => TESTNET_VERSION,
+            _ => return Err(invalid("unknown version")),
+        };
+        let info = Self {
+            version,
+            depth: decoded[4],
+            parent_fingerprint: decoded[5..9].try_into().expect("Wrong length"),
+            child_number: u32::from_be_bytes(decoded[9..13].try_into().expect("Wrong length"))
+                .into(),
+            chain_code: decoded[13..45].try_into().expect("Wrong length"),
+        };
+        Ok((
+            prefix,
+            info,
+            decoded[45..].try_into().expect("Wrong length"),
+        ))
+    }
+}
+
+/// First 4 bytes of the hash the address of `public_key` is made of
+fn fingerprint(public_key: &PublicKey) -> [u8; 4] {
+    hash160(KeyFormat::Compressed.serialize(public_key))[0..4]
+        .try_into()
+        .expect("Wrong length")
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<sha2::Sha512>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in data {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Private key with the chain code its children are derived with, `xprv`/`tprv`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPrivateKey {
+    info: KeyInfo,
+    private_key: SecretKey,
+}
+
+impl ExtendedPrivateKey {
+    /// Master key of `seed`, serializing for the network of the address `version`
+    pub fn new_master(seed: &[u8], version: u8) -> Result<Self, HdError> {
+        let hashed = hmac_sha512(b"Bitcoin seed", &[seed]);
+        let (key, chain_code) = hashed.split_at(32);
+        Ok(Self {
+            info: KeyInfo {
+                version,
+                depth: 0,
+                parent_fingerprint: [0; 4],
+                child_number: ChildNumber::Normal(0),
+                chain_code: chain_code.try_into().expect("Wrong length"),
+            },
+            private_key: SecretKey::from_slice(key).map_err(|_| HdError::InvalidDerivedKey)?,
+        })
+    }
+
+    pub fn derive_child(&self, child_number: ChildNumber) -> Result<Self, HdError> {
+        let index = child_number.to_u32()?;
+        let public_key = self.private_key.public_key(SECP256K1);
+        let hashed = if child_number.is_hardened() {
+            hmac_sha512(
+                &self.info.chain_code,
+                &[&[0], &self.private_key.secret_bytes(), &index.to_be_bytes()],
+            )
+        } else {
+            hmac_sha512(
+                &self.info.chain_code,
+                &[&public_key.serialize(), &index.to_be_bytes()],
+            )
+        };
+        let (tweak, chain_code) = hashed.split_at(32);
+
+        let tweak = Scalar::from_be_bytes(tweak.try_into().expect("Wrong length"))
+            .map_err(|_| HdError::InvalidDerivedKey)?;
+        let private_key = self
+            .private_key
+            .add_tweak(&tweak)
+            .map_err(|_| HdError::InvalidDerivedKey)?;
+
+        Ok(Self {
+            info: self.info.child(&public_key, child_number, chain_code),
+            private_key,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, HdError> {
+        path.children()
+            .iter()
+            .try_fold(self.clone(), |key, child| key.derive_child(*child))
+    }
+
+    pub fn extended_public_key(&self) -> ExtendedPublicKey {
+        ExtendedPublicKey {
+            info: self.info,
+            public_key: self.private_key.public_key(SECP256K1),
+        }
+    }
+
+    pub fn private_key(&self) -> &SecretKey {
+        &self.private_key
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.info.depth
+    }
+
+    /// Wallet of this key, with a compressed key address
+    pub fn to_wallet(&self) -> Wallet {
+        Wallet::new(self.private_key.public_key(SECP256K1), self.private_key)
+    }
+}
+
+impl fmt::Display for ExtendedPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.info.version == MAINNET_VERSION {
+            MAINNET_PRIVATE
+        } else {
+            TESTNET_PRIVATE
+        };
+        let mut key = [0; 33];
+        key[1..].copy_from_slice(&self.private_key.secret_bytes());
+        write!(f, "{}", self.info.serialize(prefix, &key)?)
+    }
+}
+
+impl FromStr for ExtendedPrivateKey {
+    type Err = HdError;
+
+    fn from_str(encoded: &str) -> Result<Self, Self::Err> {
+        let (prefix, info, key) = KeyInfo::deserialize(encoded)?;
+        if prefix != MAINNET_PRIVATE && prefix != TESTNET_PRIVATE || key[0] != 0 {
+            return Err(HdError::InvalidExtendedKey(String::from(
+                "not an extended private key",
+            )));
+        }
+        Ok(Self {
+            info,
+            private_key: SecretKey::from_slice(&key[1..])
+                .map_err(|err| HdError::InvalidExtendedKey(err.to_string()))?,
+        })
+    }
+}
+
+/// Public key with the chain code of its normal children, `xpub`/`tpub`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPublicKey {
+    info: KeyInfo,
+    public_key: PublicKey,
+}
+
+impl ExtendedPublicKey {
+    /// Derives a normal child, hardened ones need the private key
+    pub fn derive_child(&self, child_number: ChildNumber) -> Result<Self, HdError> {
+        if child_number.is_hardened() {
+            return Err(HdError::HardenedFromPublic);
+        }
+        let index = child_number.to_u32()?;
+        let hashed = hmac_sha512(
+            &self.info.chain_code,
+            &[&self.public_key.serialize(), &index.to_be_bytes()],
+        );
+        let (tweak, chain_code) = hashed.split_at(32);
+
+        let tweak = Scalar::from_be_bytes(tweak.try_into().expect("Wrong length"))
+            .map_err(|_| HdError::InvalidDerivedKey)?;
+        let public_key = self
+            .public_key
+            .add_exp_tweak(SECP256K1, &tweak)
+            .map_err(|_| HdError::InvalidDerivedKey)?;
+
+        Ok(Self {
+            info: self.info.child(&self.public_key, child_number, chain_code),
+            public_key,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, HdError> {
+        path.children()
+            .iter()
+            .try_fold(self.clone(), |key, child| key.derive_child(*child))
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// Compressed key address of this key
+    pub fn address(&self) -> Address {
+        Address::from_public_key(&self.public_key, KeyFormat::Compressed)
+    }
+}
+
+impl fmt::Display for ExtendedPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.info.version == MAINNET_VERSION {
+            MAINNET_PUBLIC
+        } else {
+            TESTNET_PUBLIC
+        };
+        write!(
+            f,
+            "{}",
+            self.info.serialize(prefix, &self.public_key.serialize())?
+        )
+    }
+}
+
+impl FromStr for ExtendedPublicKey {
+    type Err = HdError;
+
+    fn from_str(encoded: &str) -> Result<Self, Self::Err> {
+        let (prefix, info, key) = KeyInfo::deserialize(encoded)?;
+        if prefix != MAINNET_PUBLIC && prefix != TESTNET_PUBLIC {
+            return Err(HdError::InvalidExtendedKey(String::from(
+                "not an extended public key",
+            )));
+        }
+        Ok(Self {
+            info,
+            public_key: PublicKey::from_slice(&key)
+                .map_err(|err| HdError::InvalidExtendedKey(err.to_string()))?,
+        })
+    }
+}
+
+/// Every key of a user, recoverable from one mnemonic phrase
+#[derive(Debug, Clone)]
+pub struct HdWallet {
+    mnemonic: Mnemonic,
+    master: ExtendedPrivateKey,
+}
+
+impl HdWallet {
+    /// Wallet of a fresh random phrase of 12, 15, 18, 21 or 24 words
+    pub fn generate(word_count: usize, passphrase: &str) -> Result<Self, HdError> {
+        Ok(Self::from_mnemonic(
+            Mnemonic::generate(word_count)?,
+            passphrase,
+        ))
+    }
+
+    /// Restores the wallet of `phrase`, checking its words and checksum.
+    /// `passphrase` is the optional BIP39 passphrase, empty if none
+    pub fn from_phrase(phrase: &str, passphrase: &str) -> Result<Self, HdError> {
+        Ok(Self::from_mnemonic(Mnemonic::parse(phrase)?, passphrase))
+    }
+
+    fn from_mnemonic(mnemonic: Mnemonic, passphrase: &str) -> Self {
+        let seed = mnemonic.to_seed(passphrase);
+        let master = ExtendedPrivateKey::new_master(&seed, TESTNET_VERSION)
+            .expect("A seed hashes to an invalid key with negligible odds");
+        Self { mnemonic, master }
+    }
+
+    /// The words to write down
+    pub fn phrase(&self) -> String {
+        self.mnemonic.to_string()
+    }
+
+    pub fn master(&self) -> &ExtendedPrivateKey {
+        &self.master
+    }
+
+    pub fn wallet_at(&self, path: &DerivationPath) -> Result<Wallet, HdError> {
+        Ok(self.master.derive_path(path)?.to_wallet())
+    }
+
+    /// Wallet of the `index`th receiving address of `account`, at the BIP44 path
+    pub fn receiving_wallet(&self, account: u32, index: u32) -> Result<Wallet, HdError> {
+        self.wallet_at(&DerivationPath::bip44(
+            TESTNET_VERSION,
+            account,
+            false,
+            index,
+        ))
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum HdError {
+    #[error("invalid mnemonic: {0}")]
+    Mnemonic(#[from] bip39::Error),
+    #[error("{0} is not a derivation path like m/44'/1'/0'/0/0")]
+    InvalidPath(String),
+    #[error("child index {0} is out of range")]
+    InvalidIndex(u32),
+    #[error("hardened children cannot be derived from a public key")]
+    HardenedFromPublic,
+    #[error("derived key is invalid, skip to the next index")]
+    InvalidDerivedKey,
+    #[error("invalid extended key: {0}")]
+    InvalidExtendedKey(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(path: &str) -> DerivationPath {
+        path.parse().unwrap()
+    }
+
+    #[test]
+    fn derives_the_bip32_test_vector() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivateKey::new_master(&seed, MAINNET_VERSION).unwrap();
+        assert_eq!(
+            master.to_string(),
+            "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi"
+        );
+        assert_eq!(
+            master.extended_public_key().to_string(),
+            "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8"
+        );
+
+        let child = master.derive_path(&path("m/0'/1")).unwrap();
+        assert_eq!(
+            child.to_string(),
+            "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs"
+        );
+        assert_eq!(
+            child.extended_public_key().to_string(),
+            "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ"
+        );
+
+        let deepest = master.derive_path(&path("m/0h/1/2h/2/1000000000")).unwrap();
+        assert_eq!(deepest.depth(), 5);
+        assert_eq!(
+            deepest.to_string(),
+            "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76"
+        );
+        assert_eq!(
+            deepest.to_string().parse::<ExtendedPrivateKey>().unwrap(),
+            deepest
+        );
+    }
+
+    #[test]
+    fn public_keys_derive_the_same_normal_children() {
+        let wallet = HdWallet::generate(12, "").unwrap();
+        let account = wallet.master().derive_path(&path("m/44'/1'/0'")).unwrap();
+        let account_public = account.extended_public_key();
+
+        let from_private = account.derive_path(&path("m/0/7")).unwrap();
+        let from_public = account_public.derive_path(&path("m/0/7")).unwrap();
+        assert_eq!(from_private.extended_public_key(), from_public);
+        assert_eq!(
+            from_public.address(),
+            wallet.receiving_wallet(0, 7).unwrap().address()
+        );
+
+        assert_eq!(
+            account_public.derive_child(ChildNumber::Hardened(0)),
+            Err(HdError::HardenedFromPublic)
+        );
+        let serialized = account_public.to_string();
+        assert!(serialized.starts_with("tpub"));
+        assert_eq!(
+            serialized.parse::<ExtendedPublicKey>().unwrap(),
+            account_public
+        );
+        assert!(serialized.parse::<ExtendedPrivateKey>().is_err());
+    }
+
+    #[test]
+    fn phrases_restore_the_standard_addresses() {
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let wallet = HdWallet::from_phrase(phrase, "TREZOR").unwrap();
+        assert_eq!(wallet.phrase(), phrase);
+        let master = ExtendedPrivateKey::new_master(
+            &Mnemonic::parse(phrase).unwrap().to_seed("TREZOR"),
+            MAINNET_VERSION,
+        )
+        .unwrap();
+        assert_eq!(
+            master.to_string(),
+            "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF"
+        );
+
+        let wallet = HdWallet::from_phrase(phrase, "").unwrap();
+        let legacy = wallet
+            .wallet_at(&DerivationPath::bip44(MAINNET_VERSION, 0, false, 0))
+            .unwrap();
+        assert_eq!(
+            legacy.address().hash(),
+            Address::parse("1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA")
+                .unwrap()
+                .hash()
+        );
+        let segwit = wallet
+            .wallet_at(&DerivationPath::bip84(MAINNET_VERSION, 0, false, 0))
+            .unwrap();
+        assert_eq!(
+            segwit.bech32_address().hash(),
+            Address::parse("bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu")
+                .unwrap()
+                .hash()
+        );
+
+        let restored = HdWallet::from_phrase(&wallet.phrase(), "").unwrap();
+        assert_eq!(
+            restored.receiving_wallet(3, 9).unwrap().address(),
+            wallet.receiving_wallet(3, 9).unwrap().address()
+        );
+        assert!(matches!(
+            HdWallet::from_phrase("abandon abandon abandon", ""),
+            Err(HdError::Mnemonic(_))
+        ));
+    }
+
+    #[test]
+    fn paths_parse_both_hardened_notations() {
+        assert_eq!(
+            path("m/44'/1'/0'/0/5"),
+            DerivationPath::bip44(TESTNET_VERSION, 0, false, 5)
+        );
+        assert_eq!(path("m/84h/0h/2h/1/0").to_string(), "m/84'/0'/2'/1/0");
+        assert_eq!(path("m"), DerivationPath::default());
+        assert!("44'/0'".parse::<DerivationPath>().is_err());
+        assert!("m/x".parse::<DerivationPath>().is_err());
+        assert_eq!(
+            "m/2147483648".parse::<DerivationPath>(),
+            Err(HdError::InvalidIndex(HARDENED))
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Wallet keys encrypted at rest under a passphrase.
+//!
+//! The passphrase is stretched with scrypt into a 32 byte key, which seals the
+//! private key with ChaCha20-Poly1305. The address is authenticated along with
+//! it, so a keystore whose address was edited no longer unlocks. Salt and nonce
+//! are drawn afresh every time the key is sealed.
+
+use crate::address::{Address, KeyFormat};
+use crate::wallet::Wallet;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use secp256k1::rand::{rngs::OsRng, RngCore};
+use secp256k1::{SecretKey, SECP256K1};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Version of the file format
+const KEYSTORE_VERSION: u32 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Cost of deriving the encryption key from the passphrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScryptParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for ScryptParams {
+    /// 32 MiB of memory, a fraction of a second per attempt
+    fn default() -> Self {
+        Self {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "lowercase")]
+enum Kdf {
+    Scrypt {
+        #[serde(flatten)]
+        params: ScryptParams,
+        #[serde(with = "hex")]
+        salt: Vec<u8>,
+    },
+}
+
+impl Kdf {
+    fn derive_key(&self, passphrase: &str) -> Result<Key, KeystoreError> {
+        let Self::Scrypt { params, salt } = self;
+        let scrypt_params = scrypt::Params::new(params.log_n, params.r, params.p, 32)
+            .map_err(|err| KeystoreError::InvalidParams(err.to_string()))?;
+
+        let mut key = Key::default();
+        scrypt::scrypt(passphrase.as_bytes(), salt, &scrypt_params, &mut key)
+            .map_err(|err| KeystoreError::InvalidParams(err.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// Sealed private key, as stored on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SealedKey {
+    version: u32,
+    address: Address,
+    key_format: KeyFormat,
+    kdf: Kdf,
+    #[serde(with = "hex")]
+    nonce: Vec<u8>,
+    /// Private key and authentication tag
+    #[serde(with = "hex")]
+    ciphertext: Vec<u8>,
+}
+
+impl SealedKey {
+    fn seal(
+        wallet: &Wallet,
+        passphrase: &str,
+        params: ScryptParams,
+    ) -> Result<Self, KeystoreError> {
+        let mut salt = vec![0; SALT_LEN];
+        let mut nonce = vec![0; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let kdf = Kdf::Scrypt { params, salt };
+        let cipher = ChaCha20Poly1305::new(&kdf.derive_key(passphrase)?);
+        let address = wallet.address();
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &wallet.private_key().secret_bytes(),
+                    aad: address.to_string().as_bytes(),
+                },
+            )
+            .expect("Encrypting a key in memory cannot fail");
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            address,
+            key_format: wallet.key_format(),
+            kdf,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    fn open(&self, passphrase: &str) -> Result<Wallet, KeystoreError> {
+        if self.nonce.len() != NONCE_LEN {
+            return Err(KeystoreError::WrongPassphrase);
+        }
+        let cipher = ChaCha20Poly1305::new(&self.kdf.derive_key(passphrase)?);
+        let secret = cipher
+            .decrypt(
+                Nonce::from_slice(&self.nonce),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad: self.address.to_string().as_bytes(),
+                },
+            )
+            .map_err(|_| KeystoreError::WrongPassphrase)?;
+
+        let private_key = SecretKey::from_slice(&secret).map_err(|_| KeystoreError::InvalidKey)?;
+        let wallet = Wallet::with_key_format(
+            private_key.public_key(SECP256K1),
+            private_key,
+            self.key_format,
+        );
+        if wallet.address() != self.address {
+            return Err(KeystoreError::InvalidKey);
+        }
+        Ok(wallet)
+    }
+
+    fn params(&self) -> ScryptParams {
+        let Kdf::Scrypt { params, .. } = self.kdf;
+        params
+    }
+}
+
+/// A wallet whose private key is only held in memory while unlocked
+#[derive(Debug)]
+pub struct Keystore {
+    sealed: SealedKey,
+    unlocked: Option<Wallet>,
+}
+
+impl Keystore {
+    /// Seals `wallet` under `passphrase`, staying unlocked
+    pub fn create(wallet: Wallet, passphrase: &str) -> Result<Self, KeystoreError> {
+        Self::create_with_params(wallet, passphrase, ScryptParams::default())
+    }
+
+    pub fn create_with_params(
+        wallet: Wallet,
+        passphrase: &str,
+        params: ScryptParams,
+    ) -> Result<Self, KeystoreError> {
+        Ok(Self {
+            sealed: SealedKey::seal(&wallet, passphrase, params)?,
+            unlocked: Some(wallet),
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        self.sealed.address
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.unlocked.is_none()
+    }
+
+    /// The wallet, while unlocked
+    pub fn wallet(&self) -> Option<&Wallet> {
+        self.unlocked.as_ref()
+    }
+
+    pub fn unlock(&mut self, passphrase: &str) -> Result<&Wallet, KeystoreError> {
+        let wallet = self.sealed.open(passphrase)?;
+        Ok(self.unlocked.insert(wallet))
+    }
+
+    /// Forgets the decrypted private key
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+    }
+
+    /// Seals the key again under `new`, once `old` is checked to open it
+    pub fn change_passphrase(&mut self, old: &str, new: &str) -> Result<(), KeystoreError> {
+        let wallet = self.sealed.open(old)?;
+        self.sealed = SealedKey::seal(&wallet, new, self.sealed.params())?;
+        Ok(())
+    }
+
+    /// Writes the sealed key, never the unlocked one
+    pub fn write_to<W: Write>(&self, out: W) -> Result<(), KeystoreError> {
+        serde_json::to_writer_pretty(out, &self.sealed)?;
+        Ok(())
+    }
+
+    /// Reads a keystore, locked
+    pub fn read_from<R: Read>(input: R) -> Result<Self, KeystoreError> {
+        Self::locked(serde_json::from_reader(input)?)
+    }
+
+    fn locked(sealed: SealedKey) -> Result<Self, KeystoreError> {
+        if sealed.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(sealed.version));
+        }
+        Ok(Self {
+            sealed,
+            unlocked: None,
+        })
+    }
+}
+
+impl Serialize for Keystore {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.sealed.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Keystore {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Self::locked(SealedKey::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeystoreError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serialization error: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("wrong passphrase or corrupted keystore")]
+    WrongPassphrase,
+    #[error("keystore does not hold the key of its address")]
+    InvalidKey,
+    #[error("invalid key derivation parameters: {0}")]
+    InvalidParams(String),
+    #[error("unsupported keystore version {0}")]
+    UnsupportedVersion(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap enough for tests, far too cheap for real keys
+    const TEST_PARAMS: ScryptParams = ScryptParams {
+        log_n: 4,
+        r: 8,
+        p: 1,
+    };
+
+    #[test]
+    fn keys_unlock_only_with_the_passphrase() {
+        let wallet = Wallet::generate_new();
+        let address = wallet.address();
+        let mut keystore =
+            Keystore::create_with_params(wallet, "correct horse", TEST_PARAMS).unwrap();
+        assert!(!keystore.is_locked());
+
+        let mut file = Vec::new();
+        keystore.write_to(&mut file).unwrap();
+        let json = String::from_utf8(file.clone()).unwrap();
+        assert!(json.contains(&address.to_string()));
+        assert!(json.contains("\"scrypt\""));
+
+        keystore.lock();
+        assert!(keystore.wallet().is_none());
+
+        let mut restored = Keystore::read_from(file.as_slice()).unwrap();
+        assert!(restored.is_locked());
+        assert!(matches!(
+            restored.unlock("wrong horse"),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+        assert_eq!(restored.unlock("correct horse").unwrap().address(), address);
+        assert_eq!(restored.address(), address);
+    }
+
+    #[test]
+    fn changing_the_passphrase_reseals_the_key() {
+        let wallet = Wallet::generate_new();
+        let address = wallet.address();
+        let mut keystore = Keystore::create_with_params(wallet, "old", TEST_PARAMS).unwrap();
+        let before = serde_json::to_value(&keystore).unwrap();
+
+        assert!(matches!(
+            keystore.change_passphrase("not old", "new"),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+        keystore.change_passphrase("old", "new").unwrap();
+        let after = serde_json::to_value(&keystore).unwrap();
+        assert_ne!(before["kdf"]["salt"], after["kdf"]["salt"]);
+        assert_eq!(after["kdf"]["log_n"], 4);
+
+        assert!(keystore.unlock("old").is_err());
+        assert_eq!(keystore.unlock("new").unwrap().address(), address);
+    }
+
+    #[test]
+    fn edited_keystores_do_not_unlock() {
+        let wallet = Wallet::generate_new();
+        let keystore = Keystore::create_with_params(wallet, "pass", TEST_PARAMS).unwrap();
+        let mut file = serde_json::to_value(&keystore).unwrap();
+        file["address"] = serde_json::to_value(Wallet::generate_new().address()).unwrap();
+
+        let mut edited = Keystore::read_from(file.to_string().as_bytes()).unwrap();
+        assert!(matches!(
+            edited.unlock("pass"),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+
+        file["version"] = serde_json::json!(2);
+        assert!(matches!(
+            Keystore::read_from(file.to_string().as_bytes()),
+            Err(KeystoreError::UnsupportedVersion(2))
+        ));
+    }
+}
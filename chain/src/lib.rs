@@ -1,15 +1,28 @@
 use crate::{chain::Blockchain, wallet::Wallet};
 
+pub mod address;
 pub mod block;
 pub mod chain;
+pub mod events;
+pub mod export;
+pub mod hash;
+pub mod hd;
+pub mod keystore;
+pub mod message;
+pub mod miner;
+pub mod multisig;
+pub mod snapshot;
+pub mod state;
+pub mod store;
 pub mod transaction;
 pub mod wallet;
+pub mod wif;
 
 pub const MINING_DIFFICULTY: usize = 4;
 
 pub fn run() -> Result<(), Box<dyn std::error::Error>> {
     let wallet_a = Wallet::generate_new();
-    let a_address = wallet_a.address();
+    let _a_address = wallet_a.address();
 
     // let wallet_b = Wallet::generate_new();
     // let b_address = wallet_b.address();
@@ -17,7 +30,7 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
     // let wallet_c = Wallet::generate_new();
     // let c_address = wallet_c.address();
 
-    let mut my_chain = Blockchain::new(String::from("my_address"), MINING_DIFFICULTY);
+    let mut my_chain = Blockchain::new(wallet_a.address(), MINING_DIFFICULTY);
 
     println!("chain {:?}", my_chain);
 
@@ -0,0 +1,142 @@
+//! Signed messages, proving ownership of an address off-chain.
+//!
+//! Messages are hashed the way Bitcoin wallets do, double SHA-256 over a fixed
+//! prefix and the length prefixed message, so signatures check out in other
+//! tools. A signature is 65 bytes in base64: a header byte carrying the
+//! recovery id and the key format, then the compact signature.
+
+use crate::address::{Address, KeyFormat};
+use crate::hash::Hash256;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, SecretKey, SECP256K1};
+
+const MESSAGE_PREFIX: &str = "Bitcoin Signed Message:\n";
+
+const SIGNATURE_LEN: usize = 65;
+const HEADER_BASE: u8 = 27;
+const COMPRESSED_OFFSET: u8 = 4;
+
+/// Hash signed in place of `message`
+pub fn message_hash(message: &str) -> Hash256 {
+    let mut data = Vec::with_capacity(MESSAGE_PREFIX.len() + message.len() + 10);
+    write_compact_size(&mut data, MESSAGE_PREFIX.len());
+    data.extend_from_slice(MESSAGE_PREFIX.as_bytes());
+    write_compact_size(&mut data, message.len());
+    data.extend_from_slice(message.as_bytes());
+    Hash256::digest(Hash256::digest(data).as_bytes())
+}
+
+fn write_compact_size(out: &mut Vec<u8>, length: usize) {
+    match length {
+        0..=0xfc => out.push(length as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(length as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(length as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&(length as u64).to_le_bytes());
+        }
+    }
+}
+
+/// Signs `message` with `private_key`, whose address is derived from the key
+/// serialized in `key_format`
+pub fn sign(private_key: &SecretKey, key_format: KeyFormat, message: &str) -> String {
+    let digest = Message::from_digest(*message_hash(message).as_bytes());
+    let (recovery_id, compact) = SECP256K1
+        .sign_ecdsa_recoverable(&digest, private_key)
+        .serialize_compact();
+
+    let mut header = HEADER_BASE + recovery_id.to_i32() as u8;
+    if key_format == KeyFormat::Compressed {
+        header += COMPRESSED_OFFSET;
+    }
+    let mut signature = Vec::with_capacity(SIGNATURE_LEN);
+    signature.push(header);
+    signature.extend_from_slice(&compact);
+    STANDARD.encode(signature)
+}
+
+/// Whether `signature` over `message` was made with the key of `address`.
+/// Malformed signatures are errors, well formed ones by another key are not
+pub fn verify(address: &Address, message: &str, signature: &str) -> Result<bool, MessageError> {
+    let signature = STANDARD
+        .decode(signature)
+        .map_err(|_| MessageError::InvalidBase64)?;
+    if signature.len() != SIGNATURE_LEN {
+        return Err(MessageError::InvalidLength(signature.len()));
+    }
+
+    let header = signature[0];
+    let (recovery_id, key_format) = match header {
+        27..=30 => (header - HEADER_BASE, KeyFormat::Uncompressed),
+        31..=34 => (
+            header - HEADER_BASE - COMPRESSED_OFFSET,
+            KeyFormat::Compressed,
+        ),
+        _ => return Err(MessageError::InvalidHeader(header)),
+    };
+    let recovery_id = RecoveryId::from_i32(recovery_id as i32)
+        .map_err(|_| MessageError::InvalidHeader(header))?;
+    let signature = RecoverableSignature::from_compact(&signature[1..], recovery_id)
+        .map_err(|_| MessageError::InvalidSignature)?;
+
+    let digest = Message::from_digest(*message_hash(message).as_bytes());
+    match SECP256K1.recover_ecdsa(&digest, &signature) {
+        Ok(public_key) => Ok(address.is_derived_from(&public_key, key_format)),
+        Err(_) => Ok(false),
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum MessageError {
+    #[error("signature is not base64 encoded")]
+    InvalidBase64,
+    #[error("signature decodes to {0} bytes instead of 65")]
+    InvalidLength(usize),
+    #[error("unknown signature header byte {0}")]
+    InvalidHeader(u8),
+    #[error("signature is out of range")]
+    InvalidSignature,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths_are_written_as_compact_sizes() {
+        let written = |length| {
+            let mut out = Vec::new();
+            write_compact_size(&mut out, length);
+            out
+        };
+        assert_eq!(written(0xfc), [0xfc]);
+        assert_eq!(written(0xfd), [0xfd, 0xfd, 0x00]);
+        assert_eq!(written(0x1_0000), [0xfe, 0x00, 0x00, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn malformed_signatures_are_errors() {
+        let address = Address::new(0x6f, [1; 20]);
+        assert_eq!(
+            verify(&address, "hello", "not base64!"),
+            Err(MessageError::InvalidBase64)
+        );
+        assert_eq!(
+            verify(&address, "hello", &STANDARD.encode([31; 64])),
+            Err(MessageError::InvalidLength(64))
+        );
+        assert_eq!(
+            verify(&address, "hello", &STANDARD.encode([35; 65])),
+            Err(MessageError::InvalidHeader(35))
+        );
+    }
+}
@@ -0,0 +1,423 @@
+//! Background mining, keeping the chain lock free while nonces are searched.
+//!
+//! The miner takes a block template under the lock, releases it, and searches
+//! for a nonce on its own thread. Whenever the tip changes or a transaction
+//! enters the mempool the template is outdated, so the search is dropped and
+//! restarted on a fresh one.
+
+use crate::block::{meets_difficulty, Block, BlockHash};
+use crate::chain::Blockchain;
+use crate::events::ChainEvent;
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{error::TryRecvError, Receiver};
+
+/// Nonces a search thread tries between two checks for cancellation
+const CHECK_INTERVAL: u64 = 1024;
+/// How often a search polls for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct MinerStatus {
+    pub running: bool,
+    /// Height of the block being searched for
+    pub height: Option<u64>,
+    /// Number of transactions in the current template, the reward included
+    pub transactions: usize,
+    pub threads: usize,
+    /// Hashes per second over the current template
+    pub hash_rate: f64,
+    /// Templates started since the miner was created
+    pub templates: u64,
+    pub blocks_mined: u64,
+    pub last_block: Option<BlockHash>,
+    /// Why the miner stopped on its own, if it did
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    stop: AtomicBool,
+    status: Mutex<MinerStatus>,
+}
+
+impl Shared {
+    fn update(&self, f: impl FnOnce(&mut MinerStatus)) {
+        if let Ok(mut status) = self.status.lock() {
+            f(&mut status);
+        }
+    }
+}
+
+/// Mines blocks on top of a shared chain from a dedicated thread
+#[derive(Debug)]
+pub struct Miner {
+    chain: Arc<Mutex<Blockchain>>,
+    shared: Arc<Shared>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Miner {
+    pub fn new(chain: Arc<Mutex<Blockchain>>) -> Self {
+        Self {
+            chain,
+            shared: Arc::new(Shared::default()),
+            worker: Mutex::new(None),
+        }
+    }
+
+    /// Starts mining, returns false if the miner was already running
+    pub fn start(&self) -> bool {
+        let mut worker = self.worker.lock().unwrap_or_else(|e| e.into_inner());
+        if worker.as_ref().is_some_and(|handle| !handle.is_finished()) {
+            return false;
+        }
+
+        self.shared.stop.store(false, Ordering::SeqCst);
+        self.shared.update(|status| {
+            status.running = true;
+            status.error = None;
+        });
+
+        let chain = self.chain.clone();
+        let shared = self.shared.clone();
+        *worker = Some(std::thread::spawn(move || mine(chain, shared)));
+        true
+    }
+
+    /// Stops mining and waits for the worker to exit, returns false if it wasn't running
+    pub fn stop(&self) -> bool {
+        let handle = self.worker.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let Some(handle) = handle else {
+            return false;
+        };
+
+        self.shared.stop.store(true, Ordering::SeqCst);
+        let _ = handle.join();
+        true
+    }
+
+    pub fn status(&self) -> MinerStatus {
+        self.shared
+            .status
+            .lock()
+            .map(|status| status.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for Miner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Outcome of a nonce search
+#[derive(Debug)]
+pub struct Search {
+    /// The solved block, `None` if the search was cancelled
+    pub block: Option<Block>,
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl Search {
+    /// Hashes per second
+    pub fn hash_rate(&self) -> f64 {
+        hash_rate(self.hashes, self.elapsed)
+    }
+}
+
+fn hash_rate(hashes: u64, elapsed: Duration) -> f64 {
+    match elapsed.as_secs_f64() {
+        secs if secs > 0.0 => hashes as f64 / secs,
+        _ => 0.0,
+    }
+}
+
+/// Searches nonces until `block` meets `difficulty`, on `threads` threads.
+///
+/// Thread `i` tries the nonces `i`, `i + threads`, `i + 2 * threads`... and every
+/// thread stops once one of them finds a solution. A thread running out of nonces
+/// rolls the block timestamp and starts over. `cancelled` is polled from the
+/// calling thread with the number of hashes computed so far, returning true
+/// gives up on the search.
+pub fn search(
+    block: Block,
+    difficulty: usize,
+    threads: usize,
+    cancelled: impl FnMut(u64) -> bool,
+) -> Search {
+    search_nonces(block, difficulty, threads, i64::MAX, cancelled)
+}
+
+fn search_nonces(
+    block: Block,
+    difficulty: usize,
+    threads: usize,
+    max_nonce: i64,
+    mut cancelled: impl FnMut(u64) -> bool,
+) -> Search {
+    let threads = threads.max(1);
+    let hasher = block.header().hasher();
+    let start = Instant::now();
+    let stop = AtomicBool::new(false);
+    let hashes = AtomicU64::new(0);
+    let solution = Mutex::new(None);
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|first| {
+                let mut block = block.clone();
+                let mut hasher = hasher.clone();
+                let (stop, hashes, solution) = (&stop, &hashes, &solution);
+                scope.spawn(move || {
+                    let step = threads as i64;
+                    let mut nonce = first as i64;
+                    let mut tried = 0;
+
+                    while !meets_difficulty(&hasher.hash(nonce), difficulty) {
+                        tried += 1;
+                        if tried % CHECK_INTERVAL == 0 {
+                            hashes.fetch_add(CHECK_INTERVAL, Ordering::Relaxed);
+                            if stop.load(Ordering::Relaxed) {
+                                return;
+                            }
+                        }
+
+                        nonce = match nonce.checked_add(step) {
+                            Some(next) if next <= max_nonce => next,
+                            _ => {
+                                block.roll_timestamp();
+                                hasher.set_timestamp(block.timestamp());
+                                first as i64
+                            }
+                        };
+                    }
+
+                    block.set_nonce(nonce);
+                    hashes.fetch_add(tried % CHECK_INTERVAL + 1, Ordering::Relaxed);
+                    if !stop.swap(true, Ordering::SeqCst) {
+                        *solution.lock().unwrap_or_else(|e| e.into_inner()) = Some(block);
+                    }
+                })
+            })
+            .collect();
+
+        while !workers.iter().all(|worker| worker.is_finished()) {
+            if !stop.load(Ordering::SeqCst) && cancelled(hashes.load(Ordering::Relaxed)) {
+                stop.store(true, Ordering::SeqCst);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+
+    Search {
+        block: solution.into_inner().unwrap_or_else(|e| e.into_inner()),
+        hashes: hashes.into_inner(),
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Whether events since the template was taken make it stale
+fn template_outdated(events: &mut Receiver<ChainEvent>) -> bool {
+    loop {
+        match events.try_recv() {
+            Ok(ChainEvent::TransactionEvicted { .. }) => {}
+            Ok(_) | Err(TryRecvError::Lagged(_)) | Err(TryRecvError::Closed) => return true,
+            Err(TryRecvError::Empty) => return false,
+        }
+    }
+}
+
+fn mine(chain: Arc<Mutex<Blockchain>>, shared: Arc<Shared>) {
+    let error = loop {
+        if shared.stop.load(Ordering::SeqCst) {
+            break None;
+        }
+
+        let Ok(guard) = chain.lock() else {
+            break Some(String::from("Failed to lock chain"));
+        };
+        let template = guard.block_template().and_then(|template| {
+            Ok((
+                template,
+                guard.block_context()?,
+                guard.height()? + 1,
+                guard.difficulty(),
+                guard.mining_threads(),
+                guard.subscribe(),
+            ))
+        });
+        drop(guard);
+
+        let (template, context, height, difficulty, threads, mut events) = match template {
+            Ok(template) => template,
+            Err(e) => break Some(e.to_string()),
+        };
+        shared.update(|status| {
+            status.height = Some(height);
+            status.transactions = template.transactions().len();
+            status.threads = threads;
+            status.hash_rate = 0.0;
+            status.templates += 1;
+        });
+
+        let start = Instant::now();
+        let search = search(template, difficulty, threads, |hashes| {
+            shared.update(|status| status.hash_rate = hash_rate(hashes, start.elapsed()));
+            shared.stop.load(Ordering::SeqCst) || template_outdated(&mut events)
+        });
+        shared.update(|status| status.hash_rate = search.hash_rate());
+        let Some(block) = search.block else {
+            continue;
+        };
+        // Signatures are verified before taking the lock, the parent is checked
+        // again once it is held
+        let Ok(block) = context.check(block) else {
+            continue;
+        };
+
+        let Ok(mut guard) = chain.lock() else {
+            break Some(String::from("Failed to lock chain"));
+        };
+        // Transactions accepted since the last check would be evicted by the block
+        if template_outdated(&mut events) {
+            continue;
+        }
+        if let Ok(block) = guard.add_checked_block(block) {
+            shared.update(|status| {
+                status.blocks_mined += 1;
+                status.last_block = Some(block.hash());
+            });
+        }
+    };
+
+    shared.update(|status| {
+        status.running = false;
+        status.height = None;
+        status.transactions = 0;
+        status.hash_rate = 0.0;
+        status.error = error;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+    use crate::store::DiskStore;
+    use crate::transaction::Transaction;
+    use crate::wallet::Wallet;
+    use std::time::{Duration, Instant};
+
+    fn wait_for(miner: &Miner, condition: impl Fn(&MinerStatus) -> bool) -> MinerStatus {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        loop {
+            let status = miner.status();
+            if condition(&status) {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "miner status {:?}", status);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn search_gives_up_when_cancelled() {
+        let chain = Blockchain::new(Address::named("my_address"), 1);
+        let template = chain.block_template().unwrap();
+
+        let cancelled = search(template.clone(), 64, 2, |_| true);
+        assert!(cancelled.block.is_none());
+
+        let solved = search(template, 1, 2, |_| false);
+        let block = solved.block.unwrap();
+        assert!(block.check_proof_of_work(1).is_ok());
+        assert!(solved.hashes >= 1);
+    }
+
+    #[test]
+    fn threads_share_the_nonce_space_and_roll_the_timestamp() {
+        let chain = Blockchain::new(Address::named("my_address"), 1);
+        let mut template = chain.block_template().unwrap();
+        let solvable = |template: &Block| {
+            (0..4).any(|nonce| {
+                let mut block = template.clone();
+                block.set_nonce(nonce);
+                block.check_proof_of_work(3).is_ok()
+            })
+        };
+        while solvable(&template) {
+            template.roll_timestamp();
+        }
+
+        // One nonce per thread, the timestamp has to be rolled
+        let search = search_nonces(template.clone(), 3, 4, 3, |_| false);
+        let block = search.block.unwrap();
+
+        assert!(block.check_proof_of_work(3).is_ok());
+        assert!(block.nonce() <= 3);
+        assert!(block.timestamp() > template.timestamp());
+        assert!(search.hashes > 4);
+    }
+
+    #[test]
+    fn mines_in_the_background_until_stopped() {
+        let chain = Arc::new(Mutex::new(Blockchain::new(Address::named("my_address"), 1)));
+        let miner = Miner::new(chain.clone());
+
+        assert!(miner.start());
+        assert!(!miner.start());
+        let status = wait_for(&miner, |status| status.blocks_mined >= 2);
+        assert!(status.running);
+
+        assert!(miner.stop());
+        let status = miner.status();
+        assert!(!status.running);
+        assert_eq!(chain.lock().unwrap().height().unwrap(), status.blocks_mined);
+    }
+
+    #[test]
+    fn new_transactions_restart_the_search() {
+        let dir =
+            std::env::temp_dir().join(format!("blocksmith-miner-{}", Block::generate_timestamp()));
+        let sender = Wallet::generate_new();
+        {
+            let store = DiskStore::open(&dir).unwrap();
+            let mut blockchain =
+                Blockchain::with_store(sender.address(), 1, Box::new(store)).unwrap();
+            blockchain.mine().unwrap();
+        }
+
+        // Far too hard to be solved during the test
+        let store = DiskStore::open(&dir).unwrap();
+        let chain = Arc::new(Mutex::new(
+            Blockchain::with_store(Address::named("my_address"), 16, Box::new(store)).unwrap(),
+        ));
+        let miner = Miner::new(chain.clone());
+
+        miner.start();
+        wait_for(&miner, |status| status.templates == 1);
+
+        let mut transaction =
+            Transaction::new(sender.address(), Address::named("recipient_address"), 1.0);
+        sender.sign_transaction(&mut transaction);
+        chain
+            .lock()
+            .unwrap()
+            .submit_transaction(transaction)
+            .unwrap();
+        let status = wait_for(&miner, |status| status.templates == 2);
+        assert_eq!(status.transactions, 2);
+        assert_eq!(status.height, Some(2));
+
+        miner.stop();
+        drop(chain);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+//! m-of-n accounts, spendable once enough of their keys have signed.
+//!
+//! The address of an account is the hash of its threshold and of its keys,
+//! compressed and sorted, so the same set of keys gives the same address
+//! whichever order cosigners list them in. Nothing is registered on chain: a
+//! transaction spending from the account carries the policy, which has to hash
+//! to the sender address, along with exactly as many signatures as the
+//! threshold, in the order of the keys. With a single valid set of signatures
+//! per signer set, nobody relaying a transaction can change its id by adding,
+//! dropping or reordering them. Account addresses are script hashes, which no
+//! single key can spend from.
+
+use crate::address::{hash160, Address, TESTNET_SCRIPT_VERSION};
+use crate::hash::Hash256;
+
+use secp256k1::{ecdsa::Signature, Message, PublicKey, SECP256K1};
+use serde::{Deserialize, Serialize};
+
+/// Most keys an account can have, as many as standard multisig scripts allow
+pub const MAX_KEYS: usize = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Multisig {
+    threshold: usize,
+    /// Sorted by their compressed serialization
+    public_keys: Vec<PublicKey>,
+}
+
+impl Multisig {
+    /// Account of `public_keys`, in any order, needing `threshold` of them to sign
+    pub fn new(threshold: usize, mut public_keys: Vec<PublicKey>) -> Result<Self, MultisigError> {
+        if public_keys.len() > MAX_KEYS {
+            return Err(MultisigError::TooManyKeys(public_keys.len()));
+        }
+        if threshold == 0 || threshold > public_keys.len() {
+            return Err(MultisigError::InvalidThreshold {
+                threshold,
+                keys: public_keys.len(),
+            });
+        }
+
+        public_keys.sort_by_key(|key| key.serialize());
+        if public_keys.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(MultisigError::DuplicateKey);
+        }
+
+        Ok(Self {
+            threshold,
+            public_keys,
+        })
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn public_keys(&self) -> &[PublicKey] {
+        &self.public_keys
+    }
+
+    /// Testnet script hash address of the account
+    pub fn address(&self) -> Address {
+        let mut policy = Vec::with_capacity(2 + self.public_keys.len() * 33);
+        policy.push(self.threshold as u8);
+        policy.push(self.public_keys.len() as u8);
+        for public_key in &self.public_keys {
+            policy.extend_from_slice(&public_key.serialize());
+        }
+        Address::new(TESTNET_SCRIPT_VERSION, hash160(policy))
+    }
+
+    /// How many of the hex encoded compact ECDSA `signatures` are signatures
+    /// of `digest` by the keys, in the order of the keys. Counting stops at the
+    /// first signature by none of the keys after the previous signer, so each
+    /// key is tried at most once whatever the number of signatures
+    pub fn signers(&self, digest: &Hash256, signatures: &[String]) -> usize {
+        let message = Message::from_digest(*digest.as_bytes());
+        let mut public_keys = self.public_keys.iter();
+        signatures
+            .iter()
+            .take_while(|signature| {
+                let Some(signature) = parse_signature(signature) else {
+                    return false;
+                };
+                public_keys.any(|public_key| {
+                    SECP256K1
+                        .verify_ecdsa(&message, &signature, public_key)
+                        .is_ok()
+                })
+            })
+            .count()
+    }
+
+    /// Position among the keys of the key that made `signature` of `digest`
+    pub(crate) fn signer(&self, digest: &Hash256, signature: &str) -> Option<usize> {
+        let message = Message::from_digest(*digest.as_bytes());
+        let signature = parse_signature(signature)?;
+        self.public_keys.iter().position(|public_key| {
+            SECP256K1
+                .verify_ecdsa(&message, &signature, public_key)
+                .is_ok()
+        })
+    }
+}
+
+fn parse_signature(signature: &str) -> Option<Signature> {
+    Signature::from_compact(&hex::decode(signature).ok()?).ok()
+}
+
+impl<'de> Deserialize<'de> for Multisig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Policy {
+            threshold: usize,
+            public_keys: Vec<PublicKey>,
+        }
+
+        let policy = Policy::deserialize(deserializer)?;
+        Self::new(policy.threshold, policy.public_keys).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum MultisigError {
+    #[error("threshold of {threshold} signatures out of {keys} keys")]
+    InvalidThreshold { threshold: usize, keys: usize },
+    #[error("{0} keys, accounts have at most {}", MAX_KEYS)]
+    TooManyKeys(usize),
+    #[error("the same key is listed twice")]
+    DuplicateKey,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::AddressKind;
+    use crate::wallet::Wallet;
+
+    fn keys(count: usize) -> Vec<PublicKey> {
+        (0..count)
+            .map(|_| Wallet::generate_new().public_key())
+            .collect()
+    }
+
+    #[test]
+    fn addresses_do_not_depend_on_key_order() {
+        let public_keys = keys(3);
+        let multisig = Multisig::new(2, public_keys.clone()).unwrap();
+        assert_eq!(multisig.address().kind(), AddressKind::ScriptHash);
+
+        let mut reversed = public_keys.clone();
+        reversed.reverse();
+        assert_eq!(
+            Multisig::new(2, reversed).unwrap().address(),
+            multisig.address()
+        );
+
+        assert_ne!(
+            Multisig::new(3, public_keys.clone()).unwrap().address(),
+            multisig.address()
+        );
+        assert_ne!(
+            Multisig::new(2, public_keys[..2].to_vec())
+                .unwrap()
+                .address(),
+            multisig.address()
+        );
+
+        let json = serde_json::to_value(&multisig).unwrap();
+        let restored: Multisig = serde_json::from_value(json).unwrap();
+        assert_eq!(restored, multisig);
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_policies() {
+        assert_eq!(
+            Multisig::new(0, keys(2)),
+            Err(MultisigError::InvalidThreshold {
+                threshold: 0,
+                keys: 2
+            })
+        );
+        assert_eq!(
+            Multisig::new(3, keys(2)),
+            Err(MultisigError::InvalidThreshold {
+                threshold: 3,
+                keys: 2
+            })
+        );
+        assert_eq!(
+            Multisig::new(1, keys(16)),
+            Err(MultisigError::TooManyKeys(16))
+        );
+
+        let key = keys(1)[0];
+        assert_eq!(
+            Multisig::new(1, vec![key, key]),
+            Err(MultisigError::DuplicateKey)
+        );
+
+        let json = serde_json::json!({ "threshold": 0, "public_keys": keys(2) });
+        assert!(serde_json::from_value::<Multisig>(json).is_err());
+    }
+}
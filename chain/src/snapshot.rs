@@ -0,0 +1,615 @@
+//! Snapshots of the chain state, letting a node start without replaying every block.
+//!
+//! A snapshot commits to its contents with a SHA-256 over their JSON form, which
+//! has to match a commitment the operator trusts, obtained from a node they run.
+//! A node started from one serves it until `SnapshotValidator` has replayed the
+//! historical blocks up to the snapshot height, and rolls back to genesis should
+//! they lead to another state.
+
+use crate::block::{Block, BlockHash, BlockHeader};
+use crate::chain::{block_work, Blockchain, ChainError};
+use crate::hash::Hash256;
+use crate::state::ChainState;
+
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChainSnapshot {
+    /// Hashes of the active chain, by height, the last one being the tip
+    block_hashes: Vec<BlockHash>,
+    tip_header: BlockHeader,
+    cumulative_work: u128,
+    state: ChainState,
+}
+
+/// On-disk form of a snapshot
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile {
+    snapshot: ChainSnapshot,
+    commitment: Hash256,
+}
+
+impl ChainSnapshot {
+    pub fn new(
+        block_hashes: Vec<BlockHash>,
+        tip_header: BlockHeader,
+        cumulative_work: u128,
+        state: ChainState,
+    ) -> Self {
+        Self {
+            block_hashes,
+            tip_header,
+            cumulative_work,
+            state,
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.block_hashes.len().saturating_sub(1) as u64
+    }
+
+    pub fn tip_hash(&self) -> BlockHash {
+        self.block_hashes.last().copied().unwrap_or_default()
+    }
+
+    pub fn tip_header(&self) -> &BlockHeader {
+        &self.tip_header
+    }
+
+    pub fn block_hashes(&self) -> &[BlockHash] {
+        &self.block_hashes
+    }
+
+    pub fn cumulative_work(&self) -> u128 {
+        self.cumulative_work
+    }
+
+    pub fn state(&self) -> &ChainState {
+        &self.state
+    }
+
+    /// SHA-256 of the JSON serialized snapshot
+    pub fn commitment(&self) -> Result<Hash256, SnapshotError> {
+        let snapshot_json = serde_json::to_string(self)?;
+        Ok(Hash256::digest(snapshot_json.as_bytes()))
+    }
+
+    pub fn write_to<W: Write>(&self, out: W) -> Result<(), SnapshotError> {
+        let file = SnapshotFile {
+            snapshot: self.clone(),
+            commitment: self.commitment()?,
+        };
+        serde_json::to_writer(out, &file)?;
+        Ok(())
+    }
+
+    /// Reads a snapshot, rejecting it if its contents don't match the commitment
+    /// written along, nor the `trusted` one
+    pub fn read_from<R: Read>(input: R, trusted: &Hash256) -> Result<Self, SnapshotError> {
+        let file: SnapshotFile = serde_json::from_reader(input)?;
+        let found = file.snapshot.commitment()?;
+        if found != file.commitment {
+            return Err(SnapshotError::CommitmentMismatch {
+                expected: file.commitment,
+                found,
+            });
+        }
+        if found != *trusted {
+            return Err(SnapshotError::UntrustedCommitment {
+                trusted: *trusted,
+                found,
+            });
+        }
+        Ok(file.snapshot)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SnapshotStatus {
+    /// Nothing has been checked yet
+    Unverified,
+    /// Historical blocks up to `height` matched the snapshot so far
+    Validating { height: u64 },
+    /// Replaying every block up to the snapshot tip reproduced the snapshot
+    Validated,
+    /// The historical blocks lead to another state, the chain was rolled back
+    /// to genesis
+    Invalid { reason: String },
+}
+
+/// Where a `SnapshotValidator` stands, copied out of it so that the next
+/// historical block can be checked without holding the chain lock
+#[derive(Debug, Clone)]
+pub struct HistoricalContext {
+    height: u64,
+    /// Hash of the block of the snapshotted chain at `height`
+    expected: BlockHash,
+    previous: Option<(BlockHash, BlockHeader)>,
+    difficulty: usize,
+}
+
+impl HistoricalContext {
+    /// Checks that `block` is the next block of the snapshotted chain, then
+    /// that it is valid on its own, signatures included, and as the successor
+    /// of the previous one. Blocks of another chain are refused, whereas an
+    /// invalid block of the snapshotted chain is only judged by
+    /// `SnapshotValidator::add_checked_block`, as it invalidates the snapshot
+    pub fn check(&self, block: Block) -> Result<CheckedHistoricalBlock, SnapshotError> {
+        if block.hash() != self.expected {
+            return Err(SnapshotError::UnexpectedBlock {
+                height: self.height,
+            });
+        }
+
+        let invalid = self
+            .previous
+            .as_ref()
+            .and_then(|(previous_hash, previous_header)| {
+                Blockchain::verify_successor(
+                    &block,
+                    previous_hash,
+                    previous_header,
+                    self.difficulty,
+                )
+                .err()
+            })
+            .map(|e| e.to_string());
+        Ok(CheckedHistoricalBlock {
+            height: self.height,
+            block,
+            invalid,
+        })
+    }
+}
+
+/// A block of the snapshotted chain checked by `HistoricalContext::check`
+#[derive(Debug, Clone)]
+pub struct CheckedHistoricalBlock {
+    height: u64,
+    block: Block,
+    /// Why the block failed the checks, if it did
+    invalid: Option<String>,
+}
+
+impl CheckedHistoricalBlock {
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+}
+
+/// Replays historical blocks from genesis and checks they lead to the snapshot.
+///
+/// It holds its own copy of the state, so blocks can be checked without
+/// touching the running `Blockchain`, which keeps one for the snapshot it
+/// started from, see `Blockchain::add_historical_block`.
+#[derive(Debug)]
+pub struct SnapshotValidator {
+    /// Hashes of the snapshotted chain, by height
+    block_hashes: Vec<BlockHash>,
+    commitment: Hash256,
+    difficulty: usize,
+    state: ChainState,
+    cumulative_work: u128,
+    next_height: u64,
+    /// Hash and header of the last block replayed
+    previous: Option<(BlockHash, BlockHeader)>,
+}
+
+impl SnapshotValidator {
+    pub fn new(snapshot: &ChainSnapshot, difficulty: usize) -> Self {
+        let commitment = snapshot
+            .commitment()
+            .expect("Snapshot serialization cannot fail");
+        Self::resume(snapshot.block_hashes.clone(), commitment, difficulty)
+    }
+
+    /// Validator of the snapshot of the chain made of `block_hashes`, whose
+    /// commitment is `commitment`
+    pub(crate) fn resume(
+        block_hashes: Vec<BlockHash>,
+        commitment: Hash256,
+        difficulty: usize,
+    ) -> Self {
+        Self {
+            block_hashes,
+            commitment,
+            difficulty,
+            state: ChainState::new(),
+            cumulative_work: 0,
+            next_height: 0,
+            previous: None,
+        }
+    }
+
+    /// Height of the next block expected
+    pub fn next_height(&self) -> u64 {
+        self.next_height
+    }
+
+    pub fn commitment(&self) -> Hash256 {
+        self.commitment
+    }
+
+    /// Height of the snapshot tip
+    pub fn height(&self) -> u64 {
+        self.block_hashes.len().saturating_sub(1) as u64
+    }
+
+    /// What the next historical block is checked against
+    pub fn context(&self) -> Result<HistoricalContext, SnapshotError> {
+        let expected = self
+            .block_hashes
+            .get(self.next_height as usize)
+            .ok_or(SnapshotError::AlreadyValidated)?;
+        Ok(HistoricalContext {
+            height: self.next_height,
+            expected: *expected,
+            previous: self.previous.clone(),
+            difficulty: self.difficulty,
+        })
+    }
+
+    /// Checks the next historical block, in height order starting at genesis,
+    /// as thoroughly as a block connected to the chain: on its own, signatures
+    /// included, as the successor of the previous one, and that its
+    /// transactions are funded and in sequence.
+    pub fn add_block(&mut self, block: &Block) -> Result<SnapshotStatus, SnapshotError> {
+        let block = self.context()?.check(block.clone())?;
+        self.add_checked_block(block)
+    }
+
+    /// Replays a block checked by `HistoricalContext::check`, which may have
+    /// run without the chain lock. Only the funds and sequences of its
+    /// transactions are left to check, against the replayed state.
+    pub fn add_checked_block(
+        &mut self,
+        block: CheckedHistoricalBlock,
+    ) -> Result<SnapshotStatus, SnapshotError> {
+        let CheckedHistoricalBlock {
+            height,
+            block,
+            invalid: invalid_reason,
+        } = block;
+        // Another block was replayed since this one was checked
+        if height != self.next_height {
+            return Err(SnapshotError::OutOfOrder {
+                height,
+                expected: self.next_height,
+            });
+        }
+
+        let invalid = |reason: String| SnapshotError::InvalidBlock { height, reason };
+        if let Some(reason) = invalid_reason {
+            return Err(invalid(reason));
+        }
+        let mut spending = self.state.spending(height);
+        for transaction in block.transactions() {
+            spending
+                .apply(&transaction)
+                .map_err(|e| invalid(e.to_string()))?;
+        }
+
+        if self.previous.is_some() {
+            self.cumulative_work += block_work(self.difficulty);
+        }
+        self.state.apply_block(height, &block);
+        self.previous = Some((block.hash(), block.header()));
+        self.next_height += 1;
+
+        if height < self.height() {
+            return Ok(SnapshotStatus::Validating { height });
+        }
+
+        let replayed = ChainSnapshot::new(
+            self.block_hashes.clone(),
+            block.header(),
+            self.cumulative_work,
+            self.state.clone(),
+        );
+        if replayed.commitment()? != self.commitment {
+            return Err(SnapshotError::StateMismatch);
+        }
+        Ok(SnapshotStatus::Validated)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SnapshotError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serialization error: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("snapshot commitment is {expected} but its contents hash to {found}")]
+    CommitmentMismatch { expected: Hash256, found: Hash256 },
+    #[error("snapshot commitment {found} is not the trusted {trusted}")]
+    UntrustedCommitment { trusted: Hash256, found: Hash256 },
+    #[error("block at height {height} is not part of the snapshotted chain")]
+    UnexpectedBlock { height: u64 },
+    #[error("block at height {height} was checked while {expected} is expected next")]
+    OutOfOrder { height: u64, expected: u64 },
+    #[error("block at height {height} is invalid: {reason}")]
+    InvalidBlock { height: u64, reason: String },
+    #[error("replaying the chain does not reproduce the snapshot state")]
+    StateMismatch,
+    #[error("every block up to the snapshot tip has already been checked")]
+    AlreadyValidated,
+    #[error("chain is not validating a snapshot")]
+    NotValidating,
+    #[error("chain error: {0}")]
+    Chain(ChainError),
+}
+
+impl From<ChainError> for SnapshotError {
+    fn from(e: ChainError) -> Self {
+        SnapshotError::Chain(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+    use crate::events::ChainEvent;
+    use crate::miner;
+    use crate::transaction::Transaction;
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+
+    fn mined_chain(blocks: usize) -> Blockchain {
+        let mut chain = Blockchain::new(Address::named("my_address"), 2);
+        for _ in 0..blocks {
+            chain.mine().unwrap();
+        }
+        chain
+    }
+
+    #[test]
+    fn snapshot_file_roundtrip_checks_commitment() {
+        let snapshot = mined_chain(2).snapshot().unwrap();
+        let mut file = Vec::new();
+        snapshot.write_to(&mut file).unwrap();
+
+        let commitment = snapshot.commitment().unwrap();
+        assert_eq!(
+            ChainSnapshot::read_from(file.as_slice(), &commitment).unwrap(),
+            snapshot
+        );
+        assert!(matches!(
+            ChainSnapshot::read_from(file.as_slice(), &Hash256::digest("another snapshot")),
+            Err(SnapshotError::UntrustedCommitment { found, .. }) if found == commitment
+        ));
+
+        let miner = Address::named("my_address");
+        let tampered = String::from_utf8(file).unwrap().replace(
+            &format!("\"{}\":20.0", miner),
+            &format!("\"{}\":2000.0", miner),
+        );
+        assert!(matches!(
+            ChainSnapshot::read_from(tampered.as_bytes(), &commitment),
+            Err(SnapshotError::CommitmentMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn node_started_from_snapshot_keeps_extending_the_chain() {
+        let source = mined_chain(3);
+        let snapshot = source.snapshot().unwrap();
+
+        let mut restored = Blockchain::from_snapshot(
+            Address::named("other_address"),
+            2,
+            Box::new(crate::store::MemoryStore::new()),
+            &snapshot,
+        )
+        .unwrap();
+
+        assert_eq!(restored.height().unwrap(), 3);
+        assert_eq!(restored.get_balance(&Address::named("my_address")), 30.0);
+        assert_eq!(restored.cumulative_work(), source.cumulative_work());
+        assert_eq!(restored.snapshot_status(), Some(SnapshotStatus::Unverified));
+
+        restored.mine().unwrap();
+        assert_eq!(restored.height().unwrap(), 4);
+        assert_eq!(restored.get_balance(&Address::named("other_address")), 10.0);
+    }
+
+    #[test]
+    fn validator_replays_history_up_to_the_snapshot() {
+        let source = mined_chain(3);
+        let mut validator = SnapshotValidator::new(&source.snapshot().unwrap(), 2);
+
+        let statuses: Vec<_> = source
+            .chain()
+            .unwrap()
+            .iter()
+            .map(|block| validator.add_block(block).unwrap())
+            .collect();
+
+        assert_eq!(statuses[2], SnapshotStatus::Validating { height: 2 });
+        assert_eq!(statuses[3], SnapshotStatus::Validated);
+    }
+
+    #[test]
+    fn validator_rejects_blocks_from_another_chain() {
+        let source = mined_chain(2);
+        let other = mined_chain(2);
+        let mut validator = SnapshotValidator::new(&source.snapshot().unwrap(), 2);
+
+        validator
+            .add_block(&other.block_at_height(0).unwrap())
+            .unwrap();
+        assert!(matches!(
+            validator.add_block(&other.block_at_height(1).unwrap()),
+            Err(SnapshotError::UnexpectedBlock { height: 1 })
+        ));
+    }
+
+    #[test]
+    fn blocks_checked_before_another_was_replayed_are_refused() {
+        let source = mined_chain(2);
+        let mut validator = SnapshotValidator::new(&source.snapshot().unwrap(), 2);
+        let genesis = source.block_at_height(0).unwrap();
+
+        let first = validator.context().unwrap().check(genesis.clone()).unwrap();
+        let second = validator.context().unwrap().check(genesis).unwrap();
+        validator.add_checked_block(first).unwrap();
+        assert!(matches!(
+            validator.add_checked_block(second),
+            Err(SnapshotError::OutOfOrder {
+                height: 0,
+                expected: 1
+            })
+        ));
+        assert_eq!(validator.next_height(), 1);
+    }
+
+    #[test]
+    fn validator_checks_the_funds_of_historical_blocks() {
+        let wallet = Wallet::generate_new();
+        let genesis = Block::genesis();
+        let mut overdraft = Transaction::new(wallet.address(), Address::named("thief"), 5.0);
+        wallet.sign_transaction(&mut overdraft);
+        let block = Block::create_from(vec![Arc::new(overdraft)], 0, genesis.hash());
+        let block = miner::search(block, 2, 1, |_| false).block.unwrap();
+
+        // Signed and mined, but spending what the sender never had
+        let snapshot = ChainSnapshot::new(
+            vec![genesis.hash(), block.hash()],
+            block.header(),
+            block_work(2),
+            ChainState::new(),
+        );
+        let mut validator = SnapshotValidator::new(&snapshot, 2);
+        validator.add_block(&genesis).unwrap();
+        assert!(matches!(
+            validator.add_block(&block),
+            Err(SnapshotError::InvalidBlock { height: 1, reason })
+                if reason.contains("has a balance of 0")
+        ));
+    }
+
+    #[test]
+    fn historical_blocks_validate_the_snapshot_of_a_chain() {
+        let source = mined_chain(3);
+        let dir = std::env::temp_dir().join(format!(
+            "blocksmith-snapshot-{}",
+            Block::generate_timestamp()
+        ));
+        let open = || Box::new(crate::store::DiskStore::open(&dir).unwrap());
+        let mut restored = Blockchain::from_snapshot(
+            Address::named("other_address"),
+            2,
+            open(),
+            &source.snapshot().unwrap(),
+        )
+        .unwrap();
+
+        let other = mined_chain(1);
+        restored
+            .add_historical_block(&other.block_at_height(0).unwrap())
+            .unwrap();
+        assert!(matches!(
+            restored.add_historical_block(&other.block_at_height(1).unwrap()),
+            Err(SnapshotError::UnexpectedBlock { height: 1 })
+        ));
+        for height in 1..3 {
+            restored
+                .add_historical_block(&source.block_at_height(height).unwrap())
+                .unwrap();
+        }
+        assert_eq!(
+            restored.snapshot_status(),
+            Some(SnapshotStatus::Validating { height: 2 })
+        );
+
+        // Progress is lost on restart, the outcome is kept
+        drop(restored);
+        let mut restored =
+            Blockchain::with_store(Address::named("other_address"), 2, open()).unwrap();
+        assert_eq!(restored.snapshot_status(), Some(SnapshotStatus::Unverified));
+        for block in source.chain().unwrap() {
+            restored.add_historical_block(&block).unwrap();
+        }
+        assert_eq!(restored.snapshot_status(), Some(SnapshotStatus::Validated));
+        assert!(matches!(
+            restored.add_historical_block(&source.block_at_height(0).unwrap()),
+            Err(SnapshotError::NotValidating)
+        ));
+
+        drop(restored);
+        let restored = Blockchain::with_store(Address::named("other_address"), 2, open()).unwrap();
+        assert_eq!(restored.snapshot_status(), Some(SnapshotStatus::Validated));
+        assert_eq!(restored.height().unwrap(), 3);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_snapshots_roll_the_chain_back_to_genesis() {
+        let source = mined_chain(2);
+        let snapshot = source.snapshot().unwrap();
+        let mut inflated = ChainState::new();
+        inflated.apply_block(1, &mined_chain(1).block_at_height(1).unwrap());
+        let forged = ChainSnapshot::new(
+            snapshot.block_hashes.clone(),
+            snapshot.tip_header.clone(),
+            snapshot.cumulative_work,
+            inflated,
+        );
+
+        let miner = Wallet::generate_new();
+        let mut restored = Blockchain::from_snapshot(
+            miner.address(),
+            2,
+            Box::new(crate::store::MemoryStore::new()),
+            &forged,
+        )
+        .unwrap();
+        restored.mine().unwrap();
+        assert_eq!(restored.height().unwrap(), 3);
+        let mined = restored.block_at_height(3).unwrap();
+        let mut payment = Transaction::new(miner.address(), Address::named("payee"), 1.0);
+        miner.sign_transaction(&mut payment);
+        let txid = restored.submit_transaction(payment).unwrap();
+        let mut events = restored.subscribe();
+
+        let blocks = source.chain().unwrap();
+        for block in &blocks[..2] {
+            restored.add_historical_block(block).unwrap();
+        }
+        assert!(matches!(
+            restored.add_historical_block(&blocks[2]),
+            Err(SnapshotError::StateMismatch)
+        ));
+
+        assert!(matches!(
+            restored.snapshot_status(),
+            Some(SnapshotStatus::Invalid { .. })
+        ));
+        assert_eq!(restored.height().unwrap(), 0);
+        assert_eq!(restored.cumulative_work(), 0);
+        assert_eq!(restored.get_balance(&Address::named("my_address")), 0.0);
+        assert_eq!(restored.get_balance(&miner.address()), 0.0);
+
+        // Subscribers learn what the rollback dropped
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ChainEvent::BlockDisconnected { height: 3, hash, .. }) if hash == mined.hash()
+        ));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ChainEvent::TransactionEvicted { txid: evicted, .. }) if evicted == txid
+        ));
+        assert!(events.try_recv().is_err());
+
+        // The node keeps going from genesis
+        restored.mine().unwrap();
+        assert_eq!(restored.height().unwrap(), 1);
+    }
+}
@@ -0,0 +1,363 @@
+use crate::address::Address;
+use crate::block::{Block, BlockHash};
+use crate::chain::ValidationError;
+use crate::hash::Hash256;
+use crate::transaction::Transaction;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// State derived from the blocks of the active chain, indexed by address.
+///
+/// It is updated as blocks are connected and rolled back as they are
+/// disconnected, so balances and histories never need a scan of the chain, and
+/// pruned nodes can still answer them once the bodies are gone. Ordered maps
+/// keep the serialized form deterministic, which snapshot commitments rely on.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChainState {
+    balances: BTreeMap<Address, f32>,
+    /// Every transaction touching an address, oldest first
+    history: BTreeMap<Address, Vec<HistoryEntry>>,
+    /// Number of transactions each address sent, the sequence its next one
+    /// has to carry
+    sequences: BTreeMap<Address, u64>,
+}
+
+/// Effect of a transaction on the balance of an address
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoryEntry {
+    pub txid: Hash256,
+    pub height: u64,
+    pub delta: f32,
+}
+
+/// What a block changes in the state, stored apart from its body so that the
+/// state can be brought up to the tip without replaying pruned blocks
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlockDelta {
+    height: u64,
+    /// Net change each transaction makes to the addresses it touches
+    changes: Vec<(Address, Hash256, f32)>,
+    /// Sender of each transaction but the reward, using up one sequence
+    senders: Vec<Address>,
+}
+
+impl BlockDelta {
+    /// Changes made by `block` once connected at `height`
+    pub fn of(height: u64, block: &Block) -> Self {
+        let mut changes = Vec::new();
+        let mut senders = Vec::new();
+        for transaction in block.transactions().iter() {
+            let txid = transaction.id();
+            let sender = transaction.sender_address();
+            let recipient = transaction.recipient_address();
+            if !transaction.is_reward() {
+                senders.push(sender);
+            }
+
+            if sender == recipient {
+                changes.push((sender, txid, 0.0));
+            } else {
+                changes.push((sender, txid, -transaction.value()));
+                changes.push((recipient, txid, transaction.value()));
+            }
+        }
+        Self {
+            height,
+            changes,
+            senders,
+        }
+    }
+}
+
+/// The state as of a block of the active chain, which the deltas of the
+/// following blocks bring up to the tip
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StateCheckpoint {
+    pub height: u64,
+    /// Hash of the block at `height`, telling whether the checkpoint is still
+    /// on the active chain
+    pub hash: BlockHash,
+    pub cumulative_work: u128,
+    pub state: ChainState,
+}
+
+impl ChainState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply_block(&mut self, height: u64, block: &Block) {
+        self.apply(&BlockDelta::of(height, block));
+    }
+
+    pub fn apply(&mut self, delta: &BlockDelta) {
+        for &(address, txid, change) in &delta.changes {
+            *self.balances.entry(address).or_default() += change;
+            self.history.entry(address).or_default().push(HistoryEntry {
+                txid,
+                height: delta.height,
+                delta: change,
+            });
+        }
+        for &sender in &delta.senders {
+            *self.sequences.entry(sender).or_default() += 1;
+        }
+    }
+
+    /// Undoes `apply_block`, blocks have to be reverted from the tip down
+    pub fn revert_block(&mut self, height: u64, block: &Block) {
+        self.revert(&BlockDelta::of(height, block));
+    }
+
+    /// Undoes `apply`
+    pub fn revert(&mut self, delta: &BlockDelta) {
+        for sender in &delta.senders {
+            if let Some(sequence) = self.sequences.get_mut(sender) {
+                *sequence -= 1;
+                if *sequence == 0 {
+                    self.sequences.remove(sender);
+                }
+            }
+        }
+        for &(address, _, change) in delta.changes.iter().rev() {
+            *self.balances.entry(address).or_default() -= change;
+
+            if let Some(entries) = self.history.get_mut(&address) {
+                if entries.last().map(|entry| entry.height) == Some(delta.height) {
+                    entries.pop();
+                }
+                if entries.is_empty() {
+                    self.history.remove(&address);
+                    self.balances.remove(&address);
+                }
+            }
+        }
+    }
+
+    pub fn balance(&self, address: &Address) -> f32 {
+        self.balances.get(address).copied().unwrap_or(0.0)
+    }
+
+    /// Sequence the next transaction sent by `address` has to carry
+    pub fn sequence(&self, address: &Address) -> u64 {
+        self.sequences.get(address).copied().unwrap_or(0)
+    }
+
+    /// Balances and sequences as the transactions of a block at `height` get
+    /// applied on top of this state, to check that each one is funded and
+    /// sent only once
+    pub fn spending(&self, height: u64) -> Spending<'_> {
+        Spending {
+            state: self,
+            height,
+            changes: BTreeMap::new(),
+            sent: BTreeMap::new(),
+        }
+    }
+
+    /// History of `address`, newest first, skipping `offset` entries and
+    /// returning at most `limit`
+    pub fn history(&self, address: &Address, offset: usize, limit: usize) -> Vec<HistoryEntry> {
+        self.history
+            .get(address)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .rev()
+                    .skip(offset)
+                    .take(limit)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Number of history entries of `address`
+    pub fn history_len(&self, address: &Address) -> usize {
+        self.history.get(address).map(Vec::len).unwrap_or(0)
+    }
+}
+
+/// Transactions applied in order on top of a `ChainState`, without touching it
+pub struct Spending<'a> {
+    state: &'a ChainState,
+    /// Height of the block the transactions go in
+    height: u64,
+    changes: BTreeMap<Address, f32>,
+    /// Transactions applied so far, by sender
+    sent: BTreeMap<Address, u64>,
+}
+
+impl Spending<'_> {
+    pub fn balance(&self, address: &Address) -> f32 {
+        self.state.balance(address) + self.changes.get(address).copied().unwrap_or(0.0)
+    }
+
+    pub fn sequence(&self, address: &Address) -> u64 {
+        self.state.sequence(address) + self.sent.get(address).copied().unwrap_or(0)
+    }
+
+    /// Applies `transaction` if it moves a positive value its sender can
+    /// afford, counting what the transactions applied before it moved, and
+    /// carries the next sequence of its sender, so that none is applied twice.
+    /// Rewards are minted, their value being checked with the block, and carry
+    /// the height of the block
+    pub fn apply(&mut self, transaction: &Transaction) -> Result<(), ValidationError> {
+        let value = transaction.value();
+        if !(value.is_finite() && value > 0.0) {
+            return Err(ValidationError::InvalidValue {
+                txid: transaction.id(),
+                value,
+            });
+        }
+
+        let sender = transaction.sender_address();
+        let expected = if transaction.is_reward() {
+            self.height
+        } else {
+            self.sequence(&sender)
+        };
+        if transaction.sequence() != expected {
+            return Err(ValidationError::InvalidSequence {
+                txid: transaction.id(),
+                expected,
+                found: transaction.sequence(),
+            });
+        }
+
+        if !transaction.is_reward() {
+            let balance = self.balance(&sender);
+            if balance < value {
+                return Err(ValidationError::InsufficientFunds {
+                    address: sender,
+                    balance,
+                    value,
+                });
+            }
+            *self.changes.entry(sender).or_default() -= value;
+            *self.sent.entry(sender).or_default() += 1;
+        }
+        *self
+            .changes
+            .entry(transaction.recipient_address())
+            .or_default() += value;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::TESTNET_VERSION;
+    use crate::block::BlockHash;
+    use crate::transaction::Transaction;
+    use std::sync::Arc;
+
+    fn block_with(transactions: Vec<Transaction>) -> Block {
+        Block::new(
+            0,
+            transactions.into_iter().map(Arc::new).collect(),
+            0,
+            BlockHash::default(),
+        )
+    }
+
+    #[test]
+    fn apply_then_revert_restores_the_index() {
+        let (a, b, c) = (
+            Address::new(TESTNET_VERSION, [1; 20]),
+            Address::new(TESTNET_VERSION, [2; 20]),
+            Address::new(TESTNET_VERSION, [3; 20]),
+        );
+        let mut state = ChainState::new();
+        let first = block_with(vec![Transaction::new(a, b, 10.0)]);
+        state.apply_block(1, &first);
+        let before = state.clone();
+
+        let second = block_with(vec![
+            Transaction::new(b, c, 4.0),
+            Transaction::new(a, c, 1.0).with_sequence(1),
+            Transaction::reward(c, 10.0, 2),
+        ]);
+        state.apply_block(2, &second);
+
+        assert_eq!(state.balance(&c), 15.0);
+        assert_eq!(state.sequence(&a), 2);
+        assert_eq!(state.sequence(&c), 0);
+        assert_eq!(state.history_len(&a), 2);
+        assert_eq!(state.history(&a, 0, 1)[0].height, 2);
+        assert_eq!(state.history(&a, 1, 10)[0].delta, -10.0);
+
+        state.revert_block(2, &second);
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn spending_needs_the_funds_in_order() {
+        let (a, b) = (
+            Address::new(TESTNET_VERSION, [1; 20]),
+            Address::new(TESTNET_VERSION, [2; 20]),
+        );
+        let mut state = ChainState::new();
+        state.apply_block(1, &block_with(vec![Transaction::reward(a, 10.0, 1)]));
+
+        let mut spending = state.spending(2);
+        spending.apply(&Transaction::new(a, b, 6.0)).unwrap();
+        assert_eq!(
+            spending.apply(&Transaction::new(a, b, 6.0).with_sequence(1)),
+            Err(ValidationError::InsufficientFunds {
+                address: a,
+                balance: 4.0,
+                value: 6.0
+            })
+        );
+        // What `b` received earlier is spendable
+        spending.apply(&Transaction::new(b, a, 6.0)).unwrap();
+        assert_eq!(spending.balance(&a), 10.0);
+        assert_eq!(spending.balance(&b), 0.0);
+
+        let negative = Transaction::new(a, b, -1.0).with_sequence(1);
+        assert_eq!(
+            spending.apply(&negative),
+            Err(ValidationError::InvalidValue {
+                txid: negative.id(),
+                value: -1.0
+            })
+        );
+        assert_eq!(state.balance(&a), 10.0);
+    }
+
+    #[test]
+    fn spending_needs_the_next_sequence() {
+        let (a, b) = (
+            Address::new(TESTNET_VERSION, [1; 20]),
+            Address::new(TESTNET_VERSION, [2; 20]),
+        );
+        let mut state = ChainState::new();
+        let first = Transaction::new(a, b, 1.0);
+        state.apply_block(
+            1,
+            &block_with(vec![Transaction::reward(a, 10.0, 1), first.clone()]),
+        );
+
+        // Replayed once in the chain, or twice in a block
+        let mut spending = state.spending(2);
+        assert_eq!(
+            spending.apply(&first),
+            Err(ValidationError::InvalidSequence {
+                txid: first.id(),
+                expected: 1,
+                found: 0
+            })
+        );
+        let second = Transaction::new(a, b, 1.0).with_sequence(1);
+        spending.apply(&second).unwrap();
+        assert!(spending.apply(&second).is_err());
+        assert_eq!(spending.sequence(&a), 2);
+
+        // Rewards carry the height of their block
+        assert!(spending.apply(&Transaction::reward(a, 10.0, 1)).is_err());
+        spending.apply(&Transaction::reward(a, 10.0, 2)).unwrap();
+    }
+}
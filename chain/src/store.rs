@@ -0,0 +1,546 @@
+use crate::block::{Block, BlockHash, BlockHeader};
+use crate::state::{BlockDelta, StateCheckpoint};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Storage backend for the chain.
+///
+/// Blocks are addressed by their hash, and a separate height index maps each
+/// height of the active chain to the hash of the block at that height. The tip
+/// pointer and arbitrary metadata are kept alongside.
+///
+/// Headers are stored apart from block bodies so that bodies can be pruned, and
+/// so are the changes each block makes to the chain state, which is only saved
+/// whole now and then as a checkpoint.
+///
+/// The tip is set after the height index grows and before it shrinks, so that
+/// it is always in the index. Stores that persist rely on this to recover from
+/// a crash in between, by cutting the index back to the tip when reopened.
+pub trait ChainStore: std::fmt::Debug + Send {
+    fn put_block(&mut self, hash: &BlockHash, block: &Block) -> Result<(), StoreError>;
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Option<Block>, StoreError>;
+
+    /// Removes the body of a block, its header is kept
+    fn delete_block(&mut self, hash: &BlockHash) -> Result<(), StoreError>;
+
+    fn put_header(&mut self, hash: &BlockHash, header: &BlockHeader) -> Result<(), StoreError>;
+
+    fn get_header(&self, hash: &BlockHash) -> Result<Option<BlockHeader>, StoreError>;
+
+    fn set_hash_at_height(&mut self, height: u64, hash: &BlockHash) -> Result<(), StoreError>;
+
+    fn hash_at_height(&self, height: u64) -> Result<Option<BlockHash>, StoreError>;
+
+    /// Number of entries in the height index, i.e. the tip height + 1.
+    fn block_count(&self) -> Result<u64, StoreError>;
+
+    /// Drops the height index entries from height `count` up, used when blocks
+    /// are disconnected. The blocks themselves stay stored.
+    fn truncate_heights(&mut self, count: u64) -> Result<(), StoreError>;
+
+    fn tip(&self) -> Result<Option<BlockHash>, StoreError>;
+
+    fn set_tip(&mut self, hash: &BlockHash) -> Result<(), StoreError>;
+
+    fn get_meta(&self, key: &str) -> Result<Option<String>, StoreError>;
+
+    fn put_meta(&mut self, key: &str, value: &str) -> Result<(), StoreError>;
+
+    fn put_delta(&mut self, hash: &BlockHash, delta: &BlockDelta) -> Result<(), StoreError>;
+
+    fn get_delta(&self, hash: &BlockHash) -> Result<Option<BlockDelta>, StoreError>;
+
+    /// Replaces the saved checkpoint of the chain state
+    fn put_checkpoint(&mut self, checkpoint: &StateCheckpoint) -> Result<(), StoreError>;
+
+    fn get_checkpoint(&self) -> Result<Option<StateCheckpoint>, StoreError>;
+}
+
+/// Keeps everything in memory, used by default and in tests.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    blocks: HashMap<BlockHash, Block>,
+    headers: HashMap<BlockHash, BlockHeader>,
+    heights: Vec<BlockHash>,
+    tip: Option<BlockHash>,
+    meta: HashMap<String, String>,
+    deltas: HashMap<BlockHash, BlockDelta>,
+    checkpoint: Option<StateCheckpoint>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn put_block(&mut self, hash: &BlockHash, block: &Block) -> Result<(), StoreError> {
+        self.blocks.insert(*hash, block.clone());
+        Ok(())
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Option<Block>, StoreError> {
+        Ok(self.blocks.get(hash).cloned())
+    }
+
+    fn delete_block(&mut self, hash: &BlockHash) -> Result<(), StoreError> {
+        self.blocks.remove(hash);
+        Ok(())
+    }
+
+    fn put_header(&mut self, hash: &BlockHash, header: &BlockHeader) -> Result<(), StoreError> {
+        self.headers.insert(*hash, header.clone());
+        Ok(())
+    }
+
+    fn get_header(&self, hash: &BlockHash) -> Result<Option<BlockHeader>, StoreError> {
+        Ok(self.headers.get(hash).cloned())
+    }
+
+    fn set_hash_at_height(&mut self, height: u64, hash: &BlockHash) -> Result<(), StoreError> {
+        set_height(&mut self.heights, height, hash)
+    }
+
+    fn hash_at_height(&self, height: u64) -> Result<Option<BlockHash>, StoreError> {
+        Ok(self.heights.get(height as usize).copied())
+    }
+
+    fn block_count(&self) -> Result<u64, StoreError> {
+        Ok(self.heights.len() as u64)
+    }
+
+    fn truncate_heights(&mut self, count: u64) -> Result<(), StoreError> {
+        self.heights.truncate(count as usize);
+        Ok(())
+    }
+
+    fn tip(&self) -> Result<Option<BlockHash>, StoreError> {
+        Ok(self.tip)
+    }
+
+    fn set_tip(&mut self, hash: &BlockHash) -> Result<(), StoreError> {
+        self.tip = Some(*hash);
+        Ok(())
+    }
+
+    fn get_meta(&self, key: &str) -> Result<Option<String>, StoreError> {
+        Ok(self.meta.get(key).cloned())
+    }
+
+    fn put_meta(&mut self, key: &str, value: &str) -> Result<(), StoreError> {
+        self.meta.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn put_delta(&mut self, hash: &BlockHash, delta: &BlockDelta) -> Result<(), StoreError> {
+        self.deltas.insert(*hash, delta.clone());
+        Ok(())
+    }
+
+    fn get_delta(&self, hash: &BlockHash) -> Result<Option<BlockDelta>, StoreError> {
+        Ok(self.deltas.get(hash).cloned())
+    }
+
+    fn put_checkpoint(&mut self, checkpoint: &StateCheckpoint) -> Result<(), StoreError> {
+        self.checkpoint = Some(checkpoint.clone());
+        Ok(())
+    }
+
+    fn get_checkpoint(&self) -> Result<Option<StateCheckpoint>, StoreError> {
+        Ok(self.checkpoint.clone())
+    }
+}
+
+/// Persists the chain under a directory:
+///
+/// - `blocks/<hash>.json` one file per block body
+/// - `headers/<hash>.json` one file per block header
+/// - `deltas/<hash>.json` changes to the chain state, one file per block
+/// - `state.json` checkpoint of the chain state
+/// - `heights` one block hash per line, line number being the height
+/// - `tip` hash of the current tip
+/// - `meta.json` metadata key/value pairs
+///
+/// The height index and metadata are small enough to be kept in memory. The
+/// metadata is rewritten on change, while lines of the index have a fixed
+/// length and are written in place, so that connecting or disconnecting a block
+/// doesn't rewrite the whole index.
+#[derive(Debug)]
+pub struct DiskStore {
+    path: PathBuf,
+    heights: Vec<BlockHash>,
+    tip: Option<BlockHash>,
+    meta: HashMap<String, String>,
+}
+
+impl DiskStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(path.join("blocks"))?;
+        fs::create_dir_all(path.join("headers"))?;
+        fs::create_dir_all(path.join("deltas"))?;
+
+        let (mut heights, stored_len) = match fs::read_to_string(path.join("heights")) {
+            // A crash while writing can leave the last line incomplete
+            Ok(content) => {
+                let complete = content.rfind('\n').map_or(0, |end| end + 1);
+                let heights: Vec<BlockHash> = content[..complete]
+                    .lines()
+                    .map(parse_hash)
+                    .collect::<Result<_, _>>()?;
+                (heights, content.len() as u64)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (Vec::new(), 0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let tip = match fs::read_to_string(path.join("tip")) {
+            Ok(content) => Some(parse_hash(content.trim())?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        // Heights above the tip were written by a block that was never made
+        // the tip, or left behind by one disconnected since
+        match tip {
+            Some(tip) => {
+                let height = heights
+                    .iter()
+                    .rposition(|hash| *hash == tip)
+                    .ok_or_else(|| {
+                        StoreError::Corrupt(format!("tip {} is not in the height index", tip))
+                    })?;
+                heights.truncate(height + 1);
+            }
+            None => heights.clear(),
+        }
+
+        let meta = match fs::read_to_string(path.join("meta.json")) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let store = Self {
+            path,
+            heights,
+            tip,
+            meta,
+        };
+        if stored_len != store.heights.len() as u64 * HEIGHT_LINE_LEN {
+            store.truncate_heights_file()?;
+        }
+        Ok(store)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn block_path(&self, hash: &BlockHash) -> PathBuf {
+        self.path.join("blocks").join(format!("{}.json", hash))
+    }
+
+    fn header_path(&self, hash: &BlockHash) -> PathBuf {
+        self.path.join("headers").join(format!("{}.json", hash))
+    }
+
+    fn delta_path(&self, hash: &BlockHash) -> PathBuf {
+        self.path.join("deltas").join(format!("{}.json", hash))
+    }
+
+    fn heights_path(&self) -> PathBuf {
+        self.path.join("heights")
+    }
+
+    /// Writes the line of the index at `height`, which has to be at most one
+    /// past the last line of the file
+    fn write_height(&self, height: u64, hash: &BlockHash) -> Result<(), StoreError> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.heights_path())?;
+        file.seek(SeekFrom::Start(height * HEIGHT_LINE_LEN))?;
+        file.write_all(format!("{}\n", hash).as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Cuts the index file down to the heights held in memory
+    fn truncate_heights_file(&self) -> Result<(), StoreError> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.heights_path())?;
+        file.set_len(self.heights.len() as u64 * HEIGHT_LINE_LEN)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Write to a temporary file first and rename it over the target, so a
+    /// crash never leaves a half written file behind.
+    fn write_atomic(&self, target: &Path, content: &[u8]) -> Result<(), StoreError> {
+        let tmp = target.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(tmp, target)?;
+        Ok(())
+    }
+}
+
+impl ChainStore for DiskStore {
+    fn put_block(&mut self, hash: &BlockHash, block: &Block) -> Result<(), StoreError> {
+        let block_json = serde_json::to_vec(block)?;
+        self.write_atomic(&self.block_path(hash), &block_json)
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Option<Block>, StoreError> {
+        read_json(&self.block_path(hash))
+    }
+
+    fn delete_block(&mut self, hash: &BlockHash) -> Result<(), StoreError> {
+        match fs::remove_file(self.block_path(hash)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn put_header(&mut self, hash: &BlockHash, header: &BlockHeader) -> Result<(), StoreError> {
+        let header_json = serde_json::to_vec(header)?;
+        self.write_atomic(&self.header_path(hash), &header_json)
+    }
+
+    fn get_header(&self, hash: &BlockHash) -> Result<Option<BlockHeader>, StoreError> {
+        read_json(&self.header_path(hash))
+    }
+
+    fn set_hash_at_height(&mut self, height: u64, hash: &BlockHash) -> Result<(), StoreError> {
+        set_height(&mut self.heights, height, hash)?;
+        self.write_height(height, hash)
+    }
+
+    fn hash_at_height(&self, height: u64) -> Result<Option<BlockHash>, StoreError> {
+        Ok(self.heights.get(height as usize).copied())
+    }
+
+    fn block_count(&self) -> Result<u64, StoreError> {
+        Ok(self.heights.len() as u64)
+    }
+
+    fn truncate_heights(&mut self, count: u64) -> Result<(), StoreError> {
+        if count < self.heights.len() as u64 {
+            self.heights.truncate(count as usize);
+            self.truncate_heights_file()?;
+        }
+        Ok(())
+    }
+
+    fn tip(&self) -> Result<Option<BlockHash>, StoreError> {
+        Ok(self.tip)
+    }
+
+    fn set_tip(&mut self, hash: &BlockHash) -> Result<(), StoreError> {
+        self.write_atomic(&self.path.join("tip"), hash.to_string().as_bytes())?;
+        self.tip = Some(*hash);
+        Ok(())
+    }
+
+    fn get_meta(&self, key: &str) -> Result<Option<String>, StoreError> {
+        Ok(self.meta.get(key).cloned())
+    }
+
+    fn put_meta(&mut self, key: &str, value: &str) -> Result<(), StoreError> {
+        self.meta.insert(key.to_string(), value.to_string());
+        let meta_json = serde_json::to_vec(&self.meta)?;
+        self.write_atomic(&self.path.join("meta.json"), &meta_json)
+    }
+
+    fn put_delta(&mut self, hash: &BlockHash, delta: &BlockDelta) -> Result<(), StoreError> {
+        let delta_json = serde_json::to_vec(delta)?;
+        self.write_atomic(&self.delta_path(hash), &delta_json)
+    }
+
+    fn get_delta(&self, hash: &BlockHash) -> Result<Option<BlockDelta>, StoreError> {
+        read_json(&self.delta_path(hash))
+    }
+
+    fn put_checkpoint(&mut self, checkpoint: &StateCheckpoint) -> Result<(), StoreError> {
+        let checkpoint_json = serde_json::to_vec(checkpoint)?;
+        self.write_atomic(&self.path.join("state.json"), &checkpoint_json)
+    }
+
+    fn get_checkpoint(&self) -> Result<Option<StateCheckpoint>, StoreError> {
+        read_json(&self.path.join("state.json"))
+    }
+}
+
+/// A hex encoded block hash and a newline
+const HEIGHT_LINE_LEN: u64 = 65;
+
+fn parse_hash(hash: &str) -> Result<BlockHash, StoreError> {
+    hash.parse()
+        .map_err(|_| StoreError::Corrupt(format!("invalid block hash {}", hash)))
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, StoreError> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Heights can only be overwritten or appended, never skipped.
+fn set_height(
+    heights: &mut Vec<BlockHash>,
+    height: u64,
+    hash: &BlockHash,
+) -> Result<(), StoreError> {
+    let height = height as usize;
+    match height.cmp(&heights.len()) {
+        std::cmp::Ordering::Less => heights[height] = *hash,
+        std::cmp::Ordering::Equal => heights.push(*hash),
+        std::cmp::Ordering::Greater => {
+            return Err(StoreError::HeightGap {
+                height: height as u64,
+                count: heights.len() as u64,
+            })
+        }
+    }
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StoreError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serialization error: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("cannot index height {height}, only {count} heights are stored")]
+    HeightGap { height: u64, count: u64 },
+    #[error("store is corrupt: {0}")]
+    Corrupt(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "blocksmith-store-{}-{}",
+            name,
+            Block::generate_timestamp()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn exercise(store: &mut dyn ChainStore) {
+        let block = Block::genesis();
+        let hash = block.hash();
+
+        assert!(store.get_block(&hash).unwrap().is_none());
+        assert_eq!(store.block_count().unwrap(), 0);
+
+        store.put_block(&hash, &block).unwrap();
+        store.put_header(&hash, &block.header()).unwrap();
+        store.set_hash_at_height(0, &hash).unwrap();
+        store.set_tip(&hash).unwrap();
+        store.put_meta("difficulty", "3").unwrap();
+
+        assert_eq!(store.get_block(&hash).unwrap().unwrap().hash(), hash);
+        assert_eq!(store.hash_at_height(0).unwrap(), Some(hash));
+        assert_eq!(store.block_count().unwrap(), 1);
+        assert_eq!(store.tip().unwrap(), Some(hash));
+        assert_eq!(store.get_meta("difficulty").unwrap(), Some("3".into()));
+        let next = BlockHash::digest(b"next");
+        assert!(store.set_hash_at_height(5, &next).is_err());
+
+        store.set_hash_at_height(1, &next).unwrap();
+        store.truncate_heights(1).unwrap();
+        assert_eq!(store.block_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn deleting_a_block_keeps_its_header() {
+        let mut store = MemoryStore::new();
+        let block = Block::genesis();
+        let hash = block.hash();
+        store.put_block(&hash, &block).unwrap();
+        store.put_header(&hash, &block.header()).unwrap();
+
+        store.delete_block(&hash).unwrap();
+
+        assert!(store.get_block(&hash).unwrap().is_none());
+        assert_eq!(store.get_header(&hash).unwrap(), Some(block.header()));
+    }
+
+    #[test]
+    fn memory_store_roundtrip() {
+        exercise(&mut MemoryStore::new());
+    }
+
+    #[test]
+    fn disk_store_roundtrip_and_reopen() {
+        let dir = temp_dir("roundtrip");
+        exercise(&mut DiskStore::open(&dir).unwrap());
+
+        let reopened = DiskStore::open(&dir).unwrap();
+        let hash = Block::genesis().hash();
+        assert_eq!(reopened.tip().unwrap(), Some(hash));
+        assert_eq!(reopened.hash_at_height(0).unwrap(), Some(hash));
+        assert!(reopened.get_block(&hash).unwrap().is_some());
+        assert_eq!(reopened.get_meta("difficulty").unwrap(), Some("3".into()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn disk_store_cuts_the_height_index_back_to_the_tip() {
+        let dir = temp_dir("crash");
+        let hashes: Vec<BlockHash> = (0..3u8).map(|i| BlockHash::digest([i])).collect();
+        let mut store = DiskStore::open(&dir).unwrap();
+        for (height, hash) in hashes.iter().enumerate() {
+            store.set_hash_at_height(height as u64, hash).unwrap();
+        }
+        // Crashed before making the last block the tip, halfway through
+        // indexing the one after
+        store.set_tip(&hashes[1]).unwrap();
+        drop(store);
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join("heights"))
+            .unwrap();
+        file.write_all(b"00ab").unwrap();
+        drop(file);
+
+        let mut store = DiskStore::open(&dir).unwrap();
+        assert_eq!(store.block_count().unwrap(), 2);
+        assert_eq!(store.hash_at_height(1).unwrap(), Some(hashes[1]));
+        assert_eq!(
+            fs::metadata(dir.join("heights")).unwrap().len(),
+            2 * HEIGHT_LINE_LEN
+        );
+
+        store.set_hash_at_height(2, &hashes[2]).unwrap();
+        store.set_tip(&hashes[2]).unwrap();
+        let reopened = DiskStore::open(&dir).unwrap();
+        assert_eq!(reopened.block_count().unwrap(), 3);
+        assert_eq!(reopened.hash_at_height(2).unwrap(), Some(hashes[2]));
+
+        // A tip the index doesn't hold can't be recovered from
+        fs::write(dir.join("tip"), BlockHash::digest(b"elsewhere").to_string()).unwrap();
+        assert!(matches!(DiskStore::open(&dir), Err(StoreError::Corrupt(_))));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,29 +1,523 @@
+use crate::address::{Address, AddressKind, KeyFormat, TESTNET_VERSION};
+use crate::hash::Hash256;
+use crate::multisig::Multisig;
+
+use secp256k1::{ecdsa, schnorr, Keypair, Message, PublicKey, SecretKey, SECP256K1};
 use serde::{Deserialize, Serialize};
 
+/// Sender of the mining reward transactions, an address nobody holds the key of
+pub const NETWORK_ADDRESS: Address = Address::new(TESTNET_VERSION, [0; 20]);
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Transaction {
-    sender_address: String,
-    recipient_address: String,
+    sender_address: Address,
+    recipient_address: Address,
+    value: f32,
+    /// How many transactions the sender sent before this one, so that a signed
+    /// transaction is only ever valid once. Rewards carry the height of their
+    /// block instead, which gives each of them an id of its own
+    sequence: u64,
+    /// Hex encoded public key of the sender, set when signed. Serialized in
+    /// the format the sender address was derived from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    public_key: Option<String>,
+    /// Hex encoded signature of `signing_hash`, compact ECDSA or BIP340
+    /// Schnorr as told by `signature_type`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+    /// Left out for ECDSA, so transactions signed before Schnorr keep their id
+    #[serde(default, skip_serializing_if = "SignatureType::is_ecdsa")]
+    signature_type: SignatureType,
+    /// Policy of a multisig sender, which has to hash to its address. Takes
+    /// the place of `public_key` and `signature`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    multisig: Option<Multisig>,
+    /// Hex encoded compact ECDSA signatures of `signing_hash` by cosigners,
+    /// exactly the threshold of them in the order of the policy keys
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    signatures: Vec<String>,
+}
+
+/// Signature scheme of a transaction, both accepted by consensus
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureType {
+    #[default]
+    Ecdsa,
+    /// BIP340, verified against the x-only form of the sender key
+    Schnorr,
+}
+
+impl SignatureType {
+    fn is_ecdsa(&self) -> bool {
+        *self == Self::Ecdsa
+    }
+}
+
+/// The part of a transaction its signature covers
+#[derive(Serialize)]
+struct SignedContent<'a> {
+    sender_address: &'a Address,
+    recipient_address: &'a Address,
     value: f32,
+    sequence: u64,
+    /// Covered so that a signature cannot be relabelled as the other scheme
+    #[serde(skip_serializing_if = "SignatureType::is_ecdsa")]
+    signature_type: SignatureType,
 }
 
 impl Transaction {
-    pub fn new(sender_address: String, recipient_address: String, value: f32) -> Self {
+    /// Unsigned transaction, the first one of its sender until given another
+    /// sequence
+    pub fn new(sender_address: Address, recipient_address: Address, value: f32) -> Self {
         Self {
             sender_address,
             recipient_address,
             value,
+            sequence: 0,
+            public_key: None,
+            signature: None,
+            signature_type: SignatureType::Ecdsa,
+            multisig: None,
+            signatures: Vec::new(),
         }
     }
 
-    pub fn sender_address(&self) -> String {
-        self.sender_address.clone()
+    /// The same transaction as the `sequence`-th one of its sender. Signatures
+    /// have to be made afterwards, as they cover the sequence
+    pub fn with_sequence(self, sequence: u64) -> Self {
+        Self { sequence, ..self }
+    }
+
+    /// Unsigned transaction paying the mining reward of the block at `height`
+    /// to `recipient`
+    pub fn reward(recipient_address: Address, value: f32, height: u64) -> Self {
+        Self::new(NETWORK_ADDRESS, recipient_address, value).with_sequence(height)
     }
 
-    pub fn recipient_address(&self) -> String {
-        self.recipient_address.clone()
+    /// Whether this pays a mining reward, the only transaction needing no signature
+    pub fn is_reward(&self) -> bool {
+        self.sender_address == NETWORK_ADDRESS
+    }
+
+    pub fn sender_address(&self) -> Address {
+        self.sender_address
+    }
+
+    pub fn recipient_address(&self) -> Address {
+        self.recipient_address
     }
     pub fn value(&self) -> f32 {
         self.value
     }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn signature_type(&self) -> SignatureType {
+        self.signature_type
+    }
+
+    /// SHA-256 of the JSON serialized transaction
+    pub fn id(&self) -> Hash256 {
+        let transaction_json =
+            serde_json::to_string(self).expect("Transaction serialization cannot fail");
+        Hash256::digest(transaction_json.as_bytes())
+    }
+
+    /// SHA-256 of the sender, recipient, value, sequence and signature scheme,
+    /// which is what gets signed
+    pub fn signing_hash(&self) -> Hash256 {
+        let content = SignedContent {
+            sender_address: &self.sender_address,
+            recipient_address: &self.recipient_address,
+            value: self.value,
+            sequence: self.sequence,
+            signature_type: self.signature_type,
+        };
+        let content_json =
+            serde_json::to_string(&content).expect("Transaction serialization cannot fail");
+        Hash256::digest(content_json.as_bytes())
+    }
+
+    /// Signs the transaction with the key of the sender, replacing any previous
+    /// signature. `key_format` is the one the sender address was derived with.
+    /// Signatures are ECDSA, except for taproot senders, which need Schnorr
+    pub fn sign(&mut self, private_key: &SecretKey, key_format: KeyFormat) {
+        let signature_type = match self.sender_address.kind() {
+            AddressKind::Taproot => SignatureType::Schnorr,
+            _ => SignatureType::Ecdsa,
+        };
+        self.sign_with(private_key, key_format, signature_type);
+    }
+
+    /// Signs the transaction like `sign`, in the scheme `signature_type`
+    pub fn sign_with(
+        &mut self,
+        private_key: &SecretKey,
+        key_format: KeyFormat,
+        signature_type: SignatureType,
+    ) {
+        self.signature_type = signature_type;
+        let message = Message::from_digest(*self.signing_hash().as_bytes());
+        let signature = match signature_type {
+            SignatureType::Ecdsa => SECP256K1
+                .sign_ecdsa(&message, private_key)
+                .serialize_compact(),
+            SignatureType::Schnorr => *SECP256K1
+                .sign_schnorr(&message, &Keypair::from_secret_key(SECP256K1, private_key))
+                .as_ref(),
+        };
+
+        self.public_key = Some(hex::encode(
+            key_format.serialize(&PublicKey::from_secret_key(SECP256K1, private_key)),
+        ));
+        self.signature = Some(hex::encode(signature));
+    }
+
+    /// Adds the signature of a cosigner of the multisig sender described by
+    /// `multisig`, keeping the valid ones already made under the same policy
+    /// in the order of the keys. Keys that are not the policy's, or signing
+    /// once the threshold is reached, add nothing
+    pub fn sign_multisig(&mut self, multisig: &Multisig, private_key: &SecretKey) {
+        if self.multisig.as_ref() != Some(multisig) {
+            self.multisig = Some(multisig.clone());
+            self.signatures.clear();
+        }
+        self.signature_type = SignatureType::Ecdsa;
+        let signing_hash = self.signing_hash();
+        let mut signed: Vec<(usize, String)> = std::mem::take(&mut self.signatures)
+            .into_iter()
+            .filter_map(|signature| Some((multisig.signer(&signing_hash, &signature)?, signature)))
+            .collect();
+
+        let public_key = PublicKey::from_secret_key(SECP256K1, private_key);
+        let signer = multisig
+            .public_keys()
+            .iter()
+            .position(|key| *key == public_key);
+        if let Some(signer) = signer.filter(|signer| {
+            signed.len() < multisig.threshold() && signed.iter().all(|(other, _)| other != signer)
+        }) {
+            let message = Message::from_digest(*signing_hash.as_bytes());
+            let signature = SECP256K1.sign_ecdsa(&message, private_key);
+            signed.push((signer, hex::encode(signature.serialize_compact())));
+            signed.sort_by_key(|(signer, _)| *signer);
+        }
+        self.signatures = signed.into_iter().map(|(_, signature)| signature).collect();
+    }
+
+    pub fn multisig(&self) -> Option<&Multisig> {
+        self.multisig.as_ref()
+    }
+
+    /// How many keys of the multisig sender signed the transaction, in the
+    /// order of the keys, none when the policy is missing, belongs to another
+    /// address or the sender is a key hash
+    pub fn multisig_signers(&self) -> usize {
+        match &self.multisig {
+            Some(multisig)
+                if self.sender_address.kind() == AddressKind::ScriptHash
+                    && multisig.address() == self.sender_address =>
+            {
+                multisig.signers(&self.signing_hash(), &self.signatures)
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.public_key.is_some()
+            || self.signature.is_some()
+            || self.multisig.is_some()
+            || !self.signatures.is_empty()
+    }
+
+    /// Whether the transaction carries a signature of its content by a key that
+    /// belongs to the sender address, or enough cosigner signatures when the
+    /// sender is a script hash. Taproot keys only sign in Schnorr
+    pub fn has_valid_signature(&self) -> bool {
+        if self.sender_address.kind() == AddressKind::ScriptHash
+            || self.multisig.is_some()
+            || !self.signatures.is_empty()
+        {
+            return self.has_valid_multisig();
+        }
+        let (Some(public_key), Some(signature)) = (&self.public_key, &self.signature) else {
+            return false;
+        };
+        let Some((public_key, key_format)) = hex::decode(public_key).ok().and_then(|bytes| {
+            Some((
+                PublicKey::from_slice(&bytes).ok()?,
+                KeyFormat::of_serialized(&bytes)?,
+            ))
+        }) else {
+            return false;
+        };
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        if !self.sender_address.is_derived_from(&public_key, key_format)
+            || (self.sender_address.kind() == AddressKind::Taproot
+                && self.signature_type != SignatureType::Schnorr)
+        {
+            return false;
+        }
+
+        let message = Message::from_digest(*self.signing_hash().as_bytes());
+        match self.signature_type {
+            SignatureType::Ecdsa => ecdsa::Signature::from_compact(&signature)
+                .and_then(|signature| SECP256K1.verify_ecdsa(&message, &signature, &public_key))
+                .is_ok(),
+            SignatureType::Schnorr => schnorr::Signature::from_slice(&signature)
+                .and_then(|signature| {
+                    SECP256K1.verify_schnorr(
+                        &signature,
+                        &message,
+                        &public_key.x_only_public_key().0,
+                    )
+                })
+                .is_ok(),
+        }
+    }
+
+    /// Whether exactly the threshold of the multisig sender's keys signed, in
+    /// the order of the keys, in ECDSA and without a single key signature
+    /// alongside. The sender has to be a script hash, as policies are. Any
+    /// other number of signatures, such as more than there are keys, is
+    /// refused before verifying one
+    fn has_valid_multisig(&self) -> bool {
+        let Some(multisig) = &self.multisig else {
+            return false;
+        };
+        if self.signatures.len() != multisig.threshold() {
+            return false;
+        }
+        self.sender_address.kind() == AddressKind::ScriptHash
+            && self.public_key.is_none()
+            && self.signature.is_none()
+            && self.signature_type == SignatureType::Ecdsa
+            && self.multisig_signers() == multisig.threshold()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::{Encoding, TESTNET_VERSION};
+    use crate::multisig::Multisig;
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn signatures_cover_the_content_and_the_sender() {
+        let wallet = Wallet::generate_new();
+        let recipient = Address::new(TESTNET_VERSION, [2; 20]);
+        let mut transaction = Transaction::new(wallet.address(), recipient, 1.0);
+        assert!(!transaction.is_signed());
+        assert!(!transaction.has_valid_signature());
+
+        wallet.sign_transaction(&mut transaction);
+        assert!(transaction.has_valid_signature());
+
+        let mut tampered = transaction.clone();
+        tampered.value = 100.0;
+        assert!(!tampered.has_valid_signature());
+        let mut resequenced = transaction.clone();
+        resequenced.sequence = 1;
+        assert!(!resequenced.has_valid_signature());
+
+        let someone_else = Address::new(TESTNET_VERSION, [1; 20]);
+        let mut stolen = Transaction::new(someone_else, recipient, 1.0);
+        wallet.sign_transaction(&mut stolen);
+        assert!(!stolen.has_valid_signature());
+    }
+
+    #[test]
+    fn rewards_of_each_height_have_their_own_id() {
+        let miner = Address::new(TESTNET_VERSION, [2; 20]);
+        let reward = Transaction::reward(miner, 10.0, 1);
+        assert!(reward.is_reward());
+        assert_eq!(reward.sequence(), 1);
+        assert_ne!(reward.id(), Transaction::reward(miner, 10.0, 2).id());
+    }
+
+    #[test]
+    fn signatures_carry_the_key_format_of_the_sender_address() {
+        let recipient = Address::new(TESTNET_VERSION, [2; 20]);
+        let private_key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        let public_key = PublicKey::from_secret_key(SECP256K1, &private_key);
+        let legacy = Wallet::with_key_format(public_key, private_key, KeyFormat::Uncompressed);
+
+        let mut transaction = Transaction::new(legacy.address(), recipient, 1.0);
+        legacy.sign_transaction(&mut transaction);
+        assert_eq!(transaction.public_key.as_ref().unwrap().len(), 130);
+        assert!(transaction.has_valid_signature());
+
+        // The same key, compressed, belongs to another address
+        transaction.sign(&private_key, KeyFormat::Compressed);
+        assert!(!transaction.has_valid_signature());
+    }
+
+    #[test]
+    fn schnorr_signatures_are_flagged_and_covered() {
+        let wallet = Wallet::generate_new();
+        let recipient = Address::new(TESTNET_VERSION, [2; 20]);
+        let unsigned = Transaction::new(wallet.address(), recipient, 1.0);
+
+        let mut ecdsa = unsigned.clone();
+        wallet.sign_transaction(&mut ecdsa);
+        let json = serde_json::to_value(&ecdsa).unwrap();
+        assert!(json.get("signature_type").is_none());
+
+        let mut schnorr = unsigned.clone();
+        wallet.sign_transaction_with(&mut schnorr, SignatureType::Schnorr);
+        assert_eq!(schnorr.signature_type(), SignatureType::Schnorr);
+        assert!(schnorr.has_valid_signature());
+        assert_ne!(schnorr.signing_hash(), ecdsa.signing_hash());
+
+        let json = serde_json::to_value(&schnorr).unwrap();
+        assert_eq!(json["signature_type"], "schnorr");
+        let restored: Transaction = serde_json::from_value(json.clone()).unwrap();
+        assert!(restored.has_valid_signature());
+        assert_eq!(restored.id(), schnorr.id());
+
+        // Relabelling either signature as the other scheme breaks it
+        let mut relabelled = json;
+        relabelled["signature_type"] = serde_json::json!("ecdsa");
+        let relabelled: Transaction = serde_json::from_value(relabelled).unwrap();
+        assert!(!relabelled.has_valid_signature());
+        let mut relabelled = serde_json::to_value(&ecdsa).unwrap();
+        relabelled["signature_type"] = serde_json::json!("schnorr");
+        let relabelled: Transaction = serde_json::from_value(relabelled).unwrap();
+        assert!(!relabelled.has_valid_signature());
+
+        let mut tampered = schnorr.clone();
+        tampered.value = 100.0;
+        assert!(!tampered.has_valid_signature());
+    }
+
+    #[test]
+    fn multisig_senders_need_the_threshold_of_signatures() {
+        let cosigners: Vec<Wallet> = (0..3).map(|_| Wallet::generate_new()).collect();
+        let multisig = Multisig::new(
+            2,
+            cosigners.iter().map(|wallet| wallet.public_key()).collect(),
+        )
+        .unwrap();
+        let recipient = Address::new(TESTNET_VERSION, [2; 20]);
+        let mut transaction = Transaction::new(multisig.address(), recipient, 1.0);
+
+        cosigners[0].sign_multisig(&mut transaction, &multisig);
+        assert!(transaction.is_signed());
+        assert_eq!(transaction.multisig_signers(), 1);
+        assert!(!transaction.has_valid_signature());
+
+        // The same key signing again is still one signer
+        cosigners[0].sign_multisig(&mut transaction, &multisig);
+        assert_eq!(transaction.multisig_signers(), 1);
+        assert!(!transaction.has_valid_signature());
+
+        let outsider = Wallet::generate_new();
+        let mut with_outsider = transaction.clone();
+        outsider.sign_multisig(&mut with_outsider, &multisig);
+        assert_eq!(with_outsider.multisig_signers(), 1);
+        assert!(!with_outsider.has_valid_signature());
+
+        cosigners[2].sign_multisig(&mut transaction, &multisig);
+        assert_eq!(transaction.multisig_signers(), 2);
+        assert!(transaction.has_valid_signature());
+
+        // Signing past the threshold adds nothing
+        let mut extra = transaction.clone();
+        cosigners[1].sign_multisig(&mut extra, &multisig);
+        assert_eq!(extra.id(), transaction.id());
+
+        // Nor can a relayer append, duplicate or reorder signatures
+        let mut padded = transaction.clone();
+        padded.signatures.push(padded.signatures[0].clone());
+        assert!(!padded.has_valid_signature());
+        let mut junk = transaction.clone();
+        junk.signatures = vec![String::from("00"); 1000];
+        assert!(!junk.has_valid_signature());
+        let mut reordered = transaction.clone();
+        reordered.signatures.reverse();
+        assert!(!reordered.has_valid_signature());
+
+        let restored: Transaction =
+            serde_json::from_value(serde_json::to_value(&transaction).unwrap()).unwrap();
+        assert!(restored.has_valid_signature());
+
+        let mut tampered = transaction.clone();
+        tampered.value = 100.0;
+        assert!(!tampered.has_valid_signature());
+
+        // A policy only spends from the address it hashes to
+        let mut elsewhere = transaction.clone();
+        elsewhere.sender_address = Address::new(TESTNET_VERSION, [1; 20]);
+        cosigners[0].sign_multisig(&mut elsewhere, &multisig);
+        cosigners[1].sign_multisig(&mut elsewhere, &multisig);
+        assert_eq!(elsewhere.multisig_signers(), 0);
+        assert!(!elsewhere.has_valid_signature());
+
+        // Nor from the key hash address of the same hash
+        let mut key_hash = transaction.clone();
+        key_hash.sender_address =
+            Address::new(TESTNET_VERSION, *multisig.address().hash().unwrap());
+        cosigners[0].sign_multisig(&mut key_hash, &multisig);
+        cosigners[1].sign_multisig(&mut key_hash, &multisig);
+        assert_eq!(key_hash.multisig_signers(), 0);
+        assert!(!key_hash.has_valid_signature());
+
+        // And a single key never spends from a script hash
+        let mut single = Transaction::new(multisig.address(), recipient, 1.0);
+        cosigners[0].sign_transaction(&mut single);
+        assert!(!single.has_valid_signature());
+    }
+
+    #[test]
+    fn segwit_senders_sign_with_compressed_keys() {
+        let recipient = Address::new(TESTNET_VERSION, [2; 20]);
+        let private_key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        let public_key = PublicKey::from_secret_key(SECP256K1, &private_key);
+
+        let wallet = Wallet::new(public_key, private_key);
+        let mut transaction = Transaction::new(wallet.bech32_address(), recipient, 1.0);
+        wallet.sign_transaction(&mut transaction);
+        assert!(transaction.has_valid_signature());
+
+        // Segwit addresses of uncompressed keys are never spendable
+        let legacy = Wallet::with_key_format(public_key, private_key, KeyFormat::Uncompressed);
+        let sender = legacy.address().with_encoding(Encoding::Bech32);
+        let mut transaction = Transaction::new(sender, recipient, 1.0);
+        legacy.sign_transaction(&mut transaction);
+        assert!(!transaction.has_valid_signature());
+    }
+
+    #[test]
+    fn taproot_senders_sign_in_schnorr() {
+        let recipient = Address::new(TESTNET_VERSION, [2; 20]);
+        let private_key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        let public_key = PublicKey::from_secret_key(SECP256K1, &private_key);
+
+        let wallet = Wallet::new(public_key, private_key);
+        let mut transaction = Transaction::new(wallet.taproot_address(), recipient, 1.0);
+        wallet.sign_transaction(&mut transaction);
+        assert_eq!(transaction.signature_type(), SignatureType::Schnorr);
+        assert!(transaction.has_valid_signature());
+
+        // The x-only key is the same whichever way the key is serialized
+        let legacy = Wallet::with_key_format(public_key, private_key, KeyFormat::Uncompressed);
+        assert_eq!(legacy.taproot_address(), wallet.taproot_address());
+        legacy.sign_transaction(&mut transaction);
+        assert!(transaction.has_valid_signature());
+
+        wallet.sign_transaction_with(&mut transaction, SignatureType::Ecdsa);
+        assert!(!transaction.has_valid_signature());
+
+        let someone_else = Wallet::generate_new();
+        let mut stolen = Transaction::new(wallet.taproot_address(), recipient, 1.0);
+        someone_else.sign_transaction(&mut stolen);
+        assert!(!stolen.has_valid_signature());
+    }
 }
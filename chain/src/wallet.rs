@@ -1,136 +1,339 @@
-use ripemd::Digest;
+use crate::address::{Address, AddressError, Encoding, KeyFormat, TESTNET_VERSION};
+use crate::hash::Hash256;
+use crate::message::{self, MessageError};
+use crate::multisig::Multisig;
+use crate::transaction::{SignatureType, Transaction};
+use crate::wif::{Wif, WifError};
+
 use secp256k1::rand::rngs::OsRng;
 use secp256k1::Secp256k1;
-use secp256k1::{PublicKey, SecretKey};
+use secp256k1::{schnorr, Keypair, Message, PublicKey, SecretKey, XOnlyPublicKey, SECP256K1};
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
 pub struct Wallet {
     public_key: PublicKey,
+    /// Never serialized, keys are written out sealed in a `Keystore`
+    #[serde(skip_serializing)]
     private_key: SecretKey,
-    address: String,
+    key_format: KeyFormat,
+    address: Address,
+    bech32_address: Address,
+    taproot_address: Address,
 }
 
 impl Wallet {
     pub fn new(public_key: PublicKey, private_key: SecretKey) -> Self {
+        Self::with_key_format(public_key, private_key, KeyFormat::Compressed)
+    }
+
+    /// Wallet whose address is derived from the key serialized in `key_format`,
+    /// `KeyFormat::Uncompressed` for legacy addresses
+    pub fn with_key_format(
+        public_key: PublicKey,
+        private_key: SecretKey,
+        key_format: KeyFormat,
+    ) -> Self {
+        let address = Address::from_public_key(&public_key, key_format);
         Self {
             public_key,
             private_key,
-            address: Self::generate_address(public_key),
+            key_format,
+            address,
+            bech32_address: address.with_encoding(Encoding::Bech32),
+            taproot_address: Address::taproot(&public_key),
         }
     }
-    pub fn address(&self) -> String {
-        self.address.clone()
+
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    pub(crate) fn private_key(&self) -> &SecretKey {
+        &self.private_key
+    }
+
+    pub fn key_format(&self) -> KeyFormat {
+        self.key_format
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Native segwit form of the key hash, a distinct address from `address`
+    pub fn bech32_address(&self) -> Address {
+        self.bech32_address
+    }
+
+    /// Taproot address of the key, spent with Schnorr signatures
+    pub fn taproot_address(&self) -> Address {
+        self.taproot_address
     }
     pub fn generate_new() -> Self {
         let secp = Secp256k1::new();
         let (private_key, public_key) = secp.generate_keypair(&mut OsRng);
-        let address = Self::generate_address(public_key);
 
-        Self {
-            public_key,
-            private_key,
-            address,
+        Self::new(public_key, private_key)
+    }
+
+    /// Wallet of a key in Wallet Import Format, keeping the address format the
+    /// key was exported with. Only testnet keys are accepted, like every
+    /// address wallets derive
+    pub fn from_wif(wif: &str) -> Result<Self, WifError> {
+        let wif: Wif = wif.parse()?;
+        if wif.version() != TESTNET_VERSION {
+            return Err(WifError::WrongNetwork);
         }
+        let private_key = *wif.private_key();
+        Ok(Self::with_key_format(
+            private_key.public_key(SECP256K1),
+            private_key,
+            wif.key_format(),
+        ))
     }
 
-    pub fn generate_address(public_key: PublicKey) -> String {
-        //Take the corresponding public key generated with it (33 bytes, 1 byte 0x02 (y-coord is even), and 32 bytes corresponding to X coordinate)
-        let address = public_key.serialize_uncompressed();
-        //Perform SHA-256 hashing on the public key
+    /// The private key in Wallet Import Format
+    pub fn to_wif(&self) -> String {
+        Wif::new(self.address.version(), self.private_key, self.key_format).to_string()
+    }
 
-        let address_hash = sha2::Sha256::digest(address);
+    /// Signs `transaction`, which should be sent from this wallet's address
+    pub fn sign_transaction(&self, transaction: &mut Transaction) {
+        transaction.sign(&self.private_key, self.key_format);
+    }
 
-        // Perform ripemd-160 hashing on the result of SHA-256
-        let raw_rip_address = ripemd::Ripemd160::digest(&address_hash);
-        let rip_address = raw_rip_address.as_slice();
+    /// Signs `transaction` like `sign_transaction`, in the scheme `signature_type`
+    pub fn sign_transaction_with(
+        &self,
+        transaction: &mut Transaction,
+        signature_type: SignatureType,
+    ) {
+        transaction.sign_with(&self.private_key, self.key_format, signature_type);
+    }
+
+    /// Adds this wallet's signature to `transaction`, sent from the account of
+    /// `multisig`. It only counts when this wallet's key is one of its keys
+    pub fn sign_multisig(&self, transaction: &mut Transaction, multisig: &Multisig) {
+        transaction.sign_multisig(multisig, &self.private_key);
+    }
+
+    /// The public key without the parity of y, as BIP340 signatures are made for
+    pub fn x_only_public_key(&self) -> XOnlyPublicKey {
+        self.public_key.x_only_public_key().0
+    }
+
+    /// BIP340 Schnorr signature of `digest`
+    pub fn sign_schnorr(&self, digest: &Hash256) -> schnorr::Signature {
+        SECP256K1.sign_schnorr(
+            &Message::from_digest(*digest.as_bytes()),
+            &Keypair::from_secret_key(SECP256K1, &self.private_key),
+        )
+    }
+
+    /// Whether `signature` is a BIP340 Schnorr signature of `digest` by `public_key`
+    pub fn verify_schnorr(
+        public_key: &XOnlyPublicKey,
+        digest: &Hash256,
+        signature: &schnorr::Signature,
+    ) -> bool {
+        SECP256K1
+            .verify_schnorr(
+                signature,
+                &Message::from_digest(*digest.as_bytes()),
+                public_key,
+            )
+            .is_ok()
+    }
 
-        // Add version byte in front of RIPEMD-160 hash (0x00 for Main Network, 0x6f for Testnet)
-        let versioned_rip_address: Vec<u8> = [[0x6f].as_ref(), rip_address].concat();
-        // Perform SHA-256 hash on the extended RIPEMD-160 result
-        let versioned_rip_address_hash = sha2::Sha256::digest(versioned_rip_address.clone());
-        //Perform SHA-256 hash on the result of the previous SHA-256 hash
-        let double_hashed_address = sha2::Sha256::digest(versioned_rip_address_hash);
-        //Take the first 4 bytes of the second SHA-256 hash. This is the address checksum
-        let chksum: [u8; 4] = double_hashed_address[0..4]
-            .try_into()
-            .expect("Wrong length");
+    /// Recoverable signature of `message`, proving ownership of `address`
+    pub fn sign_message(&self, message: &str) -> String {
+        message::sign(&self.private_key, self.key_format, message)
+    }
 
-        // Add the 4 checksum bytes from stage 7 at the end of extended RIPEMD-160 hash from stage 4. This is the 25-byte binary Bitcoin Address.
-        let chcksum_ripemd = [versioned_rip_address.clone(), chksum.to_vec()].concat();
+    /// Whether `signature` over `message` was made with the key of `address`,
+    /// by recovering the public key and deriving its address
+    pub fn verify_message(
+        address: &Address,
+        message: &str,
+        signature: &str,
+    ) -> Result<bool, MessageError> {
+        message::verify(address, message, signature)
+    }
 
-        bs58::encode(chcksum_ripemd).into_string()
+    /// Address of the compressed `public_key`
+    pub fn generate_address(public_key: PublicKey) -> Address {
+        Address::from_public_key(&public_key, KeyFormat::Compressed)
+    }
+
+    /// Checks an address locally: base58, length, checksum and version byte
+    pub fn validate_address(address: &str) -> Result<Address, AddressError> {
+        Address::parse(address)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_follow_from_the_public_key() {
+        // Secret key 1, whose public key is the generator point
+        let mut secret = [0; 32];
+        secret[31] = 1;
+        let private_key = SecretKey::from_slice(&secret).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &private_key);
+        let hash = |hex: &str| -> [u8; 20] { hex::decode(hex).unwrap().try_into().unwrap() };
 
-    use reqwest;
+        let wallet = Wallet::new(public_key, private_key);
+        assert_eq!(wallet.key_format(), KeyFormat::Compressed);
+        assert_eq!(
+            wallet.address(),
+            Address::new(
+                TESTNET_VERSION,
+                hash("751e76e8199196d454941c45d1b3a323f1433bd6")
+            )
+        );
+        assert_eq!(Wallet::generate_address(public_key), wallet.address());
 
-    use secp256k1::rand::rngs::OsRng;
-    use secp256k1::Secp256k1;
-    use serde::Deserialize;
-    use std::env;
+        let legacy = Wallet::with_key_format(public_key, private_key, KeyFormat::Uncompressed);
+        assert_eq!(
+            legacy.address(),
+            Address::new(
+                TESTNET_VERSION,
+                hash("91b24bf9f5288532960ac687abb035127b1d28a5")
+            )
+        );
+    }
 
-    use crate::wallet;
+    #[test]
+    fn serialization_leaves_out_the_private_key() {
+        let wallet = Wallet::generate_new();
+        let json = serde_json::to_value(&wallet).unwrap();
 
-    #[derive(Debug, Deserialize)]
-    struct Response {
-        apiVersion: String,
-        requestId: String,
-        context: String,
-        data: Data,
+        assert!(json.get("private_key").is_none());
+        assert_eq!(json["address"], wallet.address().to_string());
+        assert!(!json
+            .to_string()
+            .contains(&hex::encode(wallet.private_key().secret_bytes())));
     }
 
-    #[derive(Debug, Deserialize)]
-    struct Data {
-        item: Item,
+    #[test]
+    fn keys_move_through_wif() {
+        let wallet = Wallet::generate_new();
+        let imported = Wallet::from_wif(&wallet.to_wif()).unwrap();
+        assert_eq!(imported.address(), wallet.address());
+        assert_eq!(imported.private_key(), wallet.private_key());
+
+        let legacy = Wallet::with_key_format(
+            wallet.public_key,
+            wallet.private_key,
+            KeyFormat::Uncompressed,
+        );
+        let imported = Wallet::from_wif(&legacy.to_wif()).unwrap();
+        assert_eq!(imported.key_format(), KeyFormat::Uncompressed);
+        assert_eq!(imported.address(), legacy.address());
+
+        assert_eq!(
+            Wallet::from_wif("KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn").unwrap_err(),
+            WifError::WrongNetwork
+        );
     }
 
-    #[derive(Debug, Deserialize)]
-    struct Item {
-        address: String,
-        isValid: bool,
+    #[test]
+    fn signed_messages_prove_the_address() {
+        let wallet = Wallet::generate_new();
+        let signature = wallet.sign_message("I own this address");
+
+        assert_eq!(
+            Wallet::verify_message(&wallet.address(), "I own this address", &signature),
+            Ok(true)
+        );
+        assert_eq!(
+            Wallet::verify_message(&wallet.bech32_address(), "I own this address", &signature),
+            Ok(true)
+        );
+        assert_eq!(
+            Wallet::verify_message(&wallet.address(), "I own that address", &signature),
+            Ok(false)
+        );
+        assert_eq!(
+            Wallet::verify_message(
+                &Wallet::generate_new().address(),
+                "I own this address",
+                &signature
+            ),
+            Ok(false)
+        );
+
+        let legacy = Wallet::with_key_format(
+            wallet.public_key,
+            wallet.private_key,
+            KeyFormat::Uncompressed,
+        );
+        let signature = legacy.sign_message("legacy");
+        assert_eq!(
+            Wallet::verify_message(&legacy.address(), "legacy", &signature),
+            Ok(true)
+        );
+        assert_eq!(
+            Wallet::verify_message(&wallet.address(), "legacy", &signature),
+            Ok(false)
+        );
     }
 
-    #[tokio::test]
-    async fn valid_address() {
-        let dot_env = dotenv::dotenv().ok().unwrap();
+    #[test]
+    fn schnorr_signatures_follow_bip340() {
+        // First test vector of BIP340, signed with an all zero auxiliary random
+        let mut secret = [0; 32];
+        secret[31] = 3;
+        let private_key = SecretKey::from_slice(&secret).unwrap();
+        let wallet = Wallet::new(private_key.public_key(SECP256K1), private_key);
+        assert_eq!(
+            hex::encode(wallet.x_only_public_key().serialize()),
+            "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9"
+        );
 
-        let api_key = env::var("CRYPTO_API_KEY_TEST").expect("API key not found");
+        let digest = Hash256::from([0; 32]);
+        let signature: schnorr::Signature = "e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0"
+            .parse()
+            .unwrap();
+        assert!(Wallet::verify_schnorr(
+            &wallet.x_only_public_key(),
+            &digest,
+            &signature
+        ));
 
-        let secp = Secp256k1::new();
-        let client = reqwest::Client::new();
-
-        let (_, public_key) = secp.generate_keypair(&mut OsRng);
-        let address = wallet::Wallet::generate_address(public_key);
-
-        let context = String::from("blocksmith address test");
-        let req_body = serde_json::json!({
-            "context" : context,
-            "data": {
-                "item" : {
-                    "address" : address
-                }
-            }
-        });
-
-        let res = client.post(format!(
-            "https://rest.cryptoapis.io/blockchain-tools/bitcoin/testnet/addresses/validate?context={}",context),
-        ).header("Content-Type", "application/json")
-        .header("X-API-Key", api_key)
-        .json(&req_body)
-        .send()
-        .await
-        .expect("Failed to send reqwest");
-
-        let res_text = res.text().await.expect("Failed to read response text");
-        let deserialized: Response =
-            serde_json::from_str(&res_text).expect("Failed to deserialize");
-
-        println!("isValid: {:#?}", deserialized.data.item.isValid);
-
-        assert!(deserialized.data.item.isValid);
+        let signed = wallet.sign_schnorr(&Hash256::digest("message"));
+        let other = Hash256::digest("other message");
+        assert!(Wallet::verify_schnorr(
+            &wallet.x_only_public_key(),
+            &Hash256::digest("message"),
+            &signed
+        ));
+        assert!(!Wallet::verify_schnorr(
+            &wallet.x_only_public_key(),
+            &other,
+            &signed
+        ));
+        assert!(!Wallet::verify_schnorr(
+            &Wallet::generate_new().x_only_public_key(),
+            &Hash256::digest("message"),
+            &signed
+        ));
+    }
+
+    #[test]
+    fn generated_addresses_validate_offline() {
+        let wallet = Wallet::generate_new();
+        let encoded = wallet.address().to_string();
+        assert_eq!(Wallet::validate_address(&encoded), Ok(wallet.address()));
+
+        let mut tampered = encoded.into_bytes();
+        tampered[5] = if tampered[5] == b'x' { b'y' } else { b'x' };
+        assert_eq!(
+            Wallet::validate_address(std::str::from_utf8(&tampered).unwrap()),
+            Err(AddressError::BadChecksum)
+        );
     }
 }
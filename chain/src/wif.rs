@@ -0,0 +1,176 @@
+//! Wallet Import Format, how other tools write a single private key.
+//!
+//! A network byte, the 32 byte key and, for keys whose address is derived from
+//! the compressed public key, a trailing `0x01`, written in base58check.
+
+use crate::address::{KeyFormat, MAINNET_VERSION, TESTNET_VERSION};
+
+use secp256k1::SecretKey;
+use std::fmt;
+use std::str::FromStr;
+
+const MAINNET_PREFIX: u8 = 0x80;
+const TESTNET_PREFIX: u8 = 0xef;
+const COMPRESSED_FLAG: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wif {
+    /// Address version byte of the network the key is for
+    version: u8,
+    private_key: SecretKey,
+    key_format: KeyFormat,
+}
+
+impl Wif {
+    pub fn new(version: u8, private_key: SecretKey, key_format: KeyFormat) -> Self {
+        Self {
+            version,
+            private_key,
+            key_format,
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn private_key(&self) -> &SecretKey {
+        &self.private_key
+    }
+
+    pub fn key_format(&self) -> KeyFormat {
+        self.key_format
+    }
+}
+
+impl fmt::Display for Wif {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut payload = Vec::with_capacity(34);
+        payload.push(if self.version == MAINNET_VERSION {
+            MAINNET_PREFIX
+        } else {
+            TESTNET_PREFIX
+        });
+        payload.extend_from_slice(&self.private_key.secret_bytes());
+        if self.key_format == KeyFormat::Compressed {
+            payload.push(COMPRESSED_FLAG);
+        }
+        write!(f, "{}", bs58::encode(payload).with_check().into_string())
+    }
+}
+
+impl FromStr for Wif {
+    type Err = WifError;
+
+    fn from_str(wif: &str) -> Result<Self, Self::Err> {
+        let payload = bs58::decode(wif)
+            .with_check(None)
+            .into_vec()
+            .map_err(|err| match err {
+                bs58::decode::Error::InvalidChecksum { .. } => WifError::BadChecksum,
+                _ => WifError::InvalidBase58,
+            })?;
+
+        let key_format = match payload.len() {
+            33 => KeyFormat::Uncompressed,
+            34 if payload[33] == COMPRESSED_FLAG => KeyFormat::Compressed,
+            length => return Err(WifError::InvalidLength(length)),
+        };
+        let version = match payload[0] {
+            MAINNET_PREFIX => MAINNET_VERSION,
+            TESTNET_PREFIX => TESTNET_VERSION,
+            prefix => return Err(WifError::UnknownNetwork(prefix)),
+        };
+        let private_key =
+            SecretKey::from_slice(&payload[1..33]).map_err(|_| WifError::InvalidKey)?;
+
+        Ok(Self::new(version, private_key, key_format))
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum WifError {
+    #[error("key is not base58 encoded")]
+    InvalidBase58,
+    #[error("key checksum does not match")]
+    BadChecksum,
+    #[error("key decodes to {0} bytes instead of 33 or 34")]
+    InvalidLength(usize),
+    #[error("unknown key network byte {0:#04x}")]
+    UnknownNetwork(u8),
+    #[error("key is for another network, wallets only hold testnet keys")]
+    WrongNetwork,
+    #[error("key is out of range")]
+    InvalidKey,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(hex: &str) -> SecretKey {
+        SecretKey::from_slice(&hex::decode(hex).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn decodes_known_keys() {
+        let one = key("0000000000000000000000000000000000000000000000000000000000000001");
+        let vectors = [
+            (
+                "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn",
+                Wif::new(MAINNET_VERSION, one, KeyFormat::Compressed),
+            ),
+            (
+                "5HpHagT65TZzG1PH3CSu63k8DbpvD8s5ip4nEB3kEsreAnchuDf",
+                Wif::new(MAINNET_VERSION, one, KeyFormat::Uncompressed),
+            ),
+            (
+                "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ",
+                Wif::new(
+                    MAINNET_VERSION,
+                    key("0c28fca386c7a227600b2fe50b7cae11ec86d3bf1fbe471be89827e19d72aa1d"),
+                    KeyFormat::Uncompressed,
+                ),
+            ),
+        ];
+
+        for (encoded, wif) in vectors {
+            assert_eq!(encoded.parse::<Wif>().unwrap(), wif);
+            assert_eq!(wif.to_string(), encoded);
+        }
+
+        let testnet = Wif::new(TESTNET_VERSION, one, KeyFormat::Compressed).to_string();
+        assert!(testnet.starts_with('c'));
+        assert_eq!(
+            testnet.parse::<Wif>().unwrap().key_format(),
+            KeyFormat::Compressed
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        assert_eq!(
+            "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWo".parse::<Wif>(),
+            Err(WifError::BadChecksum)
+        );
+        assert_eq!("0OIl".parse::<Wif>(), Err(WifError::InvalidBase58));
+
+        let too_short = bs58::encode([MAINNET_PREFIX; 20])
+            .with_check()
+            .into_string();
+        assert_eq!(too_short.parse::<Wif>(), Err(WifError::InvalidLength(20)));
+
+        let mut other_network = vec![0x42];
+        other_network.extend_from_slice(&[1; 32]);
+        let other_network = bs58::encode(other_network).with_check().into_string();
+        assert_eq!(
+            other_network.parse::<Wif>(),
+            Err(WifError::UnknownNetwork(0x42))
+        );
+
+        let mut zero = vec![TESTNET_PREFIX];
+        zero.extend_from_slice(&[0; 32]);
+        let zero = bs58::encode(zero).with_check().into_string();
+        assert_eq!(zero.parse::<Wif>(), Err(WifError::InvalidKey));
+    }
+}